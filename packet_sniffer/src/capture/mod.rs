@@ -0,0 +1,251 @@
+//! The capture module contains the settings used to open the capture on the interface: how many bytes of every
+//! packet are kept, the size of the kernel buffer, the read timeout, the immediate and the promiscuous mode and the
+//! precision of the timestamps. They can be set one at a time or read from a configuration file like:
+//! ```text
+//! # the hosts of the lab forbid the promiscuous mode
+//! promisc = false
+//! snaplen = 128
+//! buffer-size = 16777216
+//! ```
+
+use std::fmt::{Display, Formatter};
+use std::fs;
+use std::str::FromStr;
+use pcap::{Active, Capture, Device, Precision};
+use crate::sniffer::SnifferError;
+
+/// The default number of bytes kept for every packet, enough for the largest Ethernet frames.
+pub const DEFAULT_SNAPLEN: u32 = 65535;
+
+/// The largest snapshot length accepted by libpcap.
+pub const MAX_SNAPLEN: u32 = 262144;
+
+/// The default read timeout, in milliseconds.
+pub const DEFAULT_TIMEOUT: u32 = 1000;
+
+/// The precision of the timestamps given by the kernel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimestampPrecision {
+    Micro,
+    Nano,
+}
+
+impl FromStr for TimestampPrecision {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "micro" | "us" => Ok(TimestampPrecision::Micro),
+            "nano" | "ns" => Ok(TimestampPrecision::Nano),
+            _ => Err(format!("Unknown timestamp precision '{}', use micro or nano", s))
+        }
+    }
+}
+
+impl Display for TimestampPrecision {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TimestampPrecision::Micro => write!(f, "micro"),
+            TimestampPrecision::Nano => write!(f, "nano"),
+        }
+    }
+}
+
+/// The settings of the capture, checked when they are set so that opening the capture fails only for the errors
+/// of libpcap (e.g. a missing permission).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CaptureConfig {
+    snaplen: u32,
+    buffer_size: Option<u32>,
+    timeout: u32,
+    immediate: bool,
+    promisc: bool,
+    precision: TimestampPrecision,
+}
+
+impl Default for CaptureConfig {
+    fn default() -> Self {
+        CaptureConfig { snaplen: DEFAULT_SNAPLEN, buffer_size: None, timeout: DEFAULT_TIMEOUT, immediate: false,
+            promisc: true, precision: TimestampPrecision::Micro }
+    }
+}
+
+fn parse_bool(value: &str) -> Option<bool> {
+    match value.to_ascii_lowercase().as_str() {
+        "true" | "yes" | "on" | "1" => Some(true),
+        "false" | "no" | "off" | "0" => Some(false),
+        _ => None
+    }
+}
+
+impl CaptureConfig {
+    pub fn new() -> Self {
+        CaptureConfig::default()
+    }
+
+    /// Reads the settings from a configuration file, with an option `name = value` per line and comments starting
+    /// with '#'. The options that are not in the file keep their default value.
+    pub fn from_file(path: &str) -> Result<Self, SnifferError> {
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(error) => return Err(SnifferError::UserError(format!("The configuration file '{}' can't be read: {} ...", path, error)))
+        };
+        let mut config = CaptureConfig::default();
+        for (i, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap().trim();
+            if line.is_empty() {
+                continue
+            }
+            let (name, value) = match line.split_once('=') {
+                Some((name, value)) => (name.trim(), value.trim().trim_matches('"')),
+                None => return Err(SnifferError::UserError(format!("Expected 'option = value' at line {} of '{}' ...", i + 1, path)))
+            };
+            if let Err(error) = config.set_option(name, value) {
+                let error = error.to_string();
+                return Err(SnifferError::UserError(format!("{} (line {} of '{}') ...", error.trim_end_matches(" ..."), i + 1, path)));
+            }
+        }
+        Ok(config)
+    }
+
+    /// Sets an option by its name, as it is written in the configuration file or on the command line.
+    pub fn set_option(&mut self, name: &str, value: &str) -> Result<(), SnifferError> {
+        let invalid = || SnifferError::UserError(format!("Invalid value '{}' for the option '{}' ...", value, name));
+        match name.to_ascii_lowercase().replace('_', "-").as_str() {
+            "snaplen" => self.set_snaplen(value.parse().map_err(|_| invalid())?),
+            "buffer-size" => self.set_buffer_size(Some(value.parse().map_err(|_| invalid())?)),
+            "timeout" | "read-timeout" => self.set_timeout(value.parse().map_err(|_| invalid())?),
+            "immediate" => { self.set_immediate(parse_bool(value).ok_or_else(invalid)?); Ok(()) },
+            "promisc" => { self.set_promisc(parse_bool(value).ok_or_else(invalid)?); Ok(()) },
+            "precision" => { self.set_precision(value.parse().map_err(|_| invalid())?); Ok(()) },
+            _ => Err(SnifferError::UserError(format!("Unknown capture option '{}' ...", name)))
+        }
+    }
+
+    /// Returns the number of bytes kept for every packet.
+    pub fn get_snaplen(&self) -> u32 { return self.snaplen }
+
+    /// Sets the number of bytes kept for every packet: the longer packets are truncated, and their headers may not be
+    /// decoded if it is too small.
+    pub fn set_snaplen(&mut self, snaplen: u32) -> Result<(), SnifferError> {
+        if snaplen == 0 || snaplen > MAX_SNAPLEN {
+            return Err(SnifferError::UserError(format!("The snapshot length must be between 1 and {} bytes ...", MAX_SNAPLEN)));
+        }
+        self.snaplen = snaplen;
+        Ok(())
+    }
+
+    /// Returns the size of the kernel buffer, in bytes, if it isn't the default of the system.
+    pub fn get_buffer_size(&self) -> Option<u32> { return self.buffer_size }
+
+    /// Sets the size of the kernel buffer in which the packets wait to be read, or the default of the system.
+    pub fn set_buffer_size(&mut self, buffer_size: Option<u32>) -> Result<(), SnifferError> {
+        if let Some(size) = buffer_size {
+            if size == 0 || size > i32::MAX as u32 {
+                return Err(SnifferError::UserError(format!("The buffer size must be between 1 and {} bytes ...", i32::MAX)));
+            }
+        }
+        self.buffer_size = buffer_size;
+        Ok(())
+    }
+
+    /// Returns the read timeout, in milliseconds.
+    pub fn get_timeout(&self) -> u32 { return self.timeout }
+
+    /// Sets how long, in milliseconds, the kernel waits for more packets before returning them. It must be positive,
+    /// because the capture thread uses it to notice when the scanning is paused or stopped on an idle interface.
+    pub fn set_timeout(&mut self, timeout: u32) -> Result<(), SnifferError> {
+        if timeout == 0 || timeout > i32::MAX as u32 {
+            return Err(SnifferError::UserError("The read timeout must be a positive number of milliseconds ...".to_string()));
+        }
+        self.timeout = timeout;
+        Ok(())
+    }
+
+    pub fn get_immediate(&self) -> bool { return self.immediate }
+
+    /// Sets the immediate mode, in which the packets are delivered as soon as they arrive instead of being buffered.
+    pub fn set_immediate(&mut self, immediate: bool) {
+        self.immediate = immediate;
+    }
+
+    pub fn get_promisc(&self) -> bool { return self.promisc }
+
+    /// Sets the promiscuous mode, in which the interface captures also the packets addressed to other hosts.
+    pub fn set_promisc(&mut self, promisc: bool) {
+        self.promisc = promisc;
+    }
+
+    pub fn get_precision(&self) -> TimestampPrecision { return self.precision }
+
+    /// Sets the precision of the timestamps requested to the kernel.
+    pub fn set_precision(&mut self, precision: TimestampPrecision) {
+        self.precision = precision;
+    }
+
+    /// Opens the capture on the device with these settings.
+    pub(crate) fn open(&self, device: Device) -> Result<Capture<Active>, SnifferError> {
+        let mut capture = Capture::from_device(device).map_err(SnifferError::PcapError)?
+            .snaplen(self.snaplen as i32)
+            .timeout(self.timeout as i32)
+            .immediate_mode(self.immediate)
+            .promisc(self.promisc)
+            .precision(match self.precision {
+                TimestampPrecision::Micro => Precision::Micro,
+                TimestampPrecision::Nano => Precision::Nano,
+            });
+        if let Some(size) = self.buffer_size {
+            capture = capture.buffer_size(size as i32);
+        }
+        capture.open().map_err(SnifferError::PcapError)
+    }
+}
+
+impl Display for CaptureConfig {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "snaplen {} bytes, ", self.snaplen)?;
+        if let Some(size) = self.buffer_size {
+            write!(f, "buffer {} bytes, ", size)?;
+        }
+        write!(f, "timeout {} ms, {}{}, {}second timestamps", self.timeout,
+               if self.immediate { "immediate mode, " } else { "" },
+               if self.promisc { "promiscuous" } else { "not promiscuous" },
+               self.precision)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use crate::capture::{CaptureConfig, TimestampPrecision};
+    use crate::sniffer::SnifferError;
+
+    #[test]
+    fn test_config_file() {
+        let path = std::env::temp_dir().join("packet_sniffer_capture.conf");
+        fs::write(&path, "# no promiscuous mode on the lab hosts\npromisc = no\nsnaplen=128\n\nbuffer_size = 16777216\nprecision = \"nano\"\n").unwrap();
+        let config = CaptureConfig::from_file(path.to_str().unwrap()).unwrap();
+        assert!(!config.get_promisc());
+        assert_eq!(config.get_snaplen(), 128);
+        assert_eq!(config.get_buffer_size(), Some(16777216));
+        assert_eq!(config.get_precision(), TimestampPrecision::Nano);
+        assert_eq!(config.get_timeout(), 1000);
+
+        fs::write(&path, "promisc = false\nsnaplen = 0\n").unwrap();
+        let error = CaptureConfig::from_file(path.to_str().unwrap()).unwrap_err();
+        assert_eq!(error, SnifferError::UserError(format!("The snapshot length must be between 1 and 262144 bytes (line 2 of '{}') ...", path.display())));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_invalid_options() {
+        let mut config = CaptureConfig::new();
+        assert!(config.set_timeout(0).is_err());
+        assert!(config.set_buffer_size(Some(0)).is_err());
+        assert_eq!(config.set_option("immediate", "maybe").unwrap_err(),
+                   SnifferError::UserError("Invalid value 'maybe' for the option 'immediate' ...".to_string()));
+        assert_eq!(config.set_option("monitor", "on").unwrap_err(),
+                   SnifferError::UserError("Unknown capture option 'monitor' ...".to_string()));
+        assert_eq!(config, CaptureConfig::default());
+    }
+}
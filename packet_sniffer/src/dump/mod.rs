@@ -0,0 +1,361 @@
+//! The dump module writes the raw packets seen by the capture to a file that can be opened with Wireshark or tcpdump,
+//! in the classic pcap format or in pcapng. The pcapng files describe every captured interface with an Interface
+//! Description Block and keep the capture filter of the session as a comment. The classic pcap files have a single
+//! link type and don't say on which interface a packet has been captured, so they can be written for several
+//! interfaces only if all of them have the same link type. The capture file can be rotated by size or by time (see
+//! the rotation module).
+
+use std::fmt::{Display, Formatter};
+use std::fs::File;
+use std::io;
+use std::io::{BufWriter, Write};
+use std::str::FromStr;
+use crate::rotation::{RotatingFiles, Rotation};
+
+/// The pcap magic number for timestamps in microseconds.
+const PCAP_MAGIC_MICRO: u32 = 0xa1b2c3d4;
+/// The pcap magic number for timestamps in nanoseconds.
+const PCAP_MAGIC_NANO: u32 = 0xa1b23c4d;
+
+const SECTION_HEADER_BLOCK: u32 = 0x0a0d0d0a;
+const INTERFACE_DESCRIPTION_BLOCK: u32 = 1;
+const ENHANCED_PACKET_BLOCK: u32 = 6;
+const BYTE_ORDER_MAGIC: u32 = 0x1a2b3c4d;
+
+const OPT_END: u16 = 0;
+const OPT_COMMENT: u16 = 1;
+const SHB_USERAPPL: u16 = 4;
+const IF_NAME: u16 = 2;
+const IF_DESCRIPTION: u16 = 3;
+const IF_TSRESOL: u16 = 9;
+const IF_FILTER: u16 = 11;
+
+/// The format of the capture files.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DumpFormat {
+    Pcap,
+    PcapNg,
+}
+
+impl DumpFormat {
+    /// Guesses the format from the extension of the file: pcapng for `.pcapng`, pcap otherwise.
+    pub fn from_path(path: &str) -> Self {
+        match path.to_ascii_lowercase().ends_with(".pcapng") {
+            true => DumpFormat::PcapNg,
+            false => DumpFormat::Pcap
+        }
+    }
+}
+
+impl FromStr for DumpFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "pcap" => Ok(DumpFormat::Pcap),
+            "pcapng" => Ok(DumpFormat::PcapNg),
+            _ => Err(format!("Unknown capture file format '{}', use pcap or pcapng", s))
+        }
+    }
+}
+
+impl Display for DumpFormat {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DumpFormat::Pcap => write!(f, "pcap"),
+            DumpFormat::PcapNg => write!(f, "pcapng"),
+        }
+    }
+}
+
+/// An interface of the capture, as it is described in the file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DumpInterface {
+    name: String,
+    description: Option<String>,
+    linktype: u16,
+}
+
+impl DumpInterface {
+    pub fn new(name: String, description: Option<String>, linktype: u16) -> Self {
+        DumpInterface { name, description, linktype }
+    }
+
+    pub fn get_name(&self) -> String { return self.name.clone() }
+    pub fn get_description(&self) -> Option<String> { return self.description.clone() }
+    pub fn get_linktype(&self) -> u16 { return self.linktype }
+}
+
+/// The settings written in the header of a capture file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DumpHeader {
+    interfaces: Vec<DumpInterface>,
+    snaplen: u32,
+    nano: bool,
+    filter: Option<String>,
+}
+
+impl DumpHeader {
+    /// Describes a capture on the given interfaces, with timestamps in nanoseconds if `nano` is set.
+    pub fn new(interfaces: Vec<DumpInterface>, snaplen: u32, nano: bool, filter: Option<String>) -> Self {
+        DumpHeader { interfaces, snaplen, nano, filter }
+    }
+
+    pub fn get_interfaces(&self) -> &Vec<DumpInterface> { return &self.interfaces }
+    pub fn get_snaplen(&self) -> u32 { return self.snaplen }
+    pub fn get_nano(&self) -> bool { return self.nano }
+    pub fn get_filter(&self) -> Option<String> { return self.filter.clone() }
+}
+
+/// Appends a pcapng option, padded to 32 bits.
+fn push_option(body: &mut Vec<u8>, code: u16, value: &[u8]) {
+    body.extend_from_slice(&code.to_le_bytes());
+    body.extend_from_slice(&(value.len() as u16).to_le_bytes());
+    body.extend_from_slice(value);
+    pad(body);
+}
+
+fn pad(body: &mut Vec<u8>) {
+    while !body.len().is_multiple_of(4) {
+        body.push(0);
+    }
+}
+
+/// Wraps the body of a pcapng block with its type and its total length, repeated at the end.
+fn block(block_type: u32, body: &[u8]) -> Vec<u8> {
+    let length = (body.len() + 12) as u32;
+    let mut block = Vec::with_capacity(length as usize);
+    block.extend_from_slice(&block_type.to_le_bytes());
+    block.extend_from_slice(&length.to_le_bytes());
+    block.extend_from_slice(body);
+    block.extend_from_slice(&length.to_le_bytes());
+    block
+}
+
+/// Writes the packets in a capture file, in little endian: the readers recognise the byte order from the magic numbers.
+pub struct DumpWriter<W: Write> {
+    out: W,
+    format: DumpFormat,
+    nano: bool,
+    written: u64,
+}
+
+impl DumpWriter<BufWriter<File>> {
+    /// Creates the file, or truncates it, and writes its header.
+    pub fn create(path: &str, format: DumpFormat, header: &DumpHeader) -> io::Result<Self> {
+        DumpWriter::new(BufWriter::new(File::create(path)?), format, header)
+    }
+}
+
+impl<W: Write> DumpWriter<W> {
+    /// Writes the header of the file in the given output. The pcap format fails with interfaces of different link
+    /// types, that only pcapng can describe.
+    pub fn new(out: W, format: DumpFormat, header: &DumpHeader) -> io::Result<Self> {
+        let mut writer = DumpWriter { out, format, nano: header.nano, written: 0 };
+        match format {
+            DumpFormat::Pcap => {
+                // the classic format has a single link type, the one of all the interfaces
+                let linktype = header.interfaces.first().map_or(1, |i| i.linktype) as u32;
+                if header.interfaces.iter().any(|i| i.linktype as u32 != linktype) {
+                    return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                              "the interfaces have different link types, use the pcapng format"))
+                }
+                let mut bytes = Vec::with_capacity(24);
+                bytes.extend_from_slice(&(if header.nano { PCAP_MAGIC_NANO } else { PCAP_MAGIC_MICRO }).to_le_bytes());
+                bytes.extend_from_slice(&2u16.to_le_bytes());
+                bytes.extend_from_slice(&4u16.to_le_bytes());
+                bytes.extend_from_slice(&0i32.to_le_bytes());
+                bytes.extend_from_slice(&0u32.to_le_bytes());
+                bytes.extend_from_slice(&header.snaplen.to_le_bytes());
+                bytes.extend_from_slice(&linktype.to_le_bytes());
+                writer.put(&bytes)?;
+            },
+            DumpFormat::PcapNg => {
+                let mut body = Vec::new();
+                body.extend_from_slice(&BYTE_ORDER_MAGIC.to_le_bytes());
+                body.extend_from_slice(&1u16.to_le_bytes());
+                body.extend_from_slice(&0u16.to_le_bytes());
+                body.extend_from_slice(&(-1i64).to_le_bytes());
+                push_option(&mut body, SHB_USERAPPL, format!("packet_sniffer {}", env!("CARGO_PKG_VERSION")).as_bytes());
+                if let Some(filter) = &header.filter {
+                    push_option(&mut body, OPT_COMMENT, format!("Capture filter: {}", filter).as_bytes());
+                }
+                push_option(&mut body, OPT_END, &[]);
+                writer.put(&block(SECTION_HEADER_BLOCK, &body))?;
+
+                for interface in &header.interfaces {
+                    let mut body = Vec::new();
+                    body.extend_from_slice(&interface.linktype.to_le_bytes());
+                    body.extend_from_slice(&0u16.to_le_bytes());
+                    body.extend_from_slice(&header.snaplen.to_le_bytes());
+                    push_option(&mut body, IF_NAME, interface.name.as_bytes());
+                    if let Some(description) = &interface.description {
+                        push_option(&mut body, IF_DESCRIPTION, description.as_bytes());
+                    }
+                    push_option(&mut body, IF_TSRESOL, &[if header.nano { 9 } else { 6 }]);
+                    if let Some(filter) = &header.filter {
+                        // the first byte tells that the filter is a libpcap expression
+                        push_option(&mut body, IF_FILTER, &[&[0u8], filter.as_bytes()].concat());
+                    }
+                    push_option(&mut body, OPT_END, &[]);
+                    writer.put(&block(INTERFACE_DESCRIPTION_BLOCK, &body))?;
+                }
+            }
+        }
+        Ok(writer)
+    }
+
+    fn put(&mut self, bytes: &[u8]) -> io::Result<()> {
+        self.out.write_all(bytes)?;
+        self.written += bytes.len() as u64;
+        Ok(())
+    }
+
+    /// Writes a packet captured on the interface with the given index, with its timestamp (seconds and fraction
+    /// in the precision of the header), the captured bytes and the length of the packet on the wire.
+    /// The pcap format doesn't keep the index of the interface, and its seconds wrap around in 2106.
+    pub fn write_packet(&mut self, interface: usize, sec: u64, fraction: u32, data: &[u8], len: u32) -> io::Result<()> {
+        match self.format {
+            DumpFormat::Pcap => {
+                let mut bytes = Vec::with_capacity(16 + data.len());
+                // the header has 32 bits for the seconds, unsigned as read by libpcap
+                bytes.extend_from_slice(&(sec as u32).to_le_bytes());
+                bytes.extend_from_slice(&fraction.to_le_bytes());
+                bytes.extend_from_slice(&(data.len() as u32).to_le_bytes());
+                bytes.extend_from_slice(&len.to_le_bytes());
+                bytes.extend_from_slice(data);
+                self.put(&bytes)
+            },
+            DumpFormat::PcapNg => {
+                // the timestamp is a single number of units, micro or nanoseconds as said by if_tsresol
+                let units = if self.nano { 1_000_000_000 } else { 1_000_000 };
+                let ts = sec * units + fraction as u64;
+                let mut body = Vec::with_capacity(20 + data.len() + 3);
+                body.extend_from_slice(&(interface as u32).to_le_bytes());
+                body.extend_from_slice(&((ts >> 32) as u32).to_le_bytes());
+                body.extend_from_slice(&(ts as u32).to_le_bytes());
+                body.extend_from_slice(&(data.len() as u32).to_le_bytes());
+                body.extend_from_slice(&len.to_le_bytes());
+                body.extend_from_slice(data);
+                pad(&mut body);
+                self.put(&block(ENHANCED_PACKET_BLOCK, &body))
+            }
+        }
+    }
+
+    /// Writes to the file the packets kept in the buffer.
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+
+    /// Returns the bytes written so far, header included.
+    pub fn get_bytes_written(&self) -> u64 {
+        self.written
+    }
+
+    pub fn get_format(&self) -> DumpFormat {
+        self.format
+    }
+}
+
+/// A capture file that can be split in a ring of files: every file starts with the same header, so it can be opened
+/// on its own.
+pub struct DumpFile {
+    files: RotatingFiles,
+    format: DumpFormat,
+    header: DumpHeader,
+    writer: DumpWriter<BufWriter<File>>,
+}
+
+impl DumpFile {
+    /// Creates the first file, named as chosen or with the time if the output is rotated.
+    pub fn create(path: &str, format: DumpFormat, header: DumpHeader, rotation: Rotation) -> io::Result<Self> {
+        let mut files = RotatingFiles::new(path.to_string(), rotation);
+        let writer = DumpWriter::create(&files.open_next()?, format, &header)?;
+        Ok(DumpFile { files, format, header, writer })
+    }
+
+    /// Closes the current file and starts a new one if it is too big or too old.
+    pub fn rotate_if_due(&mut self) -> io::Result<()> {
+        if self.files.is_due(self.writer.get_bytes_written()) {
+            self.writer.flush()?;
+            self.writer = DumpWriter::create(&self.files.open_next()?, self.format, &self.header)?;
+        }
+        Ok(())
+    }
+
+    /// Writes a packet, in a new file if the current one is full (see `DumpWriter::write_packet`).
+    pub fn write_packet(&mut self, interface: usize, sec: u64, fraction: u32, data: &[u8], len: u32) -> io::Result<()> {
+        self.rotate_if_due()?;
+        self.writer.write_packet(interface, sec, fraction, data, len)
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+
+    /// Returns the files written so far that have not been deleted, from the oldest.
+    pub fn get_files(&self) -> Vec<String> {
+        self.files.get_files()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::dump::{DumpFormat, DumpHeader, DumpInterface, DumpWriter};
+
+    fn u32_at(bytes: &[u8], at: usize) -> u32 {
+        u32::from_le_bytes([bytes[at], bytes[at + 1], bytes[at + 2], bytes[at + 3]])
+    }
+
+    fn header() -> DumpHeader {
+        DumpHeader::new(vec![DumpInterface::new("eth0".to_string(), None, 1),
+                             DumpInterface::new("eth1".to_string(), Some("uplink".to_string()), 1)],
+                        65535, false, Some("not port 22".to_string()))
+    }
+
+    #[test]
+    fn test_pcap() {
+        let mut writer = DumpWriter::new(Vec::new(), DumpFormat::Pcap, &header()).unwrap();
+        writer.write_packet(1, 1_700_000_000, 250_000, &[1, 2, 3], 60).unwrap();
+        assert_eq!(writer.get_bytes_written(), 24 + 16 + 3);
+        let bytes = writer.out;
+        assert_eq!(u32_at(&bytes, 0), 0xa1b2c3d4);
+        assert_eq!(u32_at(&bytes, 16), 65535);
+        assert_eq!(u32_at(&bytes, 24), 1_700_000_000);
+        assert_eq!(u32_at(&bytes, 28), 250_000);
+        assert_eq!(u32_at(&bytes, 36), 60);
+
+        // a single link type can't describe interfaces of different types
+        let mixed = DumpHeader::new(vec![DumpInterface::new("eth0".to_string(), None, 1),
+                                         DumpInterface::new("wlan0mon".to_string(), None, 127)], 65535, false, None);
+        assert!(DumpWriter::new(Vec::new(), DumpFormat::Pcap, &mixed).is_err());
+        assert!(DumpWriter::new(Vec::new(), DumpFormat::PcapNg, &mixed).is_ok());
+    }
+
+    #[test]
+    fn test_pcapng() {
+        let mut writer = DumpWriter::new(Vec::new(), DumpFormat::PcapNg, &header()).unwrap();
+        writer.write_packet(1, 1, 5, &[1, 2, 3, 4, 5], 5).unwrap();
+        let bytes = writer.out;
+        // every block starts and ends with its length, a multiple of 4
+        let mut blocks = Vec::new();
+        let mut at = 0;
+        while at < bytes.len() {
+            let length = u32_at(&bytes, at + 4) as usize;
+            assert_eq!(length % 4, 0);
+            assert_eq!(u32_at(&bytes, at + length - 4) as usize, length);
+            blocks.push((u32_at(&bytes, at), at));
+            at += length;
+        }
+        assert_eq!(at, bytes.len());
+        assert_eq!(blocks.iter().map(|b| b.0).collect::<Vec<u32>>(), vec![0x0a0d0d0a, 1, 1, 6]);
+        assert_eq!(u32_at(&bytes, 8), 0x1a2b3c4d);
+        assert!(String::from_utf8_lossy(&bytes[..blocks[1].1]).contains("Capture filter: not port 22"));
+        assert!(String::from_utf8_lossy(&bytes[blocks[2].1..blocks[3].1]).contains("uplink"));
+        let packet = blocks[3].1;
+        assert_eq!(u32_at(&bytes, packet + 8), 1);
+        assert_eq!(u32_at(&bytes, packet + 16), 1_000_005);
+        assert_eq!(u32_at(&bytes, packet + 20), 5);
+    }
+}
@@ -0,0 +1,242 @@
+//! The events module lets the programs that embed the sniffer react to the traffic as it is decoded, instead of
+//! reading the report: they can subscribe to the decoded packets, to the flows started and closed, and to the
+//! snapshots of the flows taken at every interval, either with a channel or with a callback.
+//! ```rust,no_run
+//! use packet_sniffer::events::{EventKind, SnifferEvent};
+//! use packet_sniffer::sniffer::Sniffer;
+//! let sniffer = Sniffer::new();
+//! let subscription = sniffer.subscribe(&[EventKind::Flow], 1024);
+//! for event in subscription {
+//!     if let SnifferEvent::FlowStarted(key, _) = event {
+//!         println!("New flow with {}:{}", key.0, key.1);
+//!     }
+//! }
+//! ```
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{sync_channel, Receiver, RecvTimeoutError, SyncSender, TrySendError};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use crate::flow::{FinishedFlow, FlowEvent, FlowKey, FlowStats, FlowTable};
+use crate::pkt_parser::PacketInfo;
+
+/// The kinds of events a subscriber can ask for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EventKind {
+    /// Every packet decoded, or only the ones selected by the display filter of the sniffer.
+    Packet,
+    /// The flows started and closed.
+    Flow,
+    /// The snapshots of the flows taken at every interval of the periodic reports.
+    Snapshot,
+}
+
+/// Something that happened in the scanning.
+#[derive(Debug, Clone)]
+pub enum SnifferEvent {
+    /// A packet has been decoded.
+    Packet(PacketInfo),
+    /// A flow has been created by its first packet, with the statistics after that packet.
+    FlowStarted(FlowKey, FlowStats),
+    /// A flow has been closed by a timeout or evicted, with its final statistics.
+    FlowClosed(FinishedFlow),
+    /// The flows collected so far, when the periodic report is written.
+    Snapshot(FlowTable),
+}
+
+impl SnifferEvent {
+    pub fn get_kind(&self) -> EventKind {
+        match self {
+            SnifferEvent::Packet(_) => EventKind::Packet,
+            SnifferEvent::FlowStarted(_, _) | SnifferEvent::FlowClosed(_) => EventKind::Flow,
+            SnifferEvent::Snapshot(_) => EventKind::Snapshot,
+        }
+    }
+}
+
+impl From<FlowEvent> for SnifferEvent {
+    fn from(event: FlowEvent) -> Self {
+        match event {
+            FlowEvent::Started(key, stats) => SnifferEvent::FlowStarted(key, stats),
+            FlowEvent::Closed(flow) => SnifferEvent::FlowClosed(flow),
+        }
+    }
+}
+
+/// The events received through a channel. It can be used as a blocking iterator, that ends when the subscription
+/// is cancelled or the sniffer is dropped. When the subscriber can't keep up and the channel is full, the new events
+/// are discarded and counted, so that the scanning is never slowed down.
+#[derive(Debug)]
+pub struct Subscription {
+    id: u64,
+    receiver: Receiver<SnifferEvent>,
+    dropped: Arc<AtomicU64>,
+}
+
+impl Subscription {
+    /// The id used to cancel the subscription.
+    pub fn get_id(&self) -> u64 { return self.id }
+
+    /// The events discarded because the channel was full.
+    pub fn get_dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    /// Waits for the next event, or returns None if the subscription has been cancelled.
+    pub fn recv(&self) -> Option<SnifferEvent> {
+        self.receiver.recv().ok()
+    }
+
+    /// Returns the next event if there is one already.
+    pub fn try_recv(&self) -> Option<SnifferEvent> {
+        self.receiver.try_recv().ok()
+    }
+
+    /// Waits for the next event at most for the given time.
+    pub fn recv_timeout(&self, timeout: Duration) -> Option<SnifferEvent> {
+        match self.receiver.recv_timeout(timeout) {
+            Ok(event) => Some(event),
+            Err(RecvTimeoutError::Timeout) | Err(RecvTimeoutError::Disconnected) => None
+        }
+    }
+}
+
+impl Iterator for Subscription {
+    type Item = SnifferEvent;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.recv()
+    }
+}
+
+type Callback = Box<dyn Fn(&SnifferEvent) + Send + Sync>;
+
+enum Target {
+    Channel(SyncSender<SnifferEvent>, Arc<AtomicU64>),
+    Callback(Callback),
+}
+
+struct Subscriber {
+    id: u64,
+    kinds: Vec<EventKind>,
+    target: Target,
+}
+
+/// Delivers the events to the subscribers. The events are built only if someone has asked for their kind.
+#[derive(Default)]
+pub(crate) struct EventBus {
+    subscribers: RwLock<Vec<Arc<Subscriber>>>,
+    next_id: AtomicU64,
+}
+
+impl EventBus {
+    pub(crate) fn new() -> Self {
+        EventBus::default()
+    }
+
+    fn add(&self, kinds: &[EventKind], target: Target) -> u64 {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed) + 1;
+        self.subscribers.write().unwrap().push(Arc::new(Subscriber { id, kinds: kinds.to_vec(), target }));
+        id
+    }
+
+    pub(crate) fn subscribe(&self, kinds: &[EventKind], capacity: usize) -> Subscription {
+        let (sender, receiver) = sync_channel(capacity.max(1));
+        let dropped = Arc::new(AtomicU64::new(0));
+        let id = self.add(kinds, Target::Channel(sender, dropped.clone()));
+        Subscription { id, receiver, dropped }
+    }
+
+    pub(crate) fn subscribe_with<F: Fn(&SnifferEvent) + Send + Sync + 'static>(&self, kinds: &[EventKind], callback: F) -> u64 {
+        self.add(kinds, Target::Callback(Box::new(callback)))
+    }
+
+    pub(crate) fn unsubscribe(&self, id: u64) -> bool {
+        let mut subscribers = self.subscribers.write().unwrap();
+        let before = subscribers.len();
+        subscribers.retain(|subscriber| subscriber.id != id);
+        subscribers.len() != before
+    }
+
+    /// Returns true if someone has asked for the events of this kind.
+    pub(crate) fn wants(&self, kind: EventKind) -> bool {
+        self.subscribers.read().unwrap().iter().any(|subscriber| subscriber.kinds.contains(&kind))
+    }
+
+    /// Delivers the event built by the closure to the subscribers of its kind. The callbacks are called by the
+    /// thread that publishes the event, without holding any lock, so they can subscribe and unsubscribe.
+    pub(crate) fn publish<F: FnOnce() -> SnifferEvent>(&self, kind: EventKind, event: F) {
+        let subscribers: Vec<Arc<Subscriber>> = self.subscribers.read().unwrap().iter()
+            .filter(|subscriber| subscriber.kinds.contains(&kind))
+            .cloned()
+            .collect();
+        if subscribers.is_empty() {
+            return
+        }
+        let event = event();
+        for subscriber in subscribers {
+            match &subscriber.target {
+                Target::Channel(sender, dropped) => match sender.try_send(event.clone()) {
+                    Ok(()) => {},
+                    Err(TrySendError::Full(_)) => { dropped.fetch_add(1, Ordering::Relaxed); },
+                    // the receiver has been dropped, nobody reads these events anymore
+                    Err(TrySendError::Disconnected(_)) => { self.unsubscribe(subscriber.id); }
+                },
+                Target::Callback(callback) => callback(&event),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::sync::Arc;
+    use crate::events::{EventBus, EventKind, SnifferEvent};
+    use crate::flow::FlowTable;
+    use crate::pkt_parser::{PacketInfo, Protocol, TimeVal};
+
+    fn packet() -> SnifferEvent {
+        SnifferEvent::Packet(PacketInfo::new("10.0.0.1".to_string(), 443, Protocol::TCP, 100, TimeVal::from(1_000_000)))
+    }
+
+    #[test]
+    fn test_channel_subscription() {
+        let bus = EventBus::new();
+        assert!(!bus.wants(EventKind::Packet));
+        let subscription = bus.subscribe(&[EventKind::Packet], 2);
+        assert!(bus.wants(EventKind::Packet) && !bus.wants(EventKind::Flow));
+        for _ in 0..3 {
+            bus.publish(EventKind::Packet, packet);
+        }
+        // the snapshot isn't even built, nobody has asked for it
+        bus.publish(EventKind::Snapshot, || panic!("not requested"));
+        assert!(matches!(subscription.try_recv(), Some(SnifferEvent::Packet(_))));
+        assert!(matches!(subscription.try_recv(), Some(SnifferEvent::Packet(_))));
+        assert!(subscription.try_recv().is_none());
+        assert_eq!(subscription.get_dropped(), 1);
+
+        // a dropped subscription is removed at the next event
+        drop(subscription);
+        bus.publish(EventKind::Packet, packet);
+        assert!(!bus.wants(EventKind::Packet));
+    }
+
+    #[test]
+    fn test_callback_subscription() {
+        let bus = EventBus::new();
+        let count = Arc::new(AtomicU64::new(0));
+        let counter = count.clone();
+        let id = bus.subscribe_with(&[EventKind::Packet, EventKind::Snapshot], move |event| {
+            assert!(matches!(event.get_kind(), EventKind::Packet | EventKind::Snapshot));
+            counter.fetch_add(1, Ordering::Relaxed);
+        });
+        bus.publish(EventKind::Packet, packet);
+        bus.publish(EventKind::Snapshot, || SnifferEvent::Snapshot(FlowTable::new()));
+        assert_eq!(count.load(Ordering::Relaxed), 2);
+        assert!(bus.unsubscribe(id));
+        assert!(!bus.unsubscribe(id));
+        bus.publish(EventKind::Packet, packet);
+        assert_eq!(count.load(Ordering::Relaxed), 2);
+    }
+}
@@ -0,0 +1,690 @@
+//! The filter module implements the display filters: a small language, inspired by the one of Wireshark, that selects
+//! the packets and the flows after they have been decoded. Unlike the capture (BPF) filter, it can use the fields
+//! computed by the sniffer, for example:
+//! - `tcp and flow.bytes > 1MB`
+//! - `tls.sni endswith "example.com"`
+//! - `not ip.addr == 10.0.0.0/8`
+//! - `udp.port in {53 123 5000..6000} || tcp.rtt >= 100`
+//!
+//! The grammar is:
+//! ```text
+//! filter     := and (("or" | "||") and)*
+//! and        := unary (("and" | "&&") unary)*
+//! unary      := ("not" | "!") unary | "(" filter ")" | comparison
+//! comparison := field [operator value | "in" "{" value* "}" | "in" network]
+//! ```
+//! A field alone is true when it has a value (e.g. `tls.sni`), while `tcp` and `udp` alone select the protocol.
+//! The operators are `==`, `!=`, `>`, `>=`, `<`, `<=` (or `eq`, `ne`, `gt`, `ge`, `lt`, `le`), `contains`,
+//! `startswith` and `endswith`. Numbers accept the suffixes k, M and G (e.g. 1MB), and addresses accept a prefix length.
+//! A comparison with a field that the packet or the flow doesn't have is false.
+
+use std::fmt::{Display, Formatter};
+use std::net::IpAddr;
+use std::str::FromStr;
+use crate::flow::{service_name, FlowKey, FlowStats};
+use crate::pkt_parser::{Direction, PacketInfo, Protocol};
+
+/// The fields that can be used in a display filter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Field {
+    Address,
+    Port,
+    TcpPort,
+    UdpPort,
+    Protocol,
+    Service,
+    Sni,
+    Direction,
+    Bytes,
+    Packets,
+    FrameLen,
+    Duration,
+    Rtt,
+    HandshakeRtt,
+    Retransmissions,
+}
+
+/// The type of the values of a field.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Number,
+    Text,
+    Address,
+}
+
+impl Display for Kind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Kind::Number => write!(f, "a number"),
+            Kind::Text => write!(f, "a text"),
+            Kind::Address => write!(f, "an address"),
+        }
+    }
+}
+
+/// The names of the fields, with their aliases.
+const FIELDS: &[(&str, Field)] = &[
+    ("ip.addr", Field::Address), ("ipv6.addr", Field::Address), ("host", Field::Address),
+    ("port", Field::Port), ("tcp.port", Field::TcpPort), ("udp.port", Field::UdpPort),
+    ("proto", Field::Protocol), ("protocol", Field::Protocol), ("service", Field::Service),
+    ("tls.sni", Field::Sni), ("tls.handshake.extensions_server_name", Field::Sni),
+    ("direction", Field::Direction), ("bytes", Field::Bytes), ("flow.bytes", Field::Bytes),
+    ("packets", Field::Packets), ("flow.packets", Field::Packets), ("frame.len", Field::FrameLen),
+    ("flow.duration", Field::Duration), ("tcp.rtt", Field::Rtt), ("tcp.handshake_rtt", Field::HandshakeRtt),
+    ("tcp.retransmissions", Field::Retransmissions),
+];
+
+impl Field {
+    fn kind(&self) -> Kind {
+        match self {
+            Field::Address => Kind::Address,
+            Field::Protocol | Field::Service | Field::Sni | Field::Direction => Kind::Text,
+            _ => Kind::Number
+        }
+    }
+}
+
+/// The operators of the comparisons.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Operator {
+    Eq,
+    Ne,
+    Gt,
+    Ge,
+    Lt,
+    Le,
+    Contains,
+    StartsWith,
+    EndsWith,
+    In,
+}
+
+impl Operator {
+    fn from_word(word: &str) -> Option<Operator> {
+        match word.to_ascii_lowercase().as_str() {
+            "==" | "eq" => Some(Operator::Eq),
+            "!=" | "ne" => Some(Operator::Ne),
+            ">" | "gt" => Some(Operator::Gt),
+            ">=" | "ge" => Some(Operator::Ge),
+            "<" | "lt" => Some(Operator::Lt),
+            "<=" | "le" => Some(Operator::Le),
+            "contains" => Some(Operator::Contains),
+            "startswith" => Some(Operator::StartsWith),
+            "endswith" => Some(Operator::EndsWith),
+            "in" => Some(Operator::In),
+            _ => None
+        }
+    }
+
+    /// Returns true if the operator can be used with the given type of field.
+    fn accepts(&self, kind: Kind) -> bool {
+        match self {
+            Operator::Eq | Operator::Ne | Operator::In => true,
+            Operator::Gt | Operator::Ge | Operator::Lt | Operator::Le => kind == Kind::Number,
+            Operator::Contains | Operator::StartsWith | Operator::EndsWith => kind == Kind::Text,
+        }
+    }
+}
+
+/// A constant of a filter.
+#[derive(Debug, Clone, PartialEq)]
+enum Value {
+    Number(f64),
+    Range(f64, f64),
+    Text(String),
+    Network(IpAddr, u8),
+}
+
+/// The value of a field of a packet or a flow.
+#[derive(Debug, Clone, PartialEq)]
+enum FieldValue {
+    Number(f64),
+    Text(String),
+    Address(IpAddr),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Expr {
+    Or(Box<Expr>, Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    Exists(Field),
+    Compare(Field, Operator, Vec<Value>),
+}
+
+/// An error found while parsing a display filter, with the position (in characters) where it has been found.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FilterError {
+    message: String,
+    position: usize,
+}
+
+impl FilterError {
+    fn new(message: String, position: usize) -> Self {
+        FilterError { message, position }
+    }
+
+    pub fn get_message(&self) -> String { return self.message.clone() }
+    pub fn get_position(&self) -> usize { return self.position }
+
+    /// Returns the filter with a mark under the position of the error.
+    pub fn highlight(&self, filter: &str) -> String {
+        format!("{}\n{}^", filter, " ".repeat(self.position))
+    }
+}
+
+impl Display for FilterError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} (at column {})", self.message, self.position + 1)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Word(String),
+    Text(String),
+    Symbol(&'static str),
+    End,
+}
+
+impl Display for Token {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Token::Word(word) => write!(f, "'{}'", word),
+            Token::Text(text) => write!(f, "\"{}\"", text),
+            Token::Symbol(symbol) => write!(f, "'{}'", symbol),
+            Token::End => write!(f, "the end of the filter"),
+        }
+    }
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || "._-:/*".contains(c)
+}
+
+/// Splits the filter in tokens, each one with its position.
+fn tokenize(filter: &str) -> Result<Vec<(Token, usize)>, FilterError> {
+    const SYMBOLS: [&str; 13] = ["==", "!=", ">=", "<=", "&&", "||", ">", "<", "!", "(", ")", "{", "}"];
+    let chars: Vec<char> = filter.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() || c == ',' {
+            i += 1;
+        } else if c == '"' {
+            let start = i;
+            let mut text = String::new();
+            i += 1;
+            loop {
+                match chars.get(i) {
+                    None => return Err(FilterError::new("Unterminated string".to_string(), start)),
+                    Some('"') => break,
+                    Some('\\') if i + 1 < chars.len() => {
+                        text.push(chars[i + 1]);
+                        i += 1;
+                    },
+                    Some(c) => text.push(*c)
+                }
+                i += 1;
+            }
+            tokens.push((Token::Text(text), start));
+            i += 1;
+        } else if let Some(symbol) = SYMBOLS.iter().find(|s| chars[i..].starts_with(&s.chars().collect::<Vec<char>>())) {
+            tokens.push((Token::Symbol(symbol), i));
+            i += symbol.len();
+        } else if is_word_char(c) {
+            let start = i;
+            while i < chars.len() && is_word_char(chars[i]) { i += 1; }
+            tokens.push((Token::Word(chars[start..i].iter().collect()), start));
+        } else {
+            let hint = match c {
+                '=' => ", use '=='",
+                '&' => ", use '&&' or 'and'",
+                '|' => ", use '||' or 'or'",
+                _ => ""
+            };
+            return Err(FilterError::new(format!("Unexpected character '{}'{}", c, hint), i));
+        }
+    }
+    tokens.push((Token::End, chars.len()));
+    Ok(tokens)
+}
+
+/// Returns the edit distance between two words, used to suggest the name of a field.
+fn distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut previous = row[0];
+        row[0] = i + 1;
+        for j in 0..b.len() {
+            let current = row[j + 1];
+            row[j + 1] = (previous + (ca != b[j]) as usize).min(row[j] + 1).min(current + 1);
+            previous = current;
+        }
+    }
+    row[b.len()]
+}
+
+/// Parses a number, with an optional suffix k, M or G (powers of 1000), followed by an optional B.
+fn parse_number(word: &str) -> Option<f64> {
+    let lower = word.to_ascii_lowercase();
+    let digits = lower.strip_suffix('b').filter(|d| !d.is_empty()).unwrap_or(&lower);
+    let (digits, multiplier) = match digits.chars().last() {
+        Some('k') => (&digits[..digits.len() - 1], 1e3),
+        Some('m') => (&digits[..digits.len() - 1], 1e6),
+        Some('g') => (&digits[..digits.len() - 1], 1e9),
+        _ => (digits, 1.0)
+    };
+    let number = f64::from_str(digits).ok().filter(|n| n.is_finite())?;
+    Some(number * multiplier)
+}
+
+/// Parses an address, with an optional prefix length.
+fn parse_network(word: &str) -> Option<Value> {
+    let (address, prefix) = match word.split_once('/') {
+        Some((address, prefix)) => (address, Some(prefix.parse::<u8>().ok()?)),
+        None => (word, None)
+    };
+    let address = IpAddr::from_str(address).ok()?;
+    let max = if address.is_ipv4() { 32 } else { 128 };
+    let prefix = prefix.unwrap_or(max);
+    if prefix > max {
+        return None
+    }
+    Some(Value::Network(address, prefix))
+}
+
+fn network_contains(network: &IpAddr, prefix: u8, address: &IpAddr) -> bool {
+    match (network, address) {
+        (IpAddr::V4(network), IpAddr::V4(address)) => {
+            let mask = if prefix == 0 { 0 } else { u32::MAX << (32 - prefix) };
+            u32::from(*network) & mask == u32::from(*address) & mask
+        },
+        (IpAddr::V6(network), IpAddr::V6(address)) => {
+            let mask = if prefix == 0 { 0 } else { u128::MAX << (128 - prefix) };
+            u128::from(*network) & mask == u128::from(*address) & mask
+        },
+        _ => false
+    }
+}
+
+/// The maximum depth of the expressions: the parentheses, the negations and the operators in a row, so that a filter
+/// can't exhaust the stack of the parser and of the threads that evaluate it.
+const MAX_DEPTH: usize = 256;
+
+struct Parser {
+    tokens: Vec<(Token, usize)>,
+    next: usize,
+    depth: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> &(Token, usize) {
+        &self.tokens[self.next]
+    }
+
+    fn advance(&mut self) -> (Token, usize) {
+        let token = self.tokens[self.next].clone();
+        if token.0 != Token::End { self.next += 1; }
+        token
+    }
+
+    /// Consumes the next token if it is one of the given keywords or symbols.
+    fn accept(&mut self, alternatives: &[&str]) -> bool {
+        let matches = match &self.peek().0 {
+            Token::Word(word) => alternatives.iter().any(|a| a.eq_ignore_ascii_case(word)),
+            Token::Symbol(symbol) => alternatives.contains(symbol),
+            _ => false
+        };
+        if matches { self.next += 1; }
+        matches
+    }
+
+    /// Goes one level deeper in the expression, failing at the given position beyond the maximum depth.
+    fn enter(&mut self, position: usize) -> Result<(), FilterError> {
+        self.depth += 1;
+        if self.depth > MAX_DEPTH {
+            return Err(FilterError::new(format!("The filter is nested too deeply, the limit is {} levels", MAX_DEPTH), position))
+        }
+        Ok(())
+    }
+
+    fn or(&mut self) -> Result<Expr, FilterError> {
+        let depth = self.depth;
+        let mut expr = self.and()?;
+        while self.accept(&["or", "||"]) {
+            self.enter(self.tokens[self.next - 1].1)?;
+            expr = Expr::Or(Box::new(expr), Box::new(self.and()?));
+        }
+        self.depth = depth;
+        Ok(expr)
+    }
+
+    fn and(&mut self) -> Result<Expr, FilterError> {
+        let depth = self.depth;
+        let mut expr = self.unary()?;
+        while self.accept(&["and", "&&"]) {
+            self.enter(self.tokens[self.next - 1].1)?;
+            expr = Expr::And(Box::new(expr), Box::new(self.unary()?));
+        }
+        self.depth = depth;
+        Ok(expr)
+    }
+
+    fn unary(&mut self) -> Result<Expr, FilterError> {
+        let depth = self.depth;
+        if self.accept(&["not", "!"]) {
+            self.enter(self.tokens[self.next - 1].1)?;
+            let expr = Expr::Not(Box::new(self.unary()?));
+            self.depth = depth;
+            return Ok(expr)
+        }
+        let (token, position) = self.advance();
+        match token {
+            Token::Symbol("(") => {
+                self.enter(position)?;
+                let expr = self.or()?;
+                if !self.accept(&[")"]) {
+                    let (token, at) = self.peek().clone();
+                    return Err(FilterError::new(format!("Expected ')' to close the '(' at column {}, found {}", position + 1, token), at))
+                }
+                self.depth = depth;
+                Ok(expr)
+            },
+            Token::Word(word) => self.comparison(word, position),
+            token => Err(FilterError::new(format!("Expected a field, found {}", token), position))
+        }
+    }
+
+    fn comparison(&mut self, word: String, position: usize) -> Result<Expr, FilterError> {
+        let lower = word.to_ascii_lowercase();
+        let field = match FIELDS.iter().find(|(name, _)| *name == lower) {
+            Some((_, field)) => *field,
+            None => {
+                // the protocols can be used alone, like in Wireshark
+                return match lower.as_str() {
+                    "tcp" | "udp" => Ok(Expr::Compare(Field::Protocol, Operator::Eq, vec![Value::Text(lower)])),
+                    "tls" => Ok(Expr::Exists(Field::Sni)),
+                    _ => {
+                        let suggestion = FIELDS.iter().map(|(name, _)| (distance(&lower, name), *name)).min().unwrap();
+                        let hint = if suggestion.0 <= 2 { format!(", did you mean '{}'?", suggestion.1) } else { String::new() };
+                        Err(FilterError::new(format!("Unknown field '{}'{}", word, hint), position))
+                    }
+                }
+            }
+        };
+
+        let (token, at) = self.peek().clone();
+        let operator = match &token {
+            Token::Word(word) => Operator::from_word(word),
+            Token::Symbol(symbol) => Operator::from_word(symbol),
+            _ => None
+        };
+        let operator = match operator {
+            Some(operator) => operator,
+            None => return Ok(Expr::Exists(field))
+        };
+        self.advance();
+        if !operator.accepts(field.kind()) {
+            return Err(FilterError::new(format!("The operator {} can't be used with '{}', that is {}", token, word, field.kind()), at))
+        }
+
+        let values = if operator == Operator::In && self.accept(&["{"]) {
+            let mut values = Vec::new();
+            while !self.accept(&["}"]) {
+                if self.peek().0 == Token::End {
+                    return Err(FilterError::new(format!("Expected '}}' to close the set at column {}", at + 1), self.peek().1))
+                }
+                values.push(self.value(field, &word, true)?);
+            }
+            if values.is_empty() {
+                return Err(FilterError::new("The set of values is empty".to_string(), at))
+            }
+            values
+        } else {
+            if operator == Operator::In && field.kind() != Kind::Address {
+                return Err(FilterError::new(format!("Expected '{{' after {}", token), self.peek().1))
+            }
+            vec![self.value(field, &word, false)?]
+        };
+        Ok(Expr::Compare(field, operator, values))
+    }
+
+    fn value(&mut self, field: Field, name: &str, in_set: bool) -> Result<Value, FilterError> {
+        let (token, position) = self.advance();
+        let text = match token {
+            Token::Word(word) => word,
+            Token::Text(text) => text,
+            token => return Err(FilterError::new(format!("Expected {} for '{}', found {}", field.kind(), name, token), position))
+        };
+        let value = match field.kind() {
+            Kind::Text => Some(Value::Text(text.to_ascii_lowercase())),
+            Kind::Address => parse_network(&text),
+            Kind::Number => match text.split_once("..") {
+                Some((from, to)) if in_set => match (parse_number(from), parse_number(to)) {
+                    (Some(from), Some(to)) if from <= to => Some(Value::Range(from, to)),
+                    _ => None
+                },
+                _ => parse_number(&text).map(Value::Number)
+            }
+        };
+        value.ok_or_else(|| FilterError::new(format!("'{}' is not {}, as required by '{}'", text, field.kind(), name), position))
+    }
+}
+
+/// Gives access to the fields of a packet or a flow.
+trait Fields {
+    fn value(&self, field: Field) -> Option<FieldValue>;
+}
+
+impl Fields for PacketInfo {
+    fn value(&self, field: Field) -> Option<FieldValue> {
+        match field {
+            Field::Address => IpAddr::from_str(&self.get_address()).ok().map(FieldValue::Address),
+            Field::Port => Some(FieldValue::Number(self.get_port() as f64)),
+            Field::TcpPort if self.get_protocol() == Protocol::TCP => Some(FieldValue::Number(self.get_port() as f64)),
+            Field::UdpPort if self.get_protocol() == Protocol::UDP => Some(FieldValue::Number(self.get_port() as f64)),
+            Field::Protocol => Some(FieldValue::Text(self.get_protocol().to_string().to_ascii_lowercase())),
+            Field::Service => service_name(self.get_port(), &self.get_protocol()).map(|s| FieldValue::Text(s.to_string())),
+            Field::Sni => self.get_sni().map(|sni| FieldValue::Text(sni.to_ascii_lowercase())),
+            Field::Direction => Some(FieldValue::Text(match self.get_direction() {
+                Direction::Received => "received".to_string(),
+                Direction::Transmitted => "transmitted".to_string()
+            })),
+            Field::Bytes => Some(FieldValue::Number(self.get_byte_transmitted() as f64)),
+            Field::FrameLen => Some(FieldValue::Number(self.get_frame_len() as f64)),
+            _ => None
+        }
+    }
+}
+
+/// A flow, seen by the display filters.
+struct FlowRecord<'a>(&'a FlowKey, &'a FlowStats);
+
+impl Fields for FlowRecord<'_> {
+    fn value(&self, field: Field) -> Option<FieldValue> {
+        let FlowRecord(key, stats) = self;
+        let ms = |us: Option<u64>| us.map(|us| FieldValue::Number(us as f64 / 1000.0));
+        match field {
+            Field::Address => IpAddr::from_str(&key.0).ok().map(FieldValue::Address),
+            Field::Port => Some(FieldValue::Number(key.1 as f64)),
+            Field::TcpPort if stats.get_protocol() == Protocol::TCP => Some(FieldValue::Number(key.1 as f64)),
+            Field::UdpPort if stats.get_protocol() == Protocol::UDP => Some(FieldValue::Number(key.1 as f64)),
+            Field::Protocol => Some(FieldValue::Text(stats.get_protocol().to_string().to_ascii_lowercase())),
+            Field::Service => service_name(key.1, &stats.get_protocol()).map(|s| FieldValue::Text(s.to_string())),
+            Field::Sni => stats.get_sni().map(|sni| FieldValue::Text(sni.to_ascii_lowercase())),
+            Field::Bytes => Some(FieldValue::Number(stats.get_bytes() as f64)),
+            Field::Packets => Some(FieldValue::Number(stats.get_packets() as f64)),
+            Field::Duration => Some(FieldValue::Number(stats.get_last_ts().saturating_sub(stats.get_first_ts()) as f64 / 1e6)),
+            Field::Rtt => ms(stats.get_tcp_stats().and_then(|tcp| tcp.get_rtt())),
+            Field::HandshakeRtt => ms(stats.get_tcp_stats().and_then(|tcp| tcp.get_handshake_rtt())),
+            Field::Retransmissions => stats.get_tcp_stats().map(|tcp| FieldValue::Number(tcp.get_retransmissions() as f64)),
+            _ => None
+        }
+    }
+}
+
+fn compare(value: &FieldValue, operator: Operator, constant: &Value) -> bool {
+    match (value, constant) {
+        (FieldValue::Number(v), Value::Number(c)) => match operator {
+            Operator::Eq | Operator::In => v == c,
+            Operator::Ne => v != c,
+            Operator::Gt => v > c,
+            Operator::Ge => v >= c,
+            Operator::Lt => v < c,
+            Operator::Le => v <= c,
+            _ => false
+        },
+        (FieldValue::Number(v), Value::Range(from, to)) => from <= v && v <= to,
+        (FieldValue::Text(v), Value::Text(c)) => match operator {
+            Operator::Eq | Operator::In => v == c,
+            Operator::Ne => v != c,
+            Operator::Contains => v.contains(c.as_str()),
+            Operator::StartsWith => v.starts_with(c.as_str()),
+            Operator::EndsWith => v.ends_with(c.as_str()),
+            _ => false
+        },
+        (FieldValue::Address(v), Value::Network(network, prefix)) => match operator {
+            Operator::Ne => !network_contains(network, *prefix, v),
+            _ => network_contains(network, *prefix, v)
+        },
+        _ => false
+    }
+}
+
+fn evaluate(expr: &Expr, record: &dyn Fields) -> bool {
+    match expr {
+        Expr::Or(left, right) => evaluate(left, record) || evaluate(right, record),
+        Expr::And(left, right) => evaluate(left, record) && evaluate(right, record),
+        Expr::Not(expr) => !evaluate(expr, record),
+        Expr::Exists(field) => record.value(*field).is_some(),
+        Expr::Compare(field, operator, constants) => match record.value(*field) {
+            Some(value) => constants.iter().any(|constant| compare(&value, *operator, constant)),
+            None => false
+        }
+    }
+}
+
+/// A parsed display filter, that can be applied to the decoded packets and to the flows.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DisplayFilter {
+    text: String,
+    expr: Expr,
+}
+
+impl DisplayFilter {
+    /// Parses a display filter, returning the first error found.
+    pub fn parse(filter: &str) -> Result<Self, FilterError> {
+        let mut parser = Parser { tokens: tokenize(filter)?, next: 0, depth: 0 };
+        if parser.peek().0 == Token::End {
+            return Err(FilterError::new("The filter is empty".to_string(), 0))
+        }
+        let expr = parser.or()?;
+        let (token, position) = parser.peek().clone();
+        if token != Token::End {
+            return Err(FilterError::new(format!("Unexpected {}, expected 'and', 'or' or the end of the filter", token), position))
+        }
+        Ok(DisplayFilter { text: filter.trim().to_string(), expr })
+    }
+
+    /// Returns true if the decoded packet is selected by the filter.
+    pub fn matches_packet(&self, info: &PacketInfo) -> bool {
+        evaluate(&self.expr, info)
+    }
+
+    /// Returns true if the flow is selected by the filter.
+    pub fn matches_flow(&self, key: &FlowKey, stats: &FlowStats) -> bool {
+        evaluate(&self.expr, &FlowRecord(key, stats))
+    }
+
+    pub fn get_text(&self) -> String { return self.text.clone() }
+}
+
+impl FromStr for DisplayFilter {
+    type Err = FilterError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        DisplayFilter::parse(s)
+    }
+}
+
+impl Display for DisplayFilter {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::filter::DisplayFilter;
+    use crate::flow::FlowTable;
+    use crate::pkt_parser::{PacketInfo, Protocol, TimeVal};
+
+    fn table() -> FlowTable {
+        let mut table = FlowTable::new();
+        for i in 0..20u64 {
+            table.update(&PacketInfo::new("93.184.216.34".to_string(), 443, Protocol::TCP, 100_000, TimeVal::from(1_000_000 + i * 100_000))
+                .with_sni(Some("www.Example.com".to_string())));
+        }
+        table.update(&PacketInfo::new("10.0.0.53".to_string(), 53, Protocol::UDP, 80, TimeVal::from(1_000_000)));
+        table.update(&PacketInfo::new("fe80::1".to_string(), 5353, Protocol::UDP, 120, TimeVal::from(1_000_000)));
+        table
+    }
+
+    fn selected(filter: &str) -> Vec<String> {
+        let filter = DisplayFilter::parse(filter).unwrap();
+        let mut hosts: Vec<String> = table().filtered(|key, stats| filter.matches_flow(key, stats))
+            .iter().map(|(key, _)| key.0.clone()).collect();
+        hosts.sort();
+        hosts
+    }
+
+    #[test]
+    fn test_flow_filters() {
+        assert_eq!(selected("tcp and flow.bytes > 1MB"), vec!["93.184.216.34"]);
+        assert_eq!(selected("flow.bytes > 2MB"), Vec::<String>::new());
+        assert_eq!(selected("tls.sni endswith \"example.com\""), vec!["93.184.216.34"]);
+        assert_eq!(selected("not ip.addr == 10.0.0.0/8"), vec!["93.184.216.34", "fe80::1"]);
+        assert_eq!(selected("!(tls) && udp.port in {53 5000..6000}"), vec!["10.0.0.53", "fe80::1"]);
+        assert_eq!(selected("ip.addr in fe80::/10 or service == dns"), vec!["10.0.0.53", "fe80::1"]);
+        assert_eq!(selected("flow.duration >= 1.9 && tcp.port eq 443"), vec!["93.184.216.34"]);
+        assert_eq!(selected("tcp.rtt > 0"), Vec::<String>::new());
+    }
+
+    #[test]
+    fn test_packet_filters() {
+        let packet = PacketInfo::new("192.168.1.10".to_string(), 22, Protocol::TCP, 1400, TimeVal::from(0)).with_frame_len(1454);
+        assert!(DisplayFilter::parse("tcp.port == 22 and frame.len > 1k").unwrap().matches_packet(&packet));
+        assert!(!DisplayFilter::parse("udp.port == 22").unwrap().matches_packet(&packet));
+        assert!(!DisplayFilter::parse("flow.packets > 0").unwrap().matches_packet(&packet));
+        assert!(DisplayFilter::parse("direction == received").unwrap().matches_packet(&packet));
+    }
+
+    #[test]
+    fn test_errors() {
+        let error = DisplayFilter::parse("flow.byte > 10").unwrap_err();
+        assert_eq!(error.to_string(), "Unknown field 'flow.byte', did you mean 'flow.bytes'? (at column 1)");
+        let error = DisplayFilter::parse("tcp and flow.bytes > lots").unwrap_err();
+        assert_eq!(error.get_position(), 21);
+        assert_eq!(error.highlight("tcp and flow.bytes > lots"), "tcp and flow.bytes > lots\n                     ^");
+        assert_eq!(DisplayFilter::parse("tls.sni > 3").unwrap_err().get_message(),
+                   "The operator '>' can't be used with 'tls.sni', that is a text");
+        assert_eq!(DisplayFilter::parse("(tcp or udp").unwrap_err().get_message(),
+                   "Expected ')' to close the '(' at column 1, found the end of the filter");
+        assert_eq!(DisplayFilter::parse("port = 22").unwrap_err().get_message(), "Unexpected character '=', use '=='");
+        assert_eq!(DisplayFilter::parse("tcp udp").unwrap_err().get_message(),
+                   "Unexpected 'udp', expected 'and', 'or' or the end of the filter");
+        assert_eq!(DisplayFilter::parse("ip.addr == 10.0.0.0/33").unwrap_err().get_message(),
+                   "'10.0.0.0/33' is not an address, as required by 'ip.addr'");
+        assert!(DisplayFilter::parse("tls.sni == \"example").is_err());
+        assert!(DisplayFilter::parse("  ").is_err());
+        // the depth is bounded, instead of overflowing the stack
+        let nested = format!("{}tcp{}", "(".repeat(10_000), ")".repeat(10_000));
+        assert_eq!(DisplayFilter::parse(&nested).unwrap_err().get_position(), 256);
+        assert!(DisplayFilter::parse(&format!("{}tcp", "! ".repeat(10_000))).is_err());
+        assert!(DisplayFilter::parse(&vec!["tcp"; 10_000].join(" or ")).is_err());
+        assert!(DisplayFilter::parse(&format!("{}tcp{}", "(".repeat(100), ")".repeat(100))).is_ok());
+    }
+}
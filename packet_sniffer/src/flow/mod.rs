@@ -0,0 +1,1227 @@
+//! flow
+//! This module aggregates the information extracted from the packets. Every flow is identified by the remote address and
+//! port, as in the report, and collects the amount of traffic exchanged together with the performance statistics of its
+//! TCP connections.
+
+pub mod tcp;
+
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+use crate::pkt_parser::{Direction, PacketInfo, Protocol};
+use crate::flow::tcp::TcpStats;
+use crate::stats::{Histogram, Throughput};
+
+/// Maximum number of buckets of the throughput of the whole scanning.
+const SESSION_MAX_BUCKETS: usize = 1024;
+/// Maximum number of buckets of the throughput of every host.
+const HOST_MAX_BUCKETS: usize = 256;
+/// Maximum number of buckets of the throughput of every flow.
+const FLOW_MAX_BUCKETS: usize = 64;
+/// Maximum number of closed flows kept in the log, the oldest ones are forgotten.
+const MAX_FINISHED_FLOWS: usize = 4096;
+/// How often, in microseconds of capture time, the flows are checked for the timeouts.
+const SWEEP_INTERVAL: u64 = 1_000_000;
+
+/// The default number of shards of the flow table.
+pub const DEFAULT_SHARDS: usize = 8;
+
+/// The key of a flow: the remote address and port.
+pub type FlowKey = (String, u16);
+
+/// The statistics collected for a single flow.
+/// The timestamps are expressed in microseconds since the epoch (see `TimeVal`).
+#[derive(Debug, Clone)]
+pub struct FlowStats {
+    protocol: Protocol,
+    bytes: usize,
+    packets: u64,
+    first_ts: u64,
+    last_ts: u64,
+    tcp: Option<TcpStats>,
+    throughput: Throughput,
+    sizes: Histogram,
+    inter_arrival: Histogram,
+    sni: Option<String>,
+    interfaces: BTreeMap<String, (u64, usize)>,
+    transmitted: (u64, usize),
+    received: (u64, usize),
+}
+
+impl FlowStats {
+    fn new(info: &PacketInfo, bucket_width: u64) -> Self {
+        let ts: u64 = info.get_time_stamp().into();
+        let tcp = match info.get_protocol() {
+            Protocol::TCP => Some(TcpStats::new()),
+            _ => None
+        };
+        FlowStats { protocol: info.get_protocol(), bytes: 0, packets: 0, first_ts: ts, last_ts: ts, tcp,
+            throughput: Throughput::new(bucket_width, FLOW_MAX_BUCKETS), sizes: Histogram::frame_sizes(),
+            inter_arrival: Histogram::inter_arrival_times(), sni: None, interfaces: BTreeMap::new(),
+            transmitted: (0, 0), received: (0, 0) }
+    }
+
+    fn update(&mut self, info: &PacketInfo) {
+        let ts: u64 = info.get_time_stamp().into();
+        self.protocol = info.get_protocol();
+        if self.packets > 0 {
+            self.inter_arrival.add(ts.saturating_sub(self.last_ts));
+        }
+        self.sizes.add(info.get_frame_len() as u64);
+        self.bytes += info.get_byte_transmitted();
+        self.packets += 1;
+        // the packets may not be captured in order, e.g. by different interfaces
+        self.first_ts = self.first_ts.min(ts);
+        self.last_ts = self.last_ts.max(ts);
+        self.throughput.add(ts, info.get_byte_transmitted());
+        if self.sni.is_none() {
+            self.sni = info.get_sni();
+        }
+        if let Some(interface) = info.get_interface() {
+            let traffic = self.interfaces.entry(interface).or_insert((0, 0));
+            traffic.0 += 1;
+            traffic.1 += info.get_byte_transmitted();
+        }
+        let direction = match info.get_direction() {
+            Direction::Transmitted => &mut self.transmitted,
+            Direction::Received => &mut self.received
+        };
+        direction.0 += 1;
+        direction.1 += info.get_byte_transmitted();
+        if let (Some(tcp), Some(segment)) = (self.tcp.as_mut(), info.get_tcp_segment()) {
+            tcp.update(&segment, &info.get_direction(), ts);
+        }
+    }
+
+    /// Adds the packets of the same flow collected by another shard.
+    fn merge(&mut self, other: FlowStats) {
+        self.bytes += other.bytes;
+        self.packets += other.packets;
+        self.first_ts = self.first_ts.min(other.first_ts);
+        self.last_ts = self.last_ts.max(other.last_ts);
+        self.throughput.merge(&other.throughput);
+        self.sizes.merge(&other.sizes);
+        self.inter_arrival.merge(&other.inter_arrival);
+        if self.sni.is_none() {
+            self.sni = other.sni;
+        }
+        for (interface, (packets, bytes)) in other.interfaces {
+            let traffic = self.interfaces.entry(interface).or_insert((0, 0));
+            traffic.0 += packets;
+            traffic.1 += bytes;
+        }
+        self.transmitted.0 += other.transmitted.0;
+        self.transmitted.1 += other.transmitted.1;
+        self.received.0 += other.received.0;
+        self.received.1 += other.received.1;
+        match (self.tcp.as_mut(), other.tcp) {
+            (Some(tcp), Some(other)) => tcp.merge(other),
+            (None, Some(other)) => self.tcp = Some(other),
+            _ => {}
+        }
+    }
+
+    /// Releases the state needed only while the flow is alive.
+    fn close(&mut self) {
+        if let Some(tcp) = self.tcp.as_mut() {
+            tcp.close();
+        }
+    }
+
+    pub fn get_protocol(&self) -> Protocol { return self.protocol.clone() }
+    pub fn get_bytes(&self) -> usize { return self.bytes }
+    pub fn get_packets(&self) -> u64 { return self.packets }
+    pub fn get_first_ts(&self) -> u64 { return self.first_ts }
+    pub fn get_last_ts(&self) -> u64 { return self.last_ts }
+    /// Returns the TCP statistics of the flow, only for TCP flows.
+    pub fn get_tcp_stats(&self) -> Option<&TcpStats> { return self.tcp.as_ref() }
+    /// Returns the packets and bytes of the flow over time.
+    pub fn get_throughput(&self) -> &Throughput { return &self.throughput }
+    /// Returns the distribution of the sizes of the frames of the flow.
+    pub fn get_frame_sizes(&self) -> &Histogram { return &self.sizes }
+    /// Returns the distribution of the time between two consecutive packets of the flow.
+    pub fn get_inter_arrival_times(&self) -> &Histogram { return &self.inter_arrival }
+    /// Returns the server name requested by the first TLS ClientHello of the flow, if any.
+    pub fn get_sni(&self) -> Option<String> { return self.sni.clone() }
+
+    /// Returns the packets and the bytes of the flow seen on every interface.
+    pub fn get_interfaces(&self) -> &BTreeMap<String, (u64, usize)> { return &self.interfaces }
+    /// Returns the packets and the bytes of the flow sent by the sniffed interfaces.
+    pub fn get_transmitted(&self) -> (u64, usize) { return self.transmitted }
+    /// Returns the packets and the bytes of the flow received by the sniffed interfaces.
+    pub fn get_received(&self) -> (u64, usize) { return self.received }
+}
+
+/// The criteria used to rank hosts, ports and flows in the report.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortKey {
+    Bytes,
+    Packets,
+}
+
+impl FromStr for SortKey {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "bytes" => Ok(SortKey::Bytes),
+            "packets" => Ok(SortKey::Packets),
+            other => Err(format!("Unknown sort key '{}', use bytes or packets ...", other))
+        }
+    }
+}
+
+impl Display for SortKey {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SortKey::Bytes => write!(f, "bytes"),
+            SortKey::Packets => write!(f, "packets")
+        }
+    }
+}
+
+/// An entry of a ranking: the traffic aggregated under a host, a port or a protocol.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RankEntry {
+    name: String,
+    bytes: usize,
+    packets: u64,
+    flows: usize,
+}
+
+impl RankEntry {
+    fn new(name: String) -> Self {
+        RankEntry { name, bytes: 0, packets: 0, flows: 0 }
+    }
+
+    fn add(&mut self, stats: &FlowStats) {
+        self.bytes += stats.bytes;
+        self.packets += stats.packets;
+        self.flows += 1;
+    }
+
+    fn sort_value(&self, key: SortKey) -> u64 {
+        match key {
+            SortKey::Bytes => self.bytes as u64,
+            SortKey::Packets => self.packets
+        }
+    }
+
+    pub fn get_name(&self) -> String { return self.name.clone() }
+    pub fn get_bytes(&self) -> usize { return self.bytes }
+    pub fn get_packets(&self) -> u64 { return self.packets }
+    pub fn get_flows(&self) -> usize { return self.flows }
+}
+
+/// Returns the name of the service usually bound to a well known port.
+pub fn service_name(port: u16, protocol: &Protocol) -> Option<&'static str> {
+    let name = match (port, protocol) {
+        (20, Protocol::TCP) | (21, Protocol::TCP) => "ftp",
+        (22, Protocol::TCP) => "ssh",
+        (23, Protocol::TCP) => "telnet",
+        (25, Protocol::TCP) | (587, Protocol::TCP) => "smtp",
+        (53, _) => "dns",
+        (67, Protocol::UDP) | (68, Protocol::UDP) => "dhcp",
+        (80, Protocol::TCP) | (8080, Protocol::TCP) => "http",
+        (110, Protocol::TCP) => "pop3",
+        (123, Protocol::UDP) => "ntp",
+        (137..=139, _) => "netbios",
+        (143, Protocol::TCP) => "imap",
+        (161, Protocol::UDP) | (162, Protocol::UDP) => "snmp",
+        (443, Protocol::TCP) | (8443, Protocol::TCP) => "https",
+        (443, Protocol::UDP) => "quic",
+        (445, Protocol::TCP) => "smb",
+        (993, Protocol::TCP) => "imaps",
+        (995, Protocol::TCP) => "pop3s",
+        (1900, Protocol::UDP) => "ssdp",
+        (3306, Protocol::TCP) => "mysql",
+        (3389, Protocol::TCP) => "rdp",
+        (5353, Protocol::UDP) => "mdns",
+        (5432, Protocol::TCP) => "postgresql",
+        _ => return None
+    };
+    Some(name)
+}
+
+/// Sorts the entries of a ranking, from the biggest to the smallest, and keeps only the first n.
+fn rank(entries: HashMap<String, RankEntry>, n: usize, key: SortKey) -> Vec<RankEntry> {
+    let mut entries: Vec<RankEntry> = entries.into_values().collect();
+    entries.sort_by(|a, b| b.sort_value(key).cmp(&a.sort_value(key)).then_with(|| a.name.cmp(&b.name)));
+    entries.truncate(n);
+    entries
+}
+
+/// The policy used to choose the flows to be evicted when the table is full.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EvictionPolicy {
+    /// The flows that have not been seen for the longest time are evicted first.
+    Lru,
+    /// The flows with the smallest amount of bytes are evicted first.
+    SmallestFirst,
+}
+
+impl FromStr for EvictionPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "lru" => Ok(EvictionPolicy::Lru),
+            "smallest" | "smallest-first" => Ok(EvictionPolicy::SmallestFirst),
+            other => Err(format!("Unknown eviction policy '{}', use lru or smallest ...", other))
+        }
+    }
+}
+
+impl Display for EvictionPolicy {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EvictionPolicy::Lru => write!(f, "lru"),
+            EvictionPolicy::SmallestFirst => write!(f, "smallest")
+        }
+    }
+}
+
+/// Why a flow has been removed from the table.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CloseReason {
+    /// No packets have been seen for longer than the idle timeout.
+    IdleTimeout,
+    /// The flow has lasted longer than the active timeout: the next packets start a new flow.
+    ActiveTimeout,
+    /// The table was full and the flow has been chosen by the eviction policy.
+    Evicted,
+}
+
+impl Display for CloseReason {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CloseReason::IdleTimeout => write!(f, "Idle timeout"),
+            CloseReason::ActiveTimeout => write!(f, "Active timeout"),
+            CloseReason::Evicted => write!(f, "Evicted")
+        }
+    }
+}
+
+/// A flow that has been removed from the table, with its final statistics.
+#[derive(Debug, Clone)]
+pub struct FinishedFlow {
+    key: FlowKey,
+    stats: FlowStats,
+    reason: CloseReason,
+    closed_ts: u64,
+}
+
+impl FinishedFlow {
+    pub fn get_key(&self) -> &FlowKey { return &self.key }
+    pub fn get_stats(&self) -> &FlowStats { return &self.stats }
+    pub fn get_reason(&self) -> CloseReason { return self.reason }
+    /// Returns the time the flow has been closed at, in microseconds, according to the timestamps of the packets.
+    pub fn get_closed_ts(&self) -> u64 { return self.closed_ts }
+}
+
+/// The traffic exchanged by a flow between two snapshots of the table.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FlowDelta {
+    key: FlowKey,
+    protocol: Protocol,
+    packets: u64,
+    bytes: usize,
+    started: bool,
+}
+
+impl FlowDelta {
+    pub fn get_key(&self) -> &FlowKey { return &self.key }
+    pub fn get_protocol(&self) -> Protocol { return self.protocol.clone() }
+    pub fn get_packets(&self) -> u64 { return self.packets }
+    pub fn get_bytes(&self) -> usize { return self.bytes }
+    /// True if the flow wasn't in the previous snapshot.
+    pub fn is_started(&self) -> bool { return self.started }
+}
+
+/// A change in the flows of a table, recorded only when the table tracks them.
+#[derive(Debug, Clone)]
+pub enum FlowEvent {
+    /// A flow has been created by its first packet, with the statistics after that packet.
+    Started(FlowKey, FlowStats),
+    /// A flow has been closed by a timeout or evicted.
+    Closed(FinishedFlow),
+}
+
+/// The table that collects all the flows observed during a scanning.
+/// The number of live flows can be bounded with the idle and active timeouts and with a maximum number of entries:
+/// the flows removed end up in a log of finished flows, which is bounded as well.
+/// The series of the hosts are rebuilt from the flows, so they are bounded by them.
+#[derive(Debug, Clone)]
+pub struct FlowTable {
+    flows: HashMap<FlowKey, FlowStats>,
+    bucket_width: u64,
+    throughput: Throughput,
+    sizes: Histogram,
+    inter_arrival: Histogram,
+    last_ts: Option<u64>,
+    idle_timeout: u64,
+    active_timeout: u64,
+    max_flows: usize,
+    eviction_policy: EvictionPolicy,
+    last_sweep: u64,
+    finished: VecDeque<FinishedFlow>,
+    idle_closed: u64,
+    active_closed: u64,
+    evicted: u64,
+    finished_dropped: u64,
+    track_events: bool,
+    events: Vec<FlowEvent>,
+}
+
+impl Default for FlowTable {
+    fn default() -> Self {
+        FlowTable::with_bucket_width(1)
+    }
+}
+
+impl FlowTable {
+    pub fn new() -> Self {
+        FlowTable::default()
+    }
+
+    /// Creates an empty table whose throughput is collected in buckets of the given width, in seconds.
+    pub fn with_bucket_width(bucket_width: u64) -> Self {
+        FlowTable { flows: HashMap::new(), bucket_width, throughput: Throughput::new(bucket_width, SESSION_MAX_BUCKETS),
+            sizes: Histogram::frame_sizes(), inter_arrival: Histogram::inter_arrival_times(), last_ts: None,
+            idle_timeout: 0, active_timeout: 0, max_flows: 0, eviction_policy: EvictionPolicy::Lru, last_sweep: 0,
+            finished: VecDeque::new(), idle_closed: 0, active_closed: 0, evicted: 0, finished_dropped: 0,
+            track_events: false, events: Vec::new() }
+    }
+
+    /// Accounts a new packet in the flow it belongs to, creating the flow if needed.
+    pub fn update(&mut self, info: &PacketInfo) {
+        let ts: u64 = info.get_time_stamp().into();
+        let bucket_width = self.bucket_width;
+        if ts >= self.last_sweep + SWEEP_INTERVAL {
+            self.expire(ts);
+        }
+        if let Some(last_ts) = self.last_ts {
+            self.inter_arrival.add(ts.saturating_sub(last_ts));
+        }
+        self.last_ts = Some(self.last_ts.map_or(ts, |last| last.max(ts)));
+        self.sizes.add(info.get_frame_len() as u64);
+        self.throughput.add(ts, info.get_byte_transmitted());
+
+        let key = (info.get_address(), info.get_port());
+        let started = !self.flows.contains_key(&key);
+        if started {
+            if self.max_flows > 0 && self.flows.len() >= self.max_flows {
+                self.evict(ts);
+            }
+            self.flows.insert(key.clone(), FlowStats::new(info, bucket_width));
+        }
+        if let Some(flow) = self.flows.get_mut(&key) {
+            flow.update(info);
+            if started && self.track_events {
+                self.events.push(FlowEvent::Started(key, flow.clone()));
+            }
+        }
+    }
+
+    /// Closes the flows that have exceeded the idle or the active timeout at the given time, in microseconds.
+    pub fn expire(&mut self, now: u64) {
+        self.last_sweep = now;
+        if self.idle_timeout == 0 && self.active_timeout == 0 { return }
+        let idle = self.idle_timeout.saturating_mul(1_000_000);
+        let active = self.active_timeout.saturating_mul(1_000_000);
+        let expired: Vec<(FlowKey, CloseReason)> = self.flows.iter()
+            .filter_map(|(k, f)| {
+                if idle > 0 && now.saturating_sub(f.last_ts) >= idle {
+                    Some((k.clone(), CloseReason::IdleTimeout))
+                } else if active > 0 && now.saturating_sub(f.first_ts) >= active {
+                    Some((k.clone(), CloseReason::ActiveTimeout))
+                } else { None }
+            })
+            .collect();
+        for (key, reason) in expired {
+            self.close(&key, reason, now);
+        }
+    }
+
+    /// Makes room for new flows, evicting a small batch of them according to the policy, so that the cost of
+    /// looking for the victims is shared among many insertions.
+    fn evict(&mut self, now: u64) {
+        let count = (self.max_flows / 64).max(1).min(self.flows.len());
+        if count == 0 { return }
+        let mut candidates: Vec<((u64, u64), FlowKey)> = self.flows.iter()
+            .map(|(k, f)| {
+                let score = match self.eviction_policy {
+                    EvictionPolicy::Lru => (f.last_ts, 0),
+                    EvictionPolicy::SmallestFirst => (f.bytes as u64, f.last_ts)
+                };
+                (score, k.clone())
+            })
+            .collect();
+        if count < candidates.len() {
+            candidates.select_nth_unstable_by_key(count - 1, |c| c.0);
+        }
+        for (_, key) in candidates.into_iter().take(count) {
+            self.close(&key, CloseReason::Evicted, now);
+        }
+    }
+
+    /// Removes a flow from the table and moves it to the log of the finished flows, closing it at the given time.
+    fn close(&mut self, key: &FlowKey, reason: CloseReason, now: u64) {
+        let mut stats = match self.flows.remove(key) {
+            Some(stats) => stats,
+            None => return
+        };
+        stats.close();
+        match reason {
+            CloseReason::IdleTimeout => self.idle_closed += 1,
+            CloseReason::ActiveTimeout => self.active_closed += 1,
+            CloseReason::Evicted => self.evicted += 1
+        }
+        if self.finished.len() >= MAX_FINISHED_FLOWS {
+            self.finished.pop_front();
+            self.finished_dropped += 1;
+        }
+        let finished = FinishedFlow { key: key.clone(), stats, reason, closed_ts: now };
+        if self.track_events {
+            self.events.push(FlowEvent::Closed(finished.clone()));
+        }
+        self.finished.push_back(finished);
+    }
+
+    /// Sets whether the flows started and closed are recorded, to be taken with take_events.
+    pub fn set_track_events(&mut self, track_events: bool) {
+        self.track_events = track_events;
+        if !track_events {
+            self.events.clear();
+        }
+    }
+
+    /// Returns the flows started and closed since the last call, in order.
+    pub fn take_events(&mut self) -> Vec<FlowEvent> {
+        std::mem::take(&mut self.events)
+    }
+
+    /// Sets the time, in seconds, after which a flow without packets is closed. Zero disables the timeout.
+    pub fn set_idle_timeout(&mut self, idle_timeout: u64) {
+        self.idle_timeout = idle_timeout;
+    }
+
+    pub fn get_idle_timeout(&self) -> u64 {
+        self.idle_timeout
+    }
+
+    /// Sets the maximum duration of a flow, in seconds: after it the flow is closed, and the next packets start a new
+    /// one. Zero disables the timeout.
+    pub fn set_active_timeout(&mut self, active_timeout: u64) {
+        self.active_timeout = active_timeout;
+    }
+
+    pub fn get_active_timeout(&self) -> u64 {
+        self.active_timeout
+    }
+
+    /// Sets the maximum number of live flows. Zero means no limit.
+    pub fn set_max_flows(&mut self, max_flows: usize) {
+        self.max_flows = max_flows;
+    }
+
+    pub fn get_max_flows(&self) -> usize {
+        self.max_flows
+    }
+
+    /// Sets the policy used to choose the flows to be evicted when the table is full.
+    pub fn set_eviction_policy(&mut self, eviction_policy: EvictionPolicy) {
+        self.eviction_policy = eviction_policy;
+    }
+
+    pub fn get_eviction_policy(&self) -> EvictionPolicy {
+        self.eviction_policy
+    }
+
+    /// Returns the log of the flows that have been closed, from the oldest to the newest.
+    pub fn get_finished_flows(&self) -> Vec<FinishedFlow> {
+        self.finished.iter().cloned().collect()
+    }
+
+    /// Returns the number of flows closed by the idle timeout.
+    pub fn get_idle_closed(&self) -> u64 { self.idle_closed }
+    /// Returns the number of flows closed by the active timeout.
+    pub fn get_active_closed(&self) -> u64 { self.active_closed }
+    /// Returns the number of flows evicted because the table was full.
+    pub fn get_evicted(&self) -> u64 { self.evicted }
+    /// Returns the number of finished flows that did not fit in the log.
+    pub fn get_finished_dropped(&self) -> u64 { self.finished_dropped }
+
+    /// Sets the width of the throughput buckets, in seconds, for the series created from now on.
+    pub fn set_bucket_width(&mut self, bucket_width: u64) {
+        self.bucket_width = bucket_width;
+        if self.throughput.is_empty() {
+            self.throughput = Throughput::new(bucket_width, SESSION_MAX_BUCKETS);
+        }
+    }
+
+    /// Returns the width of the throughput buckets, in seconds, as it was configured.
+    pub fn get_bucket_width(&self) -> u64 {
+        self.bucket_width
+    }
+
+    /// Returns the packets and bytes of the whole scanning over time.
+    pub fn get_throughput(&self) -> &Throughput {
+        &self.throughput
+    }
+
+    /// Returns the distribution of the sizes of all the frames.
+    pub fn get_frame_sizes(&self) -> &Histogram {
+        &self.sizes
+    }
+
+    /// Returns the distribution of the time between two consecutive packets.
+    pub fn get_inter_arrival_times(&self) -> &Histogram {
+        &self.inter_arrival
+    }
+
+    /// Returns the packets and bytes exchanged with a remote host over time.
+    /// The series is rebuilt from the live flows of the host and from the ones in the log of the finished flows, so it
+    /// takes no memory of its own and it is the same in the filtered tables.
+    pub fn get_host_throughput(&self, host: &str) -> Option<Throughput> {
+        let finished = self.finished.iter().map(|f| (&f.key, &f.stats));
+        self.flows.iter().chain(finished)
+            .filter(|(key, _)| key.0 == host)
+            .fold(None, |series: Option<Throughput>, (_, stats)| {
+                let mut series = series.unwrap_or_else(|| Throughput::new(self.bucket_width, HOST_MAX_BUCKETS));
+                series.merge(&stats.throughput);
+                Some(series)
+            })
+    }
+
+    pub fn get(&self, key: &FlowKey) -> Option<&FlowStats> {
+        self.flows.get(key)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item=(&FlowKey, &FlowStats)> {
+        self.flows.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.flows.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.flows.is_empty()
+    }
+
+    pub fn get_total_bytes(&self) -> usize {
+        self.flows.values().map(|f| f.bytes).sum()
+    }
+
+    pub fn get_total_packets(&self) -> u64 {
+        self.flows.values().map(|f| f.packets).sum()
+    }
+
+    /// Returns all the flows sorted by the given key, from the biggest to the smallest.
+    pub fn sorted_flows(&self, key: SortKey) -> Vec<(&FlowKey, &FlowStats)> {
+        let mut flows: Vec<(&FlowKey, &FlowStats)> = self.flows.iter().collect();
+        flows.sort_by_key(|(k, f)| {
+            let value = match key {
+                SortKey::Bytes => f.bytes as u64,
+                SortKey::Packets => f.packets
+            };
+            (Reverse(value), (*k).clone())
+        });
+        flows
+    }
+
+    /// Returns the n flows (conversations with a remote endpoint) with the most traffic.
+    pub fn top_flows(&self, n: usize, key: SortKey) -> Vec<(&FlowKey, &FlowStats)> {
+        let mut flows = self.sorted_flows(key);
+        flows.truncate(n);
+        flows
+    }
+
+    /// Returns the n remote hosts with the most traffic, summing all their flows.
+    pub fn top_hosts(&self, n: usize, key: SortKey) -> Vec<RankEntry> {
+        let mut hosts: HashMap<String, RankEntry> = HashMap::new();
+        for (k, f) in self.flows.iter() {
+            hosts.entry(k.0.clone()).or_insert_with(|| RankEntry::new(k.0.clone())).add(f);
+        }
+        rank(hosts, n, key)
+    }
+
+    /// Returns the n remote ports with the most traffic, named after the service when it is a well known one.
+    pub fn top_ports(&self, n: usize, key: SortKey) -> Vec<RankEntry> {
+        let mut ports: HashMap<String, RankEntry> = HashMap::new();
+        for (k, f) in self.flows.iter() {
+            let name = match service_name(k.1, &f.protocol) {
+                Some(service) => format!("{}/{} ({})", k.1, f.protocol.to_string(), service),
+                None => format!("{}/{}", k.1, f.protocol.to_string())
+            };
+            ports.entry(name.clone()).or_insert_with(|| RankEntry::new(name)).add(f);
+        }
+        rank(ports, n, key)
+    }
+
+    /// Returns the traffic of every protocol, sorted by bytes.
+    pub fn protocol_share(&self) -> Vec<RankEntry> {
+        let mut protocols: HashMap<String, RankEntry> = HashMap::new();
+        for f in self.flows.values() {
+            let name = f.protocol.to_string();
+            protocols.entry(name.clone()).or_insert_with(|| RankEntry::new(name)).add(f);
+        }
+        rank(protocols, usize::MAX, SortKey::Bytes)
+    }
+
+    /// Returns the traffic captured on every interface, sorted by bytes. A flow seen on several interfaces is
+    /// counted in all of them, with the packets and the bytes of each one.
+    pub fn interface_share(&self) -> Vec<RankEntry> {
+        let mut interfaces: HashMap<String, RankEntry> = HashMap::new();
+        for f in self.flows.values() {
+            for (name, (packets, bytes)) in &f.interfaces {
+                let entry = interfaces.entry(name.clone()).or_insert_with(|| RankEntry::new(name.clone()));
+                entry.packets += packets;
+                entry.bytes += bytes;
+                entry.flows += 1;
+            }
+        }
+        rank(interfaces, usize::MAX, SortKey::Bytes)
+    }
+
+    /// Returns a copy of the flows collected so far.
+    pub fn get_flows(&self) -> HashMap<FlowKey, FlowStats> {
+        self.flows.clone()
+    }
+
+    /// Returns the flows that have exchanged packets since a previous snapshot of this table, with the packets and the
+    /// bytes of the interval, sorted by the given key. The flows closed in the meantime are included as well: a flow
+    /// is the same one of the previous snapshot if it has the same key and first timestamp.
+    pub fn delta(&self, previous: &FlowTable, key: SortKey) -> Vec<FlowDelta> {
+        let since = previous.last_ts.unwrap_or(0);
+        let closed = self.finished.iter().filter(|f| f.stats.last_ts > since).map(|f| (&f.key, &f.stats));
+        let mut deltas: HashMap<FlowKey, FlowDelta> = HashMap::new();
+        for (k, f) in closed.chain(self.flows.iter()) {
+            let (packets, bytes, started) = match previous.flows.get(k) {
+                Some(old) if old.first_ts == f.first_ts => (f.packets.saturating_sub(old.packets), f.bytes.saturating_sub(old.bytes), false),
+                _ => (f.packets, f.bytes, true)
+            };
+            if packets == 0 {
+                continue
+            }
+            let delta = deltas.entry(k.clone())
+                .or_insert_with(|| FlowDelta { key: k.clone(), protocol: f.protocol.clone(), packets: 0, bytes: 0, started: false });
+            delta.packets += packets;
+            delta.bytes += bytes;
+            delta.started |= started;
+        }
+        let mut deltas: Vec<FlowDelta> = deltas.into_values().collect();
+        deltas.sort_by_key(|d| {
+            let value = match key {
+                SortKey::Bytes => d.bytes as u64,
+                SortKey::Packets => d.packets
+            };
+            (Reverse(value), d.key.clone())
+        });
+        deltas
+    }
+
+    /// Returns the number of flows closed by the timeouts or evicted so far.
+    pub fn get_closed(&self) -> u64 {
+        self.idle_closed + self.active_closed + self.evicted
+    }
+
+    /// Creates an empty table with the same settings.
+    pub fn empty_like(&self) -> FlowTable {
+        let mut table = FlowTable::with_bucket_width(self.bucket_width);
+        table.idle_timeout = self.idle_timeout;
+        table.active_timeout = self.active_timeout;
+        table.max_flows = self.max_flows;
+        table.eviction_policy = self.eviction_policy;
+        table.track_events = self.track_events;
+        table
+    }
+
+    /// Returns a table with only the flows, live or closed, accepted by the predicate.
+    /// The throughput and the distributions of the whole traffic are rebuilt from the ones of the accepted flows, so
+    /// the inter-arrival times are the ones between the packets of the same flow.
+    pub fn filtered<F: Fn(&FlowKey, &FlowStats) -> bool>(&self, accept: F) -> FlowTable {
+        let mut table = self.empty_like();
+        table.last_ts = self.last_ts;
+        table.last_sweep = self.last_sweep;
+        for (key, stats) in self.flows.iter().filter(|(key, stats)| accept(key, stats)) {
+            table.flows.insert(key.clone(), stats.clone());
+        }
+        table.finished = self.finished.iter().filter(|f| accept(&f.key, &f.stats)).cloned().collect();
+        for finished in table.finished.iter() {
+            match finished.reason {
+                CloseReason::IdleTimeout => table.idle_closed += 1,
+                CloseReason::ActiveTimeout => table.active_closed += 1,
+                CloseReason::Evicted => table.evicted += 1
+            }
+        }
+        for stats in table.flows.values().chain(table.finished.iter().map(|f| &f.stats)) {
+            table.throughput.merge(&stats.throughput);
+            table.sizes.merge(&stats.sizes);
+            table.inter_arrival.merge(&stats.inter_arrival);
+        }
+        table
+    }
+
+    /// Adds the content of another table, usually collected by another shard.
+    /// The flows found in both the tables are merged, while the inter-arrival times of the two tables are simply
+    /// added, since they can't be interleaved anymore.
+    pub fn merge(&mut self, other: FlowTable) {
+        for (key, stats) in other.flows {
+            match self.flows.get_mut(&key) {
+                Some(existing) => existing.merge(stats),
+                None => { self.flows.insert(key, stats); }
+            }
+        }
+        self.throughput.merge(&other.throughput);
+        self.sizes.merge(&other.sizes);
+        self.inter_arrival.merge(&other.inter_arrival);
+        self.last_ts = self.last_ts.max(other.last_ts);
+        // both the logs go from the oldest to the newest flow, so they are merged by the time they have been closed
+        let mut finished = VecDeque::with_capacity(self.finished.len() + other.finished.len());
+        let mut mine = std::mem::take(&mut self.finished).into_iter().peekable();
+        let mut others = other.finished.into_iter().peekable();
+        while let (Some(a), Some(b)) = (mine.peek(), others.peek()) {
+            let next = if b.closed_ts < a.closed_ts { others.next() } else { mine.next() };
+            finished.extend(next);
+        }
+        finished.extend(mine.chain(others));
+        self.finished = finished;
+        while self.finished.len() > MAX_FINISHED_FLOWS {
+            self.finished.pop_front();
+            self.finished_dropped += 1;
+        }
+        self.idle_closed += other.idle_closed;
+        self.active_closed += other.active_closed;
+        self.evicted += other.evicted;
+        self.finished_dropped += other.finished_dropped;
+    }
+}
+
+/// The arrival times of the packets captured by a thread, to compute the inter-arrival times of the whole traffic
+/// that can't be computed by the single shards. The packets must be added in order of arrival.
+#[derive(Debug, Clone)]
+pub struct Arrivals {
+    last_ts: Option<u64>,
+    inter_arrival: Histogram,
+}
+
+impl Arrivals {
+    fn new() -> Self {
+        Arrivals { last_ts: None, inter_arrival: Histogram::inter_arrival_times() }
+    }
+
+    /// Accounts the arrival of a packet at the given time, in microseconds.
+    pub fn add(&mut self, ts: u64) {
+        if let Some(last_ts) = self.last_ts {
+            self.inter_arrival.add(ts.saturating_sub(last_ts));
+        }
+        self.last_ts = Some(self.last_ts.map_or(ts, |last| last.max(ts)));
+    }
+}
+
+/// A flow table divided in shards, each one protected by its own lock, so that taking a snapshot blocks the
+/// ingestion of a shard at a time, and the packets of different flows can be aggregated in parallel.
+/// The packets of a flow always end up in the same shard, chosen by hashing the flow key.
+#[derive(Debug)]
+pub struct ShardedFlowTable {
+    shards: Vec<Mutex<FlowTable>>,
+    arrivals: Mutex<Vec<Arc<Mutex<Arrivals>>>>,
+    max_flows: AtomicUsize,
+}
+
+impl ShardedFlowTable {
+    pub fn new(shards: usize) -> Self {
+        ShardedFlowTable {
+            shards: (0..shards.max(1)).map(|_| Mutex::new(FlowTable::new())).collect(),
+            arrivals: Mutex::new(Vec::new()),
+            max_flows: AtomicUsize::new(0),
+        }
+    }
+
+    /// Returns a table with a different number of shards, with the same settings and content.
+    /// The content is moved in the first shard, and it is merged with the new flows in the snapshots.
+    pub fn resize(&self, shards: usize) -> ShardedFlowTable {
+        let content = self.snapshot();
+        let mut tables = vec![content.empty_like(); shards.max(1)];
+        tables[0] = content;
+        let resized = ShardedFlowTable {
+            shards: tables.into_iter().map(Mutex::new).collect(),
+            arrivals: Mutex::new(self.arrivals.lock().unwrap().clone()),
+            max_flows: AtomicUsize::new(0),
+        };
+        resized.set_max_flows(self.get_max_flows());
+        resized
+    }
+
+    /// Returns an empty table with the same number of shards and the same settings, for a new scanning.
+    pub fn empty_like(&self) -> ShardedFlowTable {
+        let table = self.setting(|table| table.empty_like());
+        let empty = ShardedFlowTable {
+            shards: (0..self.shards.len()).map(|_| Mutex::new(table.clone())).collect(),
+            arrivals: Mutex::new(Vec::new()),
+            max_flows: AtomicUsize::new(0),
+        };
+        empty.set_max_flows(self.get_max_flows());
+        empty
+    }
+
+    /// Returns the shard a flow belongs to.
+    pub fn shard_of(&self, key: &FlowKey) -> usize {
+        let mut hasher = DefaultHasher::new();
+        key.hash(&mut hasher);
+        (hasher.finish() % self.shards.len() as u64) as usize
+    }
+
+    pub fn shards(&self) -> usize {
+        self.shards.len()
+    }
+
+    /// Accounts a new packet in the shard of its flow, so that every flow lives in a single shard whatever thread
+    /// decoded its packets. Returns the flows started and closed by the packet, if the table tracks them.
+    pub fn update(&self, info: &PacketInfo) -> Vec<FlowEvent> {
+        let mut table = self.shards[self.shard_of(&(info.get_address(), info.get_port()))].lock().unwrap();
+        table.update(info);
+        table.take_events()
+    }
+
+    /// Accounts a new packet in the given shard, that must be the one of its flow. It is used by the threads that own a
+    /// shard, receiving only the packets of its flows. Returns the flows started and closed by the packet, if the
+    /// table tracks them.
+    pub fn update_shard(&self, shard: usize, info: &PacketInfo) -> Vec<FlowEvent> {
+        debug_assert_eq!(shard, self.shard_of(&(info.get_address(), info.get_port())));
+        let mut table = self.shards[shard].lock().unwrap();
+        table.update(info);
+        table.take_events()
+    }
+
+    /// Returns the arrival times of a new capture thread, that are merged with the ones of the other threads in the
+    /// snapshots. Every thread keeps its own, so that they don't contend for a lock.
+    pub fn arrivals(&self) -> Arc<Mutex<Arrivals>> {
+        let arrivals = Arc::new(Mutex::new(Arrivals::new()));
+        self.arrivals.lock().unwrap().push(arrivals.clone());
+        arrivals
+    }
+
+    /// Closes the flows of all the shards that have exceeded the timeouts at the given time, in microseconds.
+    /// Returns the flows closed, if the table tracks them.
+    pub fn expire(&self, now: u64) -> Vec<FlowEvent> {
+        self.shards.iter().flat_map(|shard| {
+            let mut table = shard.lock().unwrap();
+            table.expire(now);
+            table.take_events()
+        }).collect()
+    }
+
+    /// Applies the same change (e.g. a setting) to all the shards.
+    pub fn configure<F: Fn(&mut FlowTable)>(&self, f: F) {
+        self.shards.iter().for_each(|shard| f(&mut shard.lock().unwrap()));
+    }
+
+    /// Reads a setting, that is the same for all the shards.
+    pub fn setting<T, F: Fn(&FlowTable) -> T>(&self, f: F) -> T {
+        f(&self.shards[0].lock().unwrap())
+    }
+
+    /// Sets the maximum number of live flows of the whole table, divided between the shards.
+    pub fn set_max_flows(&self, max_flows: usize) {
+        self.max_flows.store(max_flows, Ordering::Relaxed);
+        let share = max_flows.div_ceil(self.shards.len());
+        self.configure(|table| table.set_max_flows(share));
+    }
+
+    pub fn get_max_flows(&self) -> usize {
+        self.max_flows.load(Ordering::Relaxed)
+    }
+
+    /// Returns a copy of the whole table, merging the shards. Every shard is locked only while it is copied.
+    pub fn snapshot(&self) -> FlowTable {
+        let mut tables = self.shards.iter().map(|shard| shard.lock().unwrap().clone());
+        let mut snapshot = tables.next().unwrap();
+        tables.for_each(|table| snapshot.merge(table));
+        snapshot.inter_arrival = Histogram::inter_arrival_times();
+        for arrivals in self.arrivals.lock().unwrap().iter() {
+            snapshot.inter_arrival.merge(&arrivals.lock().unwrap().inter_arrival);
+        }
+        snapshot.max_flows = self.get_max_flows();
+        snapshot
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use crate::flow::{CloseReason, EvictionPolicy, FlowEvent, FlowTable, MAX_FINISHED_FLOWS, ShardedFlowTable, SortKey};
+    use crate::pkt_parser::{PacketInfo, Protocol, TimeVal};
+
+    fn packet(address: &str, port: u16, protocol: Protocol, bytes: usize) -> PacketInfo {
+        packet_at(address, port, protocol, bytes, 1_000_000)
+    }
+
+    fn packet_at(address: &str, port: u16, protocol: Protocol, bytes: usize, ts: u64) -> PacketInfo {
+        PacketInfo::new(address.to_string(), port, protocol, bytes, TimeVal::from(ts))
+    }
+
+    #[test]
+    fn test_rankings() {
+        let mut table = FlowTable::new();
+        table.update(&packet("10.0.0.1", 443, Protocol::TCP, 1000));
+        table.update(&packet("10.0.0.1", 80, Protocol::TCP, 500));
+        table.update(&packet("10.0.0.2", 443, Protocol::TCP, 1200));
+        for _ in 0..5 { table.update(&packet("10.0.0.3", 53, Protocol::UDP, 10)); }
+
+        let hosts = table.top_hosts(2, SortKey::Bytes);
+        assert_eq!(hosts.iter().map(|h| h.get_name()).collect::<Vec<String>>(), vec!["10.0.0.1", "10.0.0.2"]);
+        assert_eq!(hosts[0].get_bytes(), 1500);
+        assert_eq!(hosts[0].get_flows(), 2);
+
+        let ports = table.top_ports(1, SortKey::Packets);
+        assert_eq!(ports[0].get_name(), "53/UDP (dns)");
+        assert_eq!(ports[0].get_packets(), 5);
+
+        let flows = table.top_flows(1, SortKey::Bytes);
+        assert_eq!(flows[0].0, &("10.0.0.2".to_string(), 443));
+
+        let protocols = table.protocol_share();
+        assert_eq!(protocols[0].get_name(), "TCP");
+        assert_eq!(protocols[0].get_bytes() + protocols[1].get_bytes(), table.get_total_bytes());
+    }
+
+    #[test]
+    fn test_interface_share() {
+        let mut table = FlowTable::new();
+        table.update(&packet("10.0.0.1", 443, Protocol::TCP, 1000).with_interface("eth0".to_string()));
+        table.update(&packet("10.0.0.1", 443, Protocol::TCP, 300).with_interface("eth1".to_string()));
+        table.update(&packet("10.0.0.2", 53, Protocol::UDP, 100).with_interface("eth1".to_string()));
+
+        let interfaces = table.interface_share();
+        assert_eq!(interfaces.iter().map(|i| i.get_name()).collect::<Vec<String>>(), vec!["eth0", "eth1"]);
+        assert_eq!(interfaces[1].get_bytes(), 400);
+        assert_eq!(interfaces[1].get_flows(), 2);
+        assert_eq!(interfaces.iter().map(|i| i.get_bytes()).sum::<usize>(), table.get_total_bytes());
+        assert_eq!(table.get(&("10.0.0.1".to_string(), 443)).unwrap().get_interfaces().len(), 2);
+    }
+
+    #[test]
+    fn test_timeouts() {
+        let mut table = FlowTable::new();
+        table.set_idle_timeout(10);
+        table.set_active_timeout(60);
+        table.update(&packet_at("10.0.0.1", 443, Protocol::TCP, 100, 1_000_000));
+        table.update(&packet_at("10.0.0.2", 53, Protocol::UDP, 100, 1_000_000));
+        for sec in (2..70).step_by(2) {
+            table.update(&packet_at("10.0.0.1", 443, Protocol::TCP, 100, sec * 1_000_000));
+        }
+        let finished = table.get_finished_flows();
+        assert_eq!(finished.len(), 2);
+        assert_eq!(finished[0].get_key(), &("10.0.0.2".to_string(), 53));
+        assert_eq!(finished[0].get_reason(), CloseReason::IdleTimeout);
+        assert_eq!(finished[1].get_reason(), CloseReason::ActiveTimeout);
+        assert_eq!(table.len(), 1);
+        // the series of the host is kept after its last flow has been closed
+        assert_eq!(table.get_host_throughput("10.0.0.2").unwrap().get_buckets().iter().map(|b| b.get_bytes()).sum::<u64>(), 100);
+    }
+
+    #[test]
+    fn test_out_of_order_packets() {
+        let mut table = FlowTable::new();
+        table.set_idle_timeout(10);
+        table.update(&packet_at("10.0.0.1", 443, Protocol::TCP, 100, 5_000_000));
+        table.update(&packet_at("10.0.0.1", 443, Protocol::TCP, 100, 4_000_000));
+        let flow = table.get(&("10.0.0.1".to_string(), 443)).unwrap();
+        assert_eq!((flow.get_first_ts(), flow.get_last_ts()), (4_000_000, 5_000_000));
+        // the late packet doesn't move the last activity back, so the flow isn't expired early
+        table.update(&packet_at("10.0.0.2", 53, Protocol::UDP, 100, 14_500_000));
+        assert_eq!(table.len(), 2);
+        // a huge timeout never expires the flows instead of overflowing
+        table.set_idle_timeout(u64::MAX);
+        table.expire(u64::MAX - 1);
+        assert_eq!(table.len(), 2);
+    }
+
+    #[test]
+    fn test_eviction() {
+        let mut table = FlowTable::new();
+        table.set_max_flows(2);
+        table.set_eviction_policy(EvictionPolicy::SmallestFirst);
+        table.update(&packet_at("10.0.0.1", 443, Protocol::TCP, 1000, 1_000_000));
+        table.update(&packet_at("10.0.0.2", 443, Protocol::TCP, 10, 1_100_000));
+        table.update(&packet_at("10.0.0.3", 443, Protocol::TCP, 500, 1_200_000));
+        assert_eq!(table.len(), 2);
+        assert_eq!(table.get_evicted(), 1);
+        assert!(table.get(&("10.0.0.2".to_string(), 443)).is_none());
+
+        table.set_eviction_policy(EvictionPolicy::Lru);
+        table.update(&packet_at("10.0.0.4", 443, Protocol::TCP, 10, 1_300_000));
+        assert!(table.get(&("10.0.0.1".to_string(), 443)).is_none());
+        assert_eq!(table.get_finished_flows().len(), 2);
+    }
+
+    #[test]
+    fn test_evicted_hosts_are_bounded() {
+        let mut table = FlowTable::new();
+        table.set_max_flows(8);
+        let hosts: Vec<String> = (0..10_000u64).map(|i| format!("10.0.{}.{}", i / 256, i % 256)).collect();
+        for (i, host) in hosts.iter().enumerate() {
+            table.update(&packet_at(host, 443, Protocol::TCP, 100, 1_000_000 + i as u64));
+        }
+        assert!(table.len() <= 8);
+        // the series of the hosts don't outlive the log of the finished flows
+        let finished = table.get_finished_flows();
+        let series: HashSet<&String> = table.iter().map(|(k, _)| &k.0).chain(finished.iter().map(|f| &f.get_key().0)).collect();
+        assert!(series.len() <= 8 + MAX_FINISHED_FLOWS);
+        assert!(table.get_host_throughput(&hosts[0]).is_none());
+        assert!(table.get_host_throughput(&hosts[9_999]).is_some());
+    }
+
+    #[test]
+    fn test_merge_finished_flows() {
+        let mut first = FlowTable::new();
+        let mut second = FlowTable::new();
+        first.set_idle_timeout(1);
+        second.set_idle_timeout(1);
+        first.update(&packet_at("10.0.0.1", 443, Protocol::TCP, 100, 1_000_000));
+        second.update(&packet_at("10.0.0.2", 443, Protocol::TCP, 100, 1_000_000));
+        first.update(&packet_at("10.0.0.3", 443, Protocol::TCP, 100, 2_500_000));
+        second.update(&packet_at("10.0.0.4", 443, Protocol::TCP, 100, 2_000_000));
+        first.expire(5_000_000);
+        second.expire(4_000_000);
+        // the log of the merged table still goes from the oldest to the newest closed flow
+        first.merge(second);
+        let finished = first.get_finished_flows();
+        assert_eq!(finished.iter().map(|f| f.get_key().0.as_str()).collect::<Vec<&str>>(), vec!["10.0.0.2", "10.0.0.1", "10.0.0.4", "10.0.0.3"]);
+        assert_eq!(finished.iter().map(|f| f.get_closed_ts()).collect::<Vec<u64>>(), vec![2_000_000, 2_500_000, 4_000_000, 5_000_000]);
+    }
+
+    #[test]
+    fn test_sharded_snapshot() {
+        let sharded = ShardedFlowTable::new(4);
+        let arrivals = sharded.arrivals();
+        let mut table = FlowTable::new();
+        for i in 0..50u64 {
+            let info = packet_at(format!("10.0.0.{}", i % 7).as_str(), (i % 3) as u16, Protocol::UDP, 10, 1_000_000 + i * 1000);
+            arrivals.lock().unwrap().add(1_000_000 + i * 1000);
+            sharded.update(&info);
+            table.update(&info);
+        }
+        let snapshot = sharded.snapshot();
+        assert_eq!(snapshot.len(), table.len());
+        assert_eq!(snapshot.get_total_bytes(), table.get_total_bytes());
+        assert_eq!(snapshot.top_hosts(3, SortKey::Bytes), table.top_hosts(3, SortKey::Bytes));
+        assert_eq!(snapshot.get_inter_arrival_times().get_samples(), 49);
+        assert_eq!(snapshot.get_host_throughput("10.0.0.1").unwrap().get_buckets(),
+                   table.get_host_throughput("10.0.0.1").unwrap().get_buckets());
+    }
+
+    #[test]
+    fn test_flow_events() {
+        let sharded = ShardedFlowTable::new(1);
+        sharded.configure(|table| {
+            table.set_idle_timeout(1);
+            table.set_track_events(true);
+        });
+        let events = sharded.update(&packet_at("10.0.0.2", 53, Protocol::UDP, 100, 1_500_000));
+        assert!(matches!(events.as_slice(), [FlowEvent::Started(key, stats)] if key.0 == "10.0.0.2" && stats.get_packets() == 1));
+        assert!(sharded.update(&packet_at("10.0.0.2", 53, Protocol::UDP, 100, 1_600_000)).is_empty());
+        // the sweep closes the idle flow before the new one starts
+        let events = sharded.update(&packet_at("10.0.0.3", 53, Protocol::UDP, 100, 5_000_000));
+        assert_eq!(events.len(), 2);
+        assert!(matches!(&events[0], FlowEvent::Closed(flow) if flow.get_key().0 == "10.0.0.2" && flow.get_reason() == CloseReason::IdleTimeout));
+        assert!(matches!(&events[1], FlowEvent::Started(key, _) if key.0 == "10.0.0.3"));
+        assert!(sharded.empty_like().setting(|table| table.track_events));
+    }
+
+    #[test]
+    fn test_sharded_expire() {
+        let sharded = ShardedFlowTable::new(4);
+        sharded.configure(|table| {
+            table.set_idle_timeout(10);
+            table.set_track_events(true);
+        });
+        for i in 0..8u64 {
+            sharded.update(&packet_at(format!("10.0.0.{}", i).as_str(), 443, Protocol::TCP, 100, 1_000_000 + i * 1_000_000));
+        }
+        // without new packets the flows are closed by the periodic sweep
+        let events = sharded.expire(14_500_000);
+        assert_eq!(events.len(), 4);
+        assert!(events.iter().all(|event| matches!(event, FlowEvent::Closed(flow) if flow.get_reason() == CloseReason::IdleTimeout)));
+        assert_eq!(sharded.snapshot().len(), 4);
+        assert_eq!(sharded.snapshot().get_finished_flows().len(), 4);
+    }
+
+    #[test]
+    fn test_delta() {
+        let mut table = FlowTable::new();
+        table.set_idle_timeout(2);
+        table.update(&packet_at("10.0.0.1", 443, Protocol::TCP, 1000, 1_000_000));
+        table.update(&packet_at("10.0.0.2", 53, Protocol::UDP, 100, 1_100_000));
+        let previous = table.clone();
+        assert!(table.delta(&previous, SortKey::Bytes).is_empty());
+
+        table.update(&packet_at("10.0.0.1", 443, Protocol::TCP, 500, 1_200_000));
+        table.update(&packet_at("10.0.0.3", 80, Protocol::TCP, 200, 1_300_000));
+        // the UDP flow is closed by the sweep without new packets, so it isn't part of the interval
+        table.update(&packet_at("10.0.0.1", 443, Protocol::TCP, 100, 3_150_000));
+        let delta = table.delta(&previous, SortKey::Bytes);
+        assert_eq!(delta.len(), 2);
+        assert_eq!((delta[0].get_key().0.as_str(), delta[0].get_packets(), delta[0].get_bytes(), delta[0].is_started()), ("10.0.0.1", 2, 600, false));
+        assert_eq!((delta[1].get_key().0.as_str(), delta[1].get_packets(), delta[1].get_bytes(), delta[1].is_started()), ("10.0.0.3", 1, 200, true));
+        assert_eq!(table.get_closed(), 1);
+
+        // the closed flow still counts the packets it has exchanged in the interval
+        let previous = table.clone();
+        table.update(&packet_at("10.0.0.3", 80, Protocol::TCP, 50, 3_600_000));
+        table.update(&packet_at("10.0.0.4", 80, Protocol::TCP, 10, 6_000_000));
+        let delta = table.delta(&previous, SortKey::Packets);
+        assert_eq!(delta.iter().map(|d| (d.get_key().0.as_str(), d.get_packets())).collect::<Vec<_>>(), vec![("10.0.0.3", 1), ("10.0.0.4", 1)]);
+    }
+
+    #[test]
+    fn test_sharded_empty_like() {
+        let sharded = ShardedFlowTable::new(4);
+        sharded.set_max_flows(100);
+        sharded.configure(|table| table.set_idle_timeout(30));
+        sharded.update(&packet("10.0.0.1", 443, Protocol::TCP, 1000));
+        let empty = sharded.empty_like();
+        assert_eq!(empty.shards(), 4);
+        assert_eq!(empty.get_max_flows(), 100);
+        assert_eq!(empty.setting(|table| table.get_idle_timeout()), 30);
+        assert!(empty.snapshot().is_empty());
+        assert_eq!(sharded.snapshot().len(), 1);
+    }
+
+    #[test]
+    fn test_merge_same_flow() {
+        // after a resize the content is kept in the first shard, while the new packets go to the shard of their flow
+        let sharded = ShardedFlowTable::new(2);
+        let arrivals = sharded.arrivals();
+        for i in 0..5u64 {
+            arrivals.lock().unwrap().add(1_000_000 + i * 1000);
+            sharded.update(&packet_at("10.0.0.1", 443, Protocol::UDP, 100, 1_000_000 + i * 1000));
+        }
+        sharded.set_max_flows(8);
+        let resized = sharded.resize(4);
+        assert_eq!(resized.get_max_flows(), 8);
+        for i in 5..10u64 {
+            arrivals.lock().unwrap().add(1_000_000 + i * 1000);
+            resized.update(&packet_at("10.0.0.1", 443, Protocol::UDP, 100, 1_000_000 + i * 1000));
+        }
+        resized.update(&packet_at("10.0.0.2", 53, Protocol::UDP, 50, 1_020_000));
+        let snapshot = resized.snapshot();
+        assert_eq!(snapshot.len(), 2);
+        let flow = snapshot.get(&("10.0.0.1".to_string(), 443)).unwrap();
+        assert_eq!(flow.get_packets(), 10);
+        assert_eq!(flow.get_bytes(), 1000);
+        assert_eq!(flow.get_first_ts(), 1_000_000);
+        assert_eq!(flow.get_last_ts(), 1_009_000);
+        assert_eq!(snapshot.top_hosts(1, SortKey::Bytes)[0].get_flows(), 1);
+        assert_eq!(snapshot.get_inter_arrival_times().get_samples(), 9);
+        assert_eq!(snapshot.get_total_bytes(), 1050);
+    }
+}
@@ -0,0 +1,291 @@
+//! tcp
+//! Passive analysis of TCP connections. Looking at the sequence and acknowledgement numbers of both directions of a
+//! connection it is possible to estimate the round trip time and to detect retransmissions, duplicate ACKs,
+//! out-of-order segments and zero window advertisements, without any cooperation from the endpoints.
+
+use std::collections::{HashMap, VecDeque};
+use crate::pkt_parser::{Direction, TCPHeader, TCPSegment};
+
+/// Maximum number of segments waiting for an ACK tracked for each direction of a connection.
+const MAX_PENDING_SEGMENTS: usize = 128;
+/// Maximum number of connections tracked at the same time for a single flow.
+const MAX_CONNECTIONS: usize = 256;
+/// A segment that arrives "late" within this time (in microseconds) from the previous one is considered out of order
+/// instead of a retransmission, when no RTT estimation is available yet.
+const OUT_OF_ORDER_THRESHOLD: u64 = 3000;
+
+/// Returns true if the sequence number a comes before b, taking care of the wrap around.
+fn seq_before(a: u32, b: u32) -> bool {
+    (a.wrapping_sub(b) as i32) < 0
+}
+
+/// A segment sent and not yet acknowledged, used to sample the RTT.
+#[derive(Debug, Clone)]
+struct PendingSegment {
+    end_seq: u32,
+    ts: u64,
+    retransmitted: bool,
+}
+
+/// The state of one direction of a connection.
+#[derive(Debug, Clone, Default)]
+struct HalfConnection {
+    next_seq: Option<u32>,
+    last_ack: Option<u32>,
+    last_window: u16,
+    last_ts: u64,
+    pending: VecDeque<PendingSegment>,
+}
+
+/// The state of a single connection, needed to compute the statistics.
+#[derive(Debug, Clone, Default)]
+struct Connection {
+    syn_ts: Option<u64>,
+    syn_direction: Option<Direction>,
+    syn_ack_seen: bool,
+    handshake_done: bool,
+    transmitted: HalfConnection,
+    received: HalfConnection,
+    fin_transmitted: bool,
+    fin_received: bool,
+    last_ts: u64,
+}
+
+impl Connection {
+    /// Returns the state of the direction of the segment and the one of the opposite direction.
+    fn halves(&mut self, direction: &Direction) -> (&mut HalfConnection, &mut HalfConnection) {
+        match direction {
+            Direction::Transmitted => (&mut self.transmitted, &mut self.received),
+            Direction::Received => (&mut self.received, &mut self.transmitted),
+        }
+    }
+}
+
+/// Performance statistics of the TCP connections of a flow.
+/// All the times are expressed in microseconds, and the RTT is the one observed from the capture point: the time
+/// between a segment and the ACK that covers it, sampled in both directions.
+#[derive(Debug, Clone, Default)]
+pub struct TcpStats {
+    handshake_samples: u64,
+    handshake_sum: u64,
+    rtt_samples: u64,
+    rtt_sum: u64,
+    rtt_min: Option<u64>,
+    rtt_max: Option<u64>,
+    retransmissions: u64,
+    duplicate_acks: u64,
+    out_of_order: u64,
+    zero_window: u64,
+    connections: HashMap<u16, Connection>,
+}
+
+impl TcpStats {
+    pub fn new() -> Self {
+        TcpStats::default()
+    }
+
+    /// Adds the statistics collected for other connections of the same flow.
+    pub fn merge(&mut self, other: TcpStats) {
+        self.handshake_samples += other.handshake_samples;
+        self.handshake_sum += other.handshake_sum;
+        self.rtt_samples += other.rtt_samples;
+        self.rtt_sum += other.rtt_sum;
+        self.rtt_min = self.rtt_min.into_iter().chain(other.rtt_min).min();
+        self.rtt_max = self.rtt_max.into_iter().chain(other.rtt_max).max();
+        self.retransmissions += other.retransmissions;
+        self.duplicate_acks += other.duplicate_acks;
+        self.out_of_order += other.out_of_order;
+        self.zero_window += other.zero_window;
+        self.connections.extend(other.connections);
+    }
+
+    /// Updates the statistics with a new segment of the flow.
+    /// The timestamp is expressed in microseconds.
+    pub fn update(&mut self, segment: &TCPSegment, direction: &Direction, ts: u64) {
+        if !self.connections.contains_key(&segment.get_local_port()) && self.connections.len() >= MAX_CONNECTIONS {
+            // forget the connection that has been idle for the longest time
+            let oldest = self.connections.iter().min_by_key(|(_, c)| c.last_ts).map(|(port, _)| *port);
+            if let Some(port) = oldest { self.connections.remove(&port); }
+        }
+        let srtt = self.get_rtt();
+        let mut conn = self.connections.remove(&segment.get_local_port()).unwrap_or_default();
+        conn.last_ts = ts;
+
+        let syn = segment.has_flag(TCPHeader::SYN);
+        let ack = segment.has_flag(TCPHeader::ACK);
+        let fin = segment.has_flag(TCPHeader::FIN);
+        let rst = segment.has_flag(TCPHeader::RST);
+
+        if segment.get_window() == 0 && !rst && !syn {
+            self.zero_window += 1;
+        }
+
+        // handshake: the RTT is measured from the SYN to the ACK that completes the three-way handshake
+        if syn && !ack {
+            if conn.syn_ts.is_none() {
+                conn.syn_ts = Some(ts);
+                conn.syn_direction = Some(direction.clone());
+            }
+        } else if syn && ack {
+            conn.syn_ack_seen = true;
+        } else if ack && conn.syn_ack_seen && !conn.handshake_done && conn.syn_direction.as_ref() == Some(direction) {
+            conn.handshake_done = true;
+            if let Some(syn_ts) = conn.syn_ts {
+                self.handshake_samples += 1;
+                self.handshake_sum += ts.saturating_sub(syn_ts);
+            }
+        }
+
+        let (own, other) = conn.halves(direction);
+
+        // sequence analysis: SYN and FIN consume a sequence number as a byte of data does
+        let seg_len = segment.get_payload_len() as u32 + syn as u32 + fin as u32;
+        if seg_len > 0 {
+            let seq = segment.get_seq();
+            let end_seq = seq.wrapping_add(seg_len);
+            match own.next_seq {
+                Some(next) if !seq_before(next, end_seq) => {
+                    // all the data has already been seen: it is either late or sent again
+                    let threshold = srtt.unwrap_or(OUT_OF_ORDER_THRESHOLD).max(OUT_OF_ORDER_THRESHOLD);
+                    if ts.saturating_sub(own.last_ts) < threshold {
+                        self.out_of_order += 1;
+                    } else {
+                        self.retransmissions += 1;
+                    }
+                    // Karn's algorithm: retransmitted data cannot be used to sample the RTT
+                    own.pending.iter_mut()
+                        .filter(|p| seq_before(seq, p.end_seq))
+                        .for_each(|p| p.retransmitted = true);
+                },
+                _ => {
+                    own.next_seq = Some(end_seq);
+                    own.pending.push_back(PendingSegment { end_seq, ts, retransmitted: false });
+                    if own.pending.len() > MAX_PENDING_SEGMENTS {
+                        own.pending.pop_front();
+                    }
+                }
+            }
+            own.last_ts = ts;
+        }
+
+        if ack {
+            let ack_seq = segment.get_ack();
+            // RTT: the newest segment of the other direction fully covered by this ACK gives the sample
+            let mut sample = None;
+            while let Some(pending) = other.pending.front() {
+                if seq_before(ack_seq, pending.end_seq) { break; }
+                if !pending.retransmitted { sample = Some(ts.saturating_sub(pending.ts)); }
+                other.pending.pop_front();
+            }
+
+            let pure_ack = segment.get_payload_len() == 0 && !syn && !fin && !rst;
+            if pure_ack && sample.is_none() && !other.pending.is_empty()
+                && own.last_ack == Some(ack_seq) && own.last_window == segment.get_window() {
+                self.duplicate_acks += 1;
+            }
+            own.last_ack = Some(ack_seq);
+            own.last_window = segment.get_window();
+
+            if let Some(rtt) = sample {
+                self.rtt_samples += 1;
+                self.rtt_sum += rtt;
+                self.rtt_min = Some(self.rtt_min.map_or(rtt, |m| m.min(rtt)));
+                self.rtt_max = Some(self.rtt_max.map_or(rtt, |m| m.max(rtt)));
+            }
+        }
+
+        match direction {
+            Direction::Transmitted => conn.fin_transmitted |= fin,
+            Direction::Received => conn.fin_received |= fin,
+        }
+        // a closed connection is forgotten, so a new one on the same port starts from scratch
+        let closed = rst || (conn.fin_transmitted && conn.fin_received);
+        if !closed {
+            self.connections.insert(segment.get_local_port(), conn);
+        }
+    }
+
+    /// Forgets the state of the connections, keeping only the statistics.
+    pub fn close(&mut self) {
+        self.connections = HashMap::new();
+    }
+
+    /// Returns the average time needed to complete the three-way handshake, if at least one has been observed.
+    pub fn get_handshake_rtt(&self) -> Option<u64> {
+        self.handshake_sum.checked_div(self.handshake_samples)
+    }
+    /// Returns the average RTT sampled from the data segments and their ACKs.
+    pub fn get_rtt(&self) -> Option<u64> {
+        self.rtt_sum.checked_div(self.rtt_samples)
+    }
+    pub fn get_rtt_min(&self) -> Option<u64> { self.rtt_min }
+    pub fn get_rtt_max(&self) -> Option<u64> { self.rtt_max }
+    pub fn get_rtt_samples(&self) -> u64 { self.rtt_samples }
+    pub fn get_retransmissions(&self) -> u64 { self.retransmissions }
+    pub fn get_duplicate_acks(&self) -> u64 { self.duplicate_acks }
+    pub fn get_out_of_order(&self) -> u64 { self.out_of_order }
+    pub fn get_zero_window(&self) -> u64 { self.zero_window }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::flow::tcp::TcpStats;
+    use crate::pkt_parser::{Direction, TCPHeader, TCPSegment};
+
+    fn segment(seq: u32, ack: u32, flags: u8, window: u16, payload_len: usize) -> TCPSegment {
+        let mut data = vec![0u8; 20];
+        data[0..2].copy_from_slice(&50000u16.to_be_bytes());
+        data[2..4].copy_from_slice(&443u16.to_be_bytes());
+        data[4..8].copy_from_slice(&seq.to_be_bytes());
+        data[8..12].copy_from_slice(&ack.to_be_bytes());
+        data[12] = 5 << 4;
+        data[13] = flags;
+        data[14..16].copy_from_slice(&window.to_be_bytes());
+        let (header, _payload) = <TCPHeader as crate::pkt_parser::Header>::decode(data);
+        TCPSegment::new(&header.unwrap(), 50000, payload_len)
+    }
+
+    #[test]
+    fn test_handshake_and_rtt() {
+        let mut stats = TcpStats::new();
+        stats.update(&segment(100, 0, TCPHeader::SYN, 1000, 0), &Direction::Transmitted, 1_000);
+        stats.update(&segment(500, 101, TCPHeader::SYN | TCPHeader::ACK, 1000, 0), &Direction::Received, 21_000);
+        stats.update(&segment(101, 501, TCPHeader::ACK, 1000, 0), &Direction::Transmitted, 21_500);
+        assert_eq!(stats.get_handshake_rtt(), Some(20_500));
+
+        stats.update(&segment(101, 501, TCPHeader::ACK, 1000, 100), &Direction::Transmitted, 30_000);
+        stats.update(&segment(501, 201, TCPHeader::ACK, 1000, 0), &Direction::Received, 45_000);
+        // SYN -> SYN/ACK, SYN/ACK -> ACK and data -> ACK
+        assert_eq!(stats.get_rtt_samples(), 3);
+        assert_eq!(stats.get_rtt_max(), Some(20_000));
+        assert_eq!(stats.get_rtt_min(), Some(500));
+        assert_eq!(stats.get_retransmissions(), 0);
+    }
+
+    #[test]
+    fn test_retransmission_and_duplicate_ack() {
+        let mut stats = TcpStats::new();
+        stats.update(&segment(1000, 1, TCPHeader::ACK, 1000, 100), &Direction::Transmitted, 0);
+        stats.update(&segment(1100, 1, TCPHeader::ACK, 1000, 100), &Direction::Transmitted, 100);
+        // the first segment is lost: the receiver keeps asking for it
+        stats.update(&segment(1, 1000, TCPHeader::ACK, 1000, 0), &Direction::Received, 20_000);
+        stats.update(&segment(1, 1000, TCPHeader::ACK, 1000, 0), &Direction::Received, 20_100);
+        stats.update(&segment(1000, 1, TCPHeader::ACK, 1000, 100), &Direction::Transmitted, 200_000);
+        stats.update(&segment(1, 1200, TCPHeader::ACK, 1000, 0), &Direction::Received, 220_000);
+        assert_eq!(stats.get_duplicate_acks(), 1);
+        assert_eq!(stats.get_retransmissions(), 1);
+        // the retransmitted data must not be used to sample the RTT
+        assert_eq!(stats.get_rtt_samples(), 0);
+    }
+
+    #[test]
+    fn test_out_of_order_and_zero_window() {
+        let mut stats = TcpStats::new();
+        stats.update(&segment(1100, 1, TCPHeader::ACK, 1000, 100), &Direction::Received, 0);
+        stats.update(&segment(1000, 1, TCPHeader::ACK, 1000, 100), &Direction::Received, 500);
+        stats.update(&segment(1, 1200, TCPHeader::ACK, 0, 0), &Direction::Transmitted, 600);
+        assert_eq!(stats.get_out_of_order(), 1);
+        assert_eq!(stats.get_retransmissions(), 0);
+        assert_eq!(stats.get_zero_window(), 1);
+    }
+}
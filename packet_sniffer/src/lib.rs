@@ -11,142 +11,157 @@
 //! thread::sleep(Duration::from_secs(2));
 //! sniffer.save_report();
 //! ```
+//! The programs that embed the sniffer can also react to the packets and the flows as they are decoded, with the
+//! subscriptions of the [events] module. With the `tokio` feature, the applications that run on a tokio runtime can
+//! consume the same events as an async stream, see the `stream` module.
+//! The reports can be written in several formats and to several outputs at once, see the [report] module.
 
 extern crate core;
 #[macro_use] extern crate prettytable;
 pub mod pkt_parser;
+pub mod flow;
+pub mod stats;
+pub mod pipeline;
+pub mod filter;
+pub mod capture;
+pub mod dump;
+pub mod rotation;
+pub mod stop;
+pub mod session;
+pub mod events;
+pub mod report;
+#[cfg(feature = "tokio")]
+pub mod stream;
 
 /// Sniffer module
 pub mod sniffer {
-    use chrono::{Local, TimeZone};
+    use chrono::{DateTime, Local};
     use std::collections::HashMap;
     use std::fs::File;
-    use std::io::{Seek, Write};
     use std::path::Path;
-    use std::sync::{Arc, Condvar, Mutex};
+    use std::sync::{Arc, Condvar, Mutex, RwLock};
     use std::fmt::{Display, Formatter};
-    use std::sync::mpsc::channel;
+    use std::sync::mpsc::sync_channel;
     use std::thread;
-    use std::time::Duration;
+    use std::thread::JoinHandle;
+    use std::time::{Duration, Instant};
     use ansi_term::Color::{Blue, Green};
     use ansi_term::Colour;
-    use pcap::{Capture, Device};
+    use pcap::{Capture, Device, Linktype, Packet, PacketHeader};
     use libc;
-    use prettytable::{Cell, Row, Table};
-    use crate::pkt_parser;
     use crate::pkt_parser::{*};
-    use std::fs::OpenOptions;
+    use crate::flow::{EvictionPolicy, FinishedFlow, FlowKey, FlowStats, FlowTable, ShardedFlowTable, SortKey, DEFAULT_SHARDS};
+    use crate::pipeline::{DecodeFailures, Delivery, FailureSample, OverflowPolicy, PipelineCounters, PipelineStats, UnclassifiedTraffic, DEFAULT_DROP_THRESHOLD, DEFAULT_QUEUE_CAPACITY};
+    use crate::stats::{Histogram, Throughput};
+    use crate::filter::DisplayFilter;
+    use crate::capture::{CaptureConfig, TimestampPrecision};
+    use crate::dump::{DumpFile, DumpFormat, DumpHeader, DumpInterface};
+    use crate::rotation::Rotation;
+    use crate::stop::{StopConditions, StopMonitor, StopReason};
+    use crate::session::{Session, SessionSettings};
+    use crate::events::{EventBus, EventKind, SnifferEvent, Subscription};
+    use crate::report::{text, IntervalData, IntervalMode, ReportData, ReportFormat, ReportFormatter};
+    use crate::report::sink::{FileSink, ReportOutput, ReportSink};
 
-    fn decode_info_from_packet(device: Device, packet: PacketExt) -> Result<PacketInfo, DecodeError> {
+    pub(crate) fn decode_info_from_packet(device: Device, packet: PacketExt) -> Result<PacketInfo, DecodeError> {
+        let frame_len = packet.len as usize;
         let (eth_header_result, eth_payload) = EthernetHeader::decode(packet.data);
         let eth_header = eth_header_result?;
 
-        return match eth_header.get_ether_type() {
+        let (src_address, dest_address, protocol, direction, ip_payload) = match eth_header.get_ether_type() {
             EtherType::Ipv4 => {
                 let (ipv4_header_result, ipv4_payload) = Ipv4Header::decode(eth_payload);
                 let ipv4_header = ipv4_header_result?;
                 let direction = get_direction_from_ipv4(ipv4_header.clone(), device.clone());
-
-                match ipv4_header.get_protocol() {
-                    Protocol::UDP => {
-                        let (udp_header_result, udp_payload) = UDPHeader::decode(ipv4_payload);
-                        let udp_header = udp_header_result?;
-                        let byte_transmitted = udp_payload.len();
-                        match direction {
-                            Direction::Received => {
-                                let address = ipv4_header.get_src_address();
-                                let port = udp_header.get_src_port();
-                                Ok(PacketInfo::new(address, port, Protocol::UDP, byte_transmitted, packet.timestamp))
-                            },
-                            Direction::Transmitted => {
-                                let address = ipv4_header.get_dest_address();
-                                let port = udp_header.get_dest_port();
-                                Ok(PacketInfo::new(address, port, Protocol::UDP, byte_transmitted, packet.timestamp))
-                            }
-                        }
-                    }
-                    Protocol::TCP => {
-                        let (tcp_header_result, tcp_payload) = TCPHeader::decode(ipv4_payload);
-                        let tcp_header = tcp_header_result?;
-                        let byte_transmitted = tcp_payload.len();
-                        match direction {
-                            Direction::Received => {
-                                let address = ipv4_header.get_src_address();
-                                let port = tcp_header.get_src_port();
-                                Ok(PacketInfo::new(address, port, Protocol::TCP, byte_transmitted, packet.timestamp))
-                            },
-                            Direction::Transmitted => {
-                                let address = ipv4_header.get_dest_address();
-                                let port = tcp_header.get_dest_port();
-                                Ok(PacketInfo::new(address, port, Protocol::TCP, byte_transmitted, packet.timestamp))
-                            }
-                        }
-                    }
-                    Protocol::Unknown => {
-                        Err(DecodeError { msg: format!("Unknown lev 4 protocol") })
-                    }
-                }
+                (ipv4_header.get_src_address(), ipv4_header.get_dest_address(), ipv4_header.get_protocol(), direction, ipv4_payload)
             },
             EtherType::Ipv6 => {
                 let (ipv6_header_result, ipv6_payload) = Ipv6Header::decode(eth_payload);
                 let ipv6_header = ipv6_header_result?;
                 let direction = get_direction_from_ipv6(ipv6_header.clone(), device.clone());
+                (ipv6_header.get_src_address(), ipv6_header.get_dest_address(), ipv6_header.get_protocol(), direction, ipv6_payload)
+            },
+            EtherType::ARP => return Err(DecodeError::unsupported(Layer::Network, "ARP".to_string(), "Cannot decode other level 3 header".to_string())),
+        };
 
-                match ipv6_header.get_protocol() {
-                    Protocol::UDP => {
-                        let (udp_header_result, udp_payload) = UDPHeader::decode(ipv6_payload);
-                        let udp_header = udp_header_result?;
-                        let byte_transmitted = udp_payload.len();
-                        match direction {
-                            Direction::Received => {
-                                let address = ipv6_header.get_src_address();
-                                let port = udp_header.get_src_port();
-                                Ok(PacketInfo::new(address, port, Protocol::UDP, byte_transmitted, packet.timestamp))
-                            },
-                            Direction::Transmitted => {
-                                let address = ipv6_header.get_dest_address();
-                                let port = udp_header.get_dest_port();
-                                Ok(PacketInfo::new(address, port, Protocol::UDP, byte_transmitted, packet.timestamp))
-                            }
-                        }
-                    },
-                    Protocol::TCP => {
-                        let (tcp_header_result, tcp_payload) = TCPHeader::decode(ipv6_payload);
-                        let tcp_header = tcp_header_result?;
-                        let byte_transmitted = tcp_payload.len();
-                        match direction {
-                            Direction::Received => {
-                                let address = ipv6_header.get_src_address();
-                                let port = tcp_header.get_src_port();
-                                Ok(PacketInfo::new(address, port, Protocol::TCP, byte_transmitted, packet.timestamp))
-                            },
-                            Direction::Transmitted => {
-                                let address = ipv6_header.get_dest_address();
-                                let port = tcp_header.get_dest_port();
-                                Ok(PacketInfo::new(address, port, Protocol::TCP, byte_transmitted, packet.timestamp))
-                            }
-                        }
-                    },
-                    Protocol::Unknown => {
-                        Err(DecodeError { msg: format!("Unknown lev 4 protocol") })
-                    }
-                }
+        return match protocol {
+            Protocol::UDP => {
+                let (udp_header_result, udp_payload) = UDPHeader::decode(ip_payload);
+                let udp_header = udp_header_result?;
+                let byte_transmitted = udp_payload.len();
+                let (address, port) = match direction {
+                    Direction::Received => (src_address, udp_header.get_src_port()),
+                    Direction::Transmitted => (dest_address, udp_header.get_dest_port())
+                };
+                Ok(PacketInfo::new(address, port, Protocol::UDP, byte_transmitted, packet.timestamp)
+                    .with_direction(direction)
+                    .with_frame_len(frame_len))
+            },
+            Protocol::TCP => {
+                let (tcp_header_result, tcp_payload) = TCPHeader::decode(ip_payload);
+                let tcp_header = tcp_header_result?;
+                let byte_transmitted = tcp_payload.len();
+                let (address, port, local_port) = match direction {
+                    Direction::Received => (src_address, tcp_header.get_src_port(), tcp_header.get_dest_port()),
+                    Direction::Transmitted => (dest_address, tcp_header.get_dest_port(), tcp_header.get_src_port())
+                };
+                let segment = TCPSegment::new(&tcp_header, local_port, byte_transmitted);
+                Ok(PacketInfo::new(address, port, Protocol::TCP, byte_transmitted, packet.timestamp)
+                    .with_direction(direction)
+                    .with_frame_len(frame_len)
+                    .with_tcp_segment(segment)
+                    .with_sni(parse_tls_sni(&tcp_payload)))
+            },
+            Protocol::Unknown => {
+                Err(DecodeError::unsupported(Layer::Transport, "Unknown".to_string(), "Unknown lev 4 protocol".to_string()))
             }
-            _ => Err(DecodeError { msg: "Cannot decode other level 3 header".parse().unwrap() }),
         };
     }
 
+    /// How often the statistics of the capture are read from libpcap.
+    const STATS_INTERVAL: Duration = Duration::from_secs(1);
+
+    /// How often the duration of the scanning is checked against the stop conditions.
+    const STOP_CHECK_INTERVAL: Duration = Duration::from_millis(100);
+
+    /// How often the flows are checked for the timeouts, also when no packet arrives.
+    const SWEEP_INTERVAL: Duration = Duration::from_secs(1);
+
+    /// Records that the packets captured on an interface can't be written in the capture file anymore. The scanning goes
+    /// on, but like when the final report can't be written, the error is returned at its end.
+    fn dump_failed(monitor: &StopMonitor, name: &str, error: std::io::Error) {
+        let error = format!("The packets of {} are no longer saved: {} ...", name, error);
+        println!("{}", error);
+        monitor.fail(error);
+    }
+
     /// it describes a packet, like it arrives from pcap, but it has the Send trait.
     #[derive(Debug, Clone, PartialEq)]
-    struct PacketExt {
+    pub struct PacketExt {
         data: Vec<u8>,
         timestamp: TimeVal,
+        len: u32,
+        interface: usize,
     }
 
     impl PacketExt {
-        pub fn new(data: &[u8], ts: libc::timeval) -> Self {
-            PacketExt{data: Vec::from(data), timestamp: TimeVal{sec: ts.tv_sec as u32, u_sec: ts.tv_usec as u32}}
+        pub fn new(data: &[u8], ts: libc::timeval, len: u32, precision: TimestampPrecision, interface: usize) -> Self {
+            // with the nanosecond precision libpcap puts the nanoseconds in tv_usec, the flows keep the microseconds
+            let u_sec = match precision {
+                TimestampPrecision::Micro => ts.tv_usec as u32,
+                TimestampPrecision::Nano => (ts.tv_usec / 1000) as u32
+            };
+            PacketExt{data: Vec::from(data), timestamp: TimeVal{sec: ts.tv_sec as u32, u_sec}, len, interface}
         }
+
+        /// The bytes captured, at most the snapshot length.
+        pub fn get_data(&self) -> &[u8] { return &self.data }
+        /// The time of the capture, in microseconds.
+        pub fn get_timestamp(&self) -> u64 { return self.timestamp.clone().into() }
+        /// The length of the frame on the wire.
+        pub fn get_len(&self) -> u32 { return self.len }
+        /// The position of the interface that captured the packet, among the ones sniffed.
+        pub fn get_interface(&self) -> usize { return self.interface }
     }
 
     /// the possible status of the application.
@@ -197,20 +212,95 @@ pub mod sniffer {
         result
     }
 
+    /// The settings that define how the collected data are presented in the report. They are shared with the threads
+    /// that write the reports, so the changes made while sniffing apply from the next report.
+    #[derive(Debug, Clone)]
+    struct ReportOptions {
+        top_n: usize,
+        sort_key: SortKey,
+        drop_threshold: f64,
+        display_filter: Option<DisplayFilter>,
+        interval_mode: IntervalMode,
+        interval_summary: bool,
+        format: ReportFormat,
+    }
+
+    impl Default for ReportOptions {
+        fn default() -> Self {
+            ReportOptions { top_n: 10, sort_key: SortKey::Bytes, drop_threshold: DEFAULT_DROP_THRESHOLD, display_filter: None,
+                interval_mode: IntervalMode::Cumulative, interval_summary: false, format: ReportFormat::Text }
+        }
+    }
+
     /// The sniffer struct allow to start the sniffing, define the file, the interface to be sniffed and allow interacting with the pcap interface.
     /// Example of use...
     pub struct Sniffer {
-        device: Option<pcap::Device>,
+        devices: Vec<pcap::Device>,
         status: Arc<(Mutex<RunStatus>, Condvar)>,
         filename: Option<String>,
         time_interval: u64,
-        hashmap: Arc<Mutex<HashMap<(String, u16), (Protocol, usize, u64, u64)>>>,
+        flow_table: Arc<ShardedFlowTable>,
+        report_options: Arc<RwLock<ReportOptions>>,
+        pipeline: Arc<PipelineStats>,
+        queue_capacity: usize,
+        overflow_policy: OverflowPolicy,
+        workers: usize,
+        filter: Option<String>,
+        failures: Arc<DecodeFailures>,
+        failure_samples: Option<String>,
+        capture_config: CaptureConfig,
+        dump_file: Option<String>,
+        dump_format: DumpFormat,
+        dump_rotation: Rotation,
+        report_rotation: Rotation,
+        report_file: Arc<Mutex<Option<ReportOutput>>>,
+        report_outputs: Arc<Mutex<Vec<ReportOutput>>>,
+        stop_conditions: StopConditions,
+        monitor: Arc<StopMonitor>,
+        threads: Arc<Mutex<Vec<JoinHandle<()>>>>,
+        sessions: Arc<Mutex<Vec<Session>>>,
+        session: u64,
+        events: Arc<EventBus>,
+        last_interval: Arc<Mutex<(FlowTable, DateTime<Local>)>>,
+    }
+
+    /// What is needed to write the final report, so that it can be saved also by the thread that checks the stop conditions.
+    #[derive(Clone)]
+    struct Reporter {
+        status: Arc<(Mutex<RunStatus>, Condvar)>,
+        filename: Option<String>,
+        time_interval: u64,
+        devices: Vec<Device>,
+        filter: Option<String>,
+        capture_config: CaptureConfig,
+        flow_table: Arc<ShardedFlowTable>,
+        pipeline: Arc<PipelineStats>,
+        failures: Arc<DecodeFailures>,
+        report_options: Arc<RwLock<ReportOptions>>,
+        failure_samples: Option<String>,
+        report_file: Arc<Mutex<Option<ReportOutput>>>,
+        report_outputs: Arc<Mutex<Vec<ReportOutput>>>,
+        monitor: Arc<StopMonitor>,
+        threads: Arc<Mutex<Vec<JoinHandle<()>>>>,
+        sessions: Arc<Mutex<Vec<Session>>>,
+        session: u64,
+        last_interval: Arc<Mutex<(FlowTable, DateTime<Local>)>>,
     }
 
     impl Sniffer {
         pub fn new() -> Self {
-            return Sniffer { device: None, status: Arc::new((Mutex::new(RunStatus::Stop), Condvar::new())),
-                filename: None, time_interval: 0, hashmap: Arc::new(Mutex::new(HashMap::new()))
+            let workers = thread::available_parallelism().map_or(1, |n| n.get()).min(DEFAULT_SHARDS);
+            return Sniffer { devices: Vec::new(), status: Arc::new((Mutex::new(RunStatus::Stop), Condvar::new())),
+                filename: None, time_interval: 0, flow_table: Arc::new(ShardedFlowTable::new(workers)),
+                report_options: Arc::new(RwLock::new(ReportOptions::default())), pipeline: Arc::new(PipelineStats::new()),
+                queue_capacity: DEFAULT_QUEUE_CAPACITY, overflow_policy: OverflowPolicy::Block, workers, filter: None,
+                failures: Arc::new(DecodeFailures::new()), failure_samples: None, capture_config: CaptureConfig::default(),
+                dump_file: None, dump_format: DumpFormat::Pcap,
+                dump_rotation: Rotation::default(), report_rotation: Rotation::default(), report_file: Arc::new(Mutex::new(None)),
+                report_outputs: Arc::new(Mutex::new(Vec::new())),
+                stop_conditions: StopConditions::default(), monitor: Arc::new(StopMonitor::new(StopConditions::default(), Instant::now())),
+                threads: Arc::new(Mutex::new(Vec::new())), sessions: Arc::new(Mutex::new(Vec::new())), session: 0,
+                events: Arc::new(EventBus::new()), last_interval: Arc::new(Mutex::new((FlowTable::new(), Local::now())))
             }
         }
 
@@ -226,18 +316,26 @@ pub mod sniffer {
         ///Sets the device that will be used during the sniffing process.
         ///The specified Device must be listed in the list of devices returned by list_devices().
         pub fn attach(&mut self, device: pcap::Device) -> Result<(), SnifferError> {
-            return match Sniffer::list_devices() {
-                Ok(devices) => {
-                    for dev in &devices {
-                        if dev.name == device.name {
-                            self.set_device(Some(device));
-                            return Ok(())
-                        }
-                    }
-                    return Err(SnifferError::UserError("The device selected is not in list ...".to_string()))
-                },
-                Err(error) => Err(error)
+            self.attach_all(vec![device])
+        }
+
+        ///Sets the devices that will be sniffed at the same time, feeding the same flow table.
+        ///The specified Devices must be listed in the list of devices returned by list_devices().
+        pub fn attach_all(&mut self, devices: Vec<pcap::Device>) -> Result<(), SnifferError> {
+            if devices.is_empty() {
+                return Err(SnifferError::UserError("You have to specify a device ...".to_string()));
+            }
+            let available = Sniffer::list_devices()?;
+            for (i, device) in devices.iter().enumerate() {
+                if !available.iter().any(|dev| dev.name == device.name) {
+                    return Err(SnifferError::UserError("The device selected is not in list ...".to_string()));
+                }
+                if devices[..i].iter().any(|dev| dev.name == device.name) {
+                    return Err(SnifferError::UserError(format!("The device {} is selected more than once ...", device.name)));
+                }
             }
+            self.set_devices(devices);
+            Ok(())
         }
 
         ///Starts the sniffing process.
@@ -247,78 +345,242 @@ pub mod sniffer {
             let status = self.get_status();
             return match &status {
                 RunStatus::Stop => {
-                    if self.get_filename().is_none() {
+                    if self.get_filename().is_none() && self.report_outputs.lock().unwrap().is_empty() {
                         return Err(SnifferError::UserError("File is null ...".to_string()));
                     }
-                    if self.get_device().is_none() {
+                    if self.get_devices().is_empty() {
                         return Err(SnifferError::UserError("You have to specify a device ...".to_string()));
                     }
 
+                    // the captures are opened here, so that a device or filter error is returned to the caller
+                    let mut captures = Vec::new();
+                    for device in self.get_devices() {
+                        let mut cap = self.capture_config.open(device.clone())?;
+                        if let Some(filter) = &self.filter {
+                            if let Err(error) = cap.filter(filter.as_str(), true) {
+                                return Err(SnifferError::UserError(format!("The capture filter '{}' can't be applied on {}: {} ...", filter, device.name, error)));
+                            }
+                        }
+                        captures.push(cap);
+                    }
+                    self.start_outputs()?;
+                    let dump = match &self.dump_file {
+                        Some(filename) => {
+                            let interfaces = self.get_devices().iter().zip(&captures)
+                                .map(|(device, cap)| DumpInterface::new(device.name.clone(), device.desc.clone(), cap.get_datalink().0 as u16))
+                                .collect();
+                            let header = DumpHeader::new(interfaces, self.capture_config.get_snaplen(),
+                                                         self.capture_config.get_precision() == TimestampPrecision::Nano, self.filter.clone());
+                            match DumpFile::create(filename, self.dump_format, header, self.dump_rotation) {
+                                Ok(writer) => Some(Arc::new(Mutex::new(writer))),
+                                Err(error) => return Err(SnifferError::UserError(format!("The capture file '{}' can't be created: {} ...", filename, error)))
+                            }
+                        },
+                        None => None
+                    };
+
+                    // every scanning collects its data in new tables, the ones of the previous sessions stay in the history
+                    self.clear_data();
+                    self.session += 1;
+                    let settings = SessionSettings::new(self.get_devices().iter().map(|device| device.name.clone()).collect(),
+                                                        self.get_filename(), self.time_interval, self.filter.clone(),
+                                                        self.capture_config.clone(), self.stop_conditions);
+                    self.sessions.lock().unwrap().push(Session::new(self.session, settings, self.flow_table.clone(),
+                                                                    self.pipeline.clone(), self.failures.clone()));
+                    self.monitor = Arc::new(StopMonitor::new(self.stop_conditions, Instant::now()));
                     self.set_status(RunStatus::Running);
 
-                    let device = self.get_device().clone().unwrap();
-                    print!("Running on {}", display_device(device.clone()));
-                    let (tx, rx) = channel();
+                    self.get_devices().iter().for_each(|device| print!("Running on {}", display_device(device.clone())));
+                    // every decoder has its own bounded queue: when it can't keep up, the overflow policy decides what to do
+                    let capacity = (self.queue_capacity / self.workers).max(1);
+                    let (senders, receivers): (Vec<_>, Vec<_>) = (0..self.workers).map(|_| sync_channel(capacity)).unzip();
                     let tuple = self.status.clone();
+                    let flow_table = self.flow_table.clone();
+                    let pipeline = self.pipeline.clone();
+                    let policy = self.overflow_policy;
+                    let precision = self.capture_config.get_precision();
+
+                    // every interface has its own capture thread, and all of them feed the same decoders
+                    for (interface, mut cap) in captures.into_iter().enumerate() {
+                        let device = self.get_devices()[interface].clone();
+                        let name = device.name.clone();
+                        let tuple = tuple.clone();
+                        let flow_table = flow_table.clone();
+                        let arrivals = flow_table.arrivals();
+                        let pipeline = pipeline.clone();
+                        let senders = senders.clone();
+                        let mut dump = dump.clone();
+                        let monitor = self.monitor.clone();
+                        let sniffer_thread = thread::spawn(move || {
+                            let mut last_stats = Instant::now();
+                            loop {
+                                // the status is copied, so the lock isn't held while reading the statistics and writing the file
+                                let status = tuple.0.lock().unwrap().clone();
+
+                                // the drops of the kernel and of the interface are known only to libpcap
+                                if last_stats.elapsed() >= STATS_INTERVAL || status == RunStatus::Stop {
+                                    if let Ok(stats) = cap.stats() {
+                                        pipeline.capture_stats(&name, stats.received, stats.dropped, stats.if_dropped);
+                                    }
+                                    // the capture file can be opened while the scanning goes on, and it is rotated even without traffic
+                                    let saved = dump.as_ref().map(|writer| {
+                                        let mut writer = writer.lock().unwrap();
+                                        writer.rotate_if_due().and_then(|_| writer.flush())
+                                    });
+                                    if let Some(Err(error)) = saved {
+                                        dump_failed(&monitor, &name, error);
+                                        dump = None;
+                                    }
+                                    last_stats = Instant::now();
+                                }
+
+                                match &status {
+                                    RunStatus::Running => {
+                                        match cap.next_packet() {
+                                            Ok(packet) => {
+                                                // after a stop condition the packets are discarded until the scanning stops
+                                                if !monitor.admit(packet.header.len) {
+                                                    continue
+                                                }
+                                                let ts = packet.header.ts;
+                                                let saved = dump.as_ref().map(|writer| writer.lock().unwrap()
+                                                    .write_packet(interface, ts.tv_sec as u64, ts.tv_usec as u32, packet.data, packet.header.len));
+                                                if let Some(Err(error)) = saved {
+                                                    dump_failed(&monitor, &name, error);
+                                                    dump = None;
+                                                }
+                                                // every decoder owns the shard of the flows it receives, so the packets of a flow are
+                                                // processed in order and the shards are not contended
+                                                let worker = flow_key(packet.data, &device).map_or(0, |key| flow_table.shard_of(&key)) % senders.len();
+                                                let packet = PacketExt::new(packet.data, packet.header.ts, packet.header.len, precision, interface);
+                                                let ts: u64 = packet.timestamp.clone().into();
+                                                match pipeline.send(&senders[worker], packet, policy) {
+                                                    // only the packets given to the decoders count in the inter-arrival times
+                                                    Delivery::Queued => arrivals.lock().unwrap().add(ts),
+                                                    Delivery::Dropped => {},
+                                                    Delivery::Disconnected => break
+                                                }
+                                                continue;
+                                            },
+                                            // the read timeout only gives the chance to check the status
+                                            Err(pcap::Error::TimeoutExpired) => {},
+                                            Err(error) => {
+                                                let error = format!("The capture on {} has failed: {} ...", name, SnifferError::PcapError(error));
+                                                println!("{}", error);
+                                                monitor.fail(error);
+                                                break;
+                                            }
+                                        }
+                                    },
+                                    RunStatus::Wait => {
+                                        let _s = tuple.1.wait_while(tuple.0.lock().unwrap(), |status| { *status == RunStatus::Wait }).unwrap();
+                                    },
+                                    RunStatus::Stop => { break; }
+                                    RunStatus::Error(e) => { println!("{}", e) }
+                                }
+                                thread::sleep(Duration::from_micros(100));
+                            };
+                        });
+                        self.threads.lock().unwrap().push(sniffer_thread);
+                    }
+                    // the decoders stop when all the capture threads have dropped their senders
+                    drop(senders);
+
+                    // the limits on the packets and the bytes are checked by the capture threads, the duration here
+                    if self.stop_conditions.is_enabled() {
+                        let reporter = self.reporter();
+                        let stop_thread = thread::spawn(move || {
+                            loop {
+                                let mut _s = reporter.status.0.lock().unwrap();
+                                let status = (*_s).clone();
+                                match &status {
+                                    RunStatus::Running => {
+                                        drop(_s);
+                                        if reporter.monitor.check_duration(Instant::now()) {
+                                            reporter.complete();
+                                            break;
+                                        }
+                                    },
+                                    RunStatus::Wait => {
+                                        _s = reporter.status.1.wait_while(_s, |status| { *status == RunStatus::Wait }).unwrap();
+                                    },
+                                    _ => { break; }
+                                }
+                                thread::sleep(STOP_CHECK_INTERVAL);
+                            }
+                        });
+                        self.threads.lock().unwrap().push(stop_thread);
+                    }
 
-                    let _sniffer_thread = thread::spawn(move || {
-                        let mut cap = Capture::from_device(device).unwrap().promisc(true).open().unwrap();
+                    // the decoders check the timeouts only when the packets arrive, this closes the flows also without traffic
+                    let events = self.events.clone();
+                    let sweep_thread = thread::spawn(move || {
+                        let mut last_sweep = Instant::now();
                         loop {
                             let mut _s = tuple.0.lock().unwrap();
                             let status = (*_s).clone();
-
                             match &status {
                                 RunStatus::Running => {
                                     drop(_s);
-                                    match cap.next_packet() {
-                                        Ok(packet) => {
-                                            let res = tx.send(PacketExt::new(packet.data, packet.header.ts));
-                                            match res {
-                                                Ok(()) => continue,
-                                                Err(error) => SnifferError::UserError(error.to_string())
-                                            };
-                                        },
-                                        Err(error) => {
-                                            SnifferError::PcapError(error);
+                                    if last_sweep.elapsed() >= SWEEP_INTERVAL {
+                                        let now = Local::now().timestamp_micros().max(0) as u64;
+                                        for event in flow_table.expire(now) {
+                                            events.publish(EventKind::Flow, || SnifferEvent::from(event));
                                         }
+                                        last_sweep = Instant::now();
                                     }
                                 },
                                 RunStatus::Wait => {
                                     _s = tuple.1.wait_while(_s, |status| { *status == RunStatus::Wait }).unwrap();
                                 },
-                                RunStatus::Stop => { break; }
-                                RunStatus::Error(e) => { println!("{}", e) }
+                                _ => { break; }
                             }
-                            thread::sleep(Duration::from_micros(100));
-                        };
+                            thread::sleep(STOP_CHECK_INTERVAL);
+                        }
                     });
+                    self.threads.lock().unwrap().push(sweep_thread);
+
+                    for (shard, rx) in receivers.into_iter().enumerate() {
+                        let devices = self.get_devices().clone();
+                        let events = self.events.clone();
+                        let flow_table = self.flow_table.clone();
+                        let pipeline = self.pipeline.clone();
+                        let failures = self.failures.clone();
+                        let report_options = self.report_options.clone();
 
-                    let device = self.get_device().clone().unwrap();
-                    let hashmap = self.get_hashmap().clone();
-
-                    let _decoder_thread = thread::spawn(move || {
-                        while let Ok(packet) = rx.recv() {
-                            match decode_info_from_packet(device.clone(), packet) {
-                                Ok(info) => {
-                                    let mut hm = hashmap.lock().unwrap();
-                                    let existing_pkt = hm.get(&(info.get_address(), info.get_port()));
-                                    match existing_pkt {
-                                        None => {
-                                            hm.insert((info.get_address(), info.get_port()),
-                                                      (info.get_protocol(), info.get_byte_transmitted(), info.get_time_stamp().into(), info.get_time_stamp().into()));
-                                        },
-                                        value => {
-                                            let bytes = info.get_byte_transmitted() + value.unwrap().clone().1;
-                                            let first_time = value.unwrap().clone().2;
-                                            hm.insert((info.get_address(), info.get_port()),
-                                                      (info.get_protocol(), bytes, first_time, info.get_time_stamp().into()));
+                        // the decoders aggregate every packet in the shard they own, where the capture threads sent its flow
+                        let decoder_thread = thread::spawn(move || {
+                            while let Ok(packet) = rx.recv() {
+                                let frame_len = packet.len;
+                                let sample = match failures.is_sampling() {
+                                    true => Some(FailureSample::new(packet.timestamp.clone().into(), frame_len, packet.data.clone())),
+                                    false => None
+                                };
+                                // the direction is computed against the addresses of the interface that captured the packet
+                                let device = devices[packet.interface].clone();
+                                let name = device.name.clone();
+                                match decode_info_from_packet(device, packet).map(|info| info.with_interface(name)) {
+                                    Ok(info) => {
+                                        // the subscribers see the flows changed by the packet before the packet itself
+                                        for event in flow_table.update_shard(shard, &info) {
+                                            events.publish(EventKind::Flow, || SnifferEvent::from(event));
                                         }
+                                        pipeline.processed(true);
+                                        // the display filter, if any, selects the packets delivered like the flows of the report
+                                        if events.wants(EventKind::Packet) && report_options.read().unwrap().display_filter.as_ref()
+                                            .is_none_or(|filter| filter.matches_packet(&info)) {
+                                            events.publish(EventKind::Packet, || SnifferEvent::Packet(info));
+                                        }
+                                    },
+                                    Err(error) => {
+                                        failures.add(&error, frame_len, sample);
+                                        pipeline.processed(false);
                                     }
-                                },
-                                Err(_) => {}
+                                }
                             }
-                        }
-                    });
+                        });
+                        self.threads.lock().unwrap().push(decoder_thread);
+                    }
                     Ok(())
                 },
                 RunStatus::Error(error) => {
@@ -345,36 +607,32 @@ pub mod sniffer {
             println!("Saving after {} {} ...", Colour::Blue.paint(self.time_interval.to_string()), Colour::Blue.paint("sec".to_string()));
 
             let tuple = self.status.clone();
-            let hashmap = self.get_hashmap().clone();
+            let flow_table = self.flow_table.clone();
             let interval = self.get_time_interval().clone();
-            let device = self.get_device().clone().unwrap();
-            let file = match self.get_filename() {
-                Some(filename) => filename,
-                None => return Err(SnifferError::UserError("File is not set".to_string()))
-            };
+            let reporter = self.reporter();
+            let events = self.events.clone();
+            *self.last_interval.lock().unwrap() = (flow_table.snapshot(), Local::now());
 
-            let _sleep_thread = thread::spawn(move || {
-                let mut count = 0;
+            let sleep_thread = thread::spawn(move || {
                 loop {
                     let mut _s = tuple.0.lock().unwrap();
                     let status = (*_s).clone();
 
                     match &status {
                         RunStatus::Running => {
-                            drop(_s);
-                            let mut heading = String::new();
-                            thread::sleep(Duration::from_secs(interval.clone()));
-                            if count == 0 {
-                                heading = Sniffer::heading(&device.clone());
+                            // the wait ends early when the scanning is paused or stopped, without writing a report
+                            let (_s, wait) = tuple.1.wait_timeout_while(_s, Duration::from_secs(interval.clone()), |status| {
+                                *status == RunStatus::Running
+                            }).unwrap();
+                            if !wait.timed_out() {
+                                continue
                             }
-                            let center = Sniffer::center(hashmap.clone());
-                            heading.push_str(center.as_str());
-                            let mut file = OpenOptions::new().append(true).open(file.clone()).unwrap();
-                            match file.write(heading.as_bytes()) {
-                                Ok(_) => {},
-                                Err(_) => { break; }
+                            drop(_s);
+                            if let Err(error) = reporter.section(false) {
+                                println!("The periodic report can't be written: {}", error);
+                                break;
                             }
-                            count += 1;
+                            events.publish(EventKind::Snapshot, || SnifferEvent::Snapshot(flow_table.snapshot()));
                         },
                         RunStatus::Wait => {
                             _s = tuple.1.wait_while(_s, |status| { *status == RunStatus::Wait }).unwrap();
@@ -385,6 +643,7 @@ pub mod sniffer {
                 }
                 thread::sleep(Duration::from_micros(100));
             });
+            self.threads.lock().unwrap().push(sleep_thread);
             Ok(())
         }
 
@@ -395,7 +654,9 @@ pub mod sniffer {
             match &status {
                 RunStatus::Error(error) => Err(SnifferError::UserError(error.to_string())),
                 RunStatus::Running => {
+                    self.monitor.pause(Instant::now());
                     self.set_status(RunStatus::Wait);
+                    self.status.1.notify_all();
                     Ok(())
                 },
                 RunStatus::Stop => { return Err(SnifferError::UserWarning("There is no scanning in execution ...".to_string())); },
@@ -411,6 +672,7 @@ pub mod sniffer {
             match &status {
                 RunStatus::Error(error) => Err(SnifferError::UserError(error.to_string())),
                 RunStatus::Wait => {
+                    self.monitor.resume(Instant::now());
                     self.set_status(RunStatus::Running);
                     self.status.1.notify_all();
                     Ok(())
@@ -420,88 +682,185 @@ pub mod sniffer {
             }
         }
 
-        fn heading(device: &Device) -> String {
-            let mut string = "Scanning on: \n\t- Interface ".to_string();
-            string.push_str(device.name.as_str());
-            string.push_str("\nAddresses: ");
-            device.addresses.iter().for_each(|a| {
-                string.push_str("\n\t- ");
-                string.push_str(a.addr.to_string().as_str());
-            });
-            return string
-        }
-
-        fn center(hashmap: Arc<Mutex<HashMap<(String, u16), (Protocol, usize, u64, u64)>>>) -> String {
-            let mut center = "\n\nScanning: \n\t- Update Time: ".to_string();
-            center.push_str(Local::now().to_string().as_str());
-            let mut table = Table::new();
-            table.add_row(row!["IP Address", "Port", "Protocol", "Bytes Transmitted", "First Timestamp", "Last Timestamp"]);
-            let hm = hashmap.clone();
-            for (key, value) in hm.lock().unwrap().iter() {
-                let first = pkt_parser::TimeVal::from(value.2);
-                let last = pkt_parser::TimeVal::from(value.3);
-                table.add_row(Row::new(vec![
-                    Cell::new(key.0.as_str()),
-                    Cell::new(key.1.to_string().as_str()),
-                    Cell::new(value.0.to_string().as_str()),
-                    Cell::new(value.1.to_string().as_str()),
-                    Cell::new(format!("{}", Local.timestamp_opt(first.sec as i64, first.u_sec * 1000).unwrap().format("%H:%M:%S %f ns")).as_str()),
-                    Cell::new(format!("{}", Local.timestamp_opt(last.sec as i64, last.u_sec * 1000).unwrap().format("%H:%M:%S %f ns")).as_str()),
-                ]));
-            }
-            center.push_str("\n");
-            center.push_str(table.to_string().as_str());
-            return center
-        }
-
         ///Saves in the specified file a report of the collected data.
         ///This function works only if the status is either RunStatus::Wait or RunStatus::Running.
         pub fn save_report(&self) -> Result<String, SnifferError> {
-            let status = self.get_status();
-            match &status {
-                RunStatus::Error(error) => Err(SnifferError::UserError(error.to_string())),
-                RunStatus::Stop => { Err(SnifferError::UserWarning("The scanning is already stopped ...".to_string())) },
-                _ => {
-                    if self.get_filename().is_none() {
-                        Err(SnifferError::UserError("The file doesn't exist ...".to_string()))
-                    } else {
-                        let write;
-                        let center;
-                        if self.get_time_interval() == 0 {
-                            let mut file = match OpenOptions::new().write(true).open(self.get_filename().unwrap()) {
-                                Ok(file) => file,
-                                Err(error) => return Err(SnifferError::UserError(error.to_string()))
-                            };
-                            match file.rewind() {
-                                Ok(_) => (),
-                                Err(error) =>  return Err(SnifferError::UserError(error.to_string()))
-                            };
+            self.reporter().save()
+        }
 
-                            let mut heading = Sniffer::heading(&self.device.as_ref().unwrap().clone());
-                            center = Sniffer::center(self.get_hashmap().clone());
-                            heading.push_str(center.as_str());
-
-                            write = file.write(heading.as_bytes());
-                            //println!("{:?}", write);
-                        } else {
-                            center = Sniffer::center(self.get_hashmap().clone());
-                            let mut file = match OpenOptions::new().append(true).open(self.get_filename().unwrap()) {
-                                Ok(file) => file,
-                                Err(error) => return Err(SnifferError::UserError(error.to_string()))
-                            };
-                            write = file.write(center.as_bytes());
-                            println!("{:?}", write);
-                        }
-                        return match write {
-                            Ok(_) => {
-                                self.set_status(RunStatus::Stop);
-                                Ok("The report has been saved and the scanning has been stopped ...".to_string())
-                            },
-                            Err(error) => Err(SnifferError::UserError(error.to_string()))
-                        }
-                    }
+        ///Waits until the scanning ends, because a stop condition has been reached or the report has been saved, and
+        ///returns the reason. It fails if the final report couldn't be written.
+        pub fn wait_for_completion(&self) -> Result<StopReason, SnifferError> {
+            let status = self.status.1.wait_while(self.status.0.lock().unwrap(), |status| {
+                *status == RunStatus::Running || *status == RunStatus::Wait || self.monitor.is_stopping()
+            }).unwrap();
+            if let RunStatus::Error(error) = &*status {
+                return Err(SnifferError::UserError(error.to_string()));
+            }
+            if let Some(error) = self.monitor.get_failure() {
+                return Err(SnifferError::UserError(error));
+            }
+            match self.monitor.get_reason() {
+                Some(reason) => Ok(reason),
+                None => Err(SnifferError::UserWarning("There is no scanning in execution ...".to_string()))
+            }
+        }
+
+        fn reporter(&self) -> Reporter {
+            Reporter { status: self.status.clone(), filename: self.get_filename(), time_interval: self.time_interval,
+                devices: self.get_devices().clone(), filter: self.filter.clone(), capture_config: self.capture_config.clone(),
+                flow_table: self.flow_table.clone(), pipeline: self.pipeline.clone(), failures: self.failures.clone(),
+                report_options: self.report_options.clone(), failure_samples: self.failure_samples.clone(),
+                report_file: self.report_file.clone(), report_outputs: self.report_outputs.clone(), monitor: self.monitor.clone(), threads: self.threads.clone(),
+                sessions: self.sessions.clone(), session: self.session, last_interval: self.last_interval.clone() }
+        }
+
+        ///Returns the packets that couldn't be decoded, grouped by layer and reason, from the most frequent.
+        pub fn get_unclassified_traffic(&self) -> Vec<UnclassifiedTraffic> {
+            self.failures.snapshot()
+        }
+
+        ///Returns the file in which the captured packets are saved, if any, and its format.
+        pub fn get_dump_file(&self) -> Option<(String, DumpFormat)> {
+            self.dump_file.clone().map(|filename| (filename, self.dump_format))
+        }
+
+        ///Sets the file in which the raw packets are saved from the next scanning, in the pcap or pcapng format, so
+        ///that they can be opened with Wireshark. None disables the capture file.
+        pub fn set_dump_file(&mut self, filename: Option<String>, format: DumpFormat) {
+            self.dump_file = filename;
+            self.dump_format = format;
+        }
+
+        ///Returns the rotation of the file of the raw packets.
+        pub fn get_dump_rotation(&self) -> Rotation {
+            self.dump_rotation
+        }
+
+        ///Sets when the file of the raw packets is closed to start a new one, named with the time, and how many of
+        ///these files are kept, like tcpdump -C -G -W.
+        pub fn set_dump_rotation(&mut self, rotation: Rotation) {
+            self.dump_rotation = rotation;
+        }
+
+        ///Returns the rotation of the reports written by run_with_interval().
+        pub fn get_report_rotation(&self) -> Rotation {
+            self.report_rotation
+        }
+
+        ///Sets when the periodic reports of run_with_interval() move to a new file, named with the time, instead of
+        ///being appended to the same file, and how many of these files are kept.
+        pub fn set_report_rotation(&mut self, rotation: Rotation) {
+            self.report_rotation = rotation;
+        }
+
+        ///Returns the pcap file in which the samples of the frames that couldn't be decoded are saved, if any.
+        pub fn get_failure_samples(&self) -> Option<String> {
+            self.failure_samples.clone()
+        }
+
+        ///Sets the pcap file in which a few frames for every reason of the decode failures are saved, together with the report.
+        ///None disables the samples.
+        pub fn set_failure_samples(&mut self, filename: Option<String>) {
+            self.failures.set_sampling(filename.is_some());
+            self.failure_samples = filename;
+        }
+
+        ///Returns the BPF expression used to filter the captured packets, if any.
+        pub fn get_filter(&self) -> Option<String> {
+            self.filter.clone()
+        }
+
+        ///Sets the BPF expression (e.g. "not port 22") used to filter the packets in the kernel, from the next scanning.
+        ///The expression is compiled to check its syntax, and an empty expression removes the filter.
+        pub fn set_filter(&mut self, filter: &str) -> Result<(), SnifferError> {
+            let filter = filter.trim();
+            if filter.is_empty() {
+                self.filter = None;
+                return Ok(())
+            }
+            let capture = Capture::dead(Linktype::ETHERNET).map_err(SnifferError::PcapError)?;
+            match capture.compile(filter, true) {
+                Ok(_) => {
+                    self.filter = Some(filter.to_string());
+                    Ok(())
                 },
+                Err(error) => Err(SnifferError::UserError(format!("Invalid capture filter '{}': {} ...", filter, error)))
+            }
+        }
+
+        ///Returns the conditions that stop the scanning by themselves.
+        pub fn get_stop_conditions(&self) -> StopConditions {
+            return self.stop_conditions
+        }
+
+        ///Sets the number of packets, the duration and the number of bytes after which the scanning is stopped and
+        ///the report is saved, as if the user had called save_report. The pauses don't count in the duration.
+        ///It works only if the status is RunStatus::Stop.
+        pub fn set_stop_conditions(&mut self, stop_conditions: StopConditions) -> Result<(), SnifferError> {
+            if self.get_status() != RunStatus::Stop {
+                return Err(SnifferError::UserWarning("The stop conditions can't be changed while sniffing ...".to_string()));
+            }
+            self.stop_conditions = stop_conditions;
+            Ok(())
+        }
+
+        ///Returns the settings used to open the capture.
+        pub fn get_capture_config(&self) -> CaptureConfig {
+            self.capture_config.clone()
+        }
+
+        ///Sets the settings used to open the capture (snapshot length, buffer size, read timeout, immediate and
+        ///promiscuous mode, timestamp precision). It works only if the status is RunStatus::Stop.
+        pub fn set_capture_config(&mut self, capture_config: CaptureConfig) -> Result<(), SnifferError> {
+            if self.get_status() != RunStatus::Stop {
+                return Err(SnifferError::UserWarning("The capture settings can't be changed while sniffing ...".to_string()));
+            }
+            self.capture_config = capture_config;
+            Ok(())
+        }
+
+        ///Returns the display filter applied to the flows of the report, if any.
+        pub fn get_display_filter(&self) -> Option<String> {
+            self.report_options.read().unwrap().display_filter.as_ref().map(|filter| filter.get_text())
+        }
+
+        ///Sets the display filter (e.g. "tcp and flow.bytes > 1MB") that selects the flows shown in the report and the
+        ///packets delivered to the subscribers of EventKind::Packet.
+        ///Unlike the capture filter, it can be changed while sniffing, and an empty expression removes it.
+        pub fn set_display_filter(&mut self, filter: &str) -> Result<(), SnifferError> {
+            if filter.trim().is_empty() {
+                self.report_options.write().unwrap().display_filter = None;
+                return Ok(())
+            }
+            self.report_options.write().unwrap().display_filter = Some(Sniffer::parse_display_filter(filter)?);
+            Ok(())
+        }
+
+        fn parse_display_filter(filter: &str) -> Result<DisplayFilter, SnifferError> {
+            DisplayFilter::parse(filter).map_err(|error| SnifferError::UserError(
+                format!("Invalid display filter: {}\n{} ...", error, error.highlight(filter))))
+        }
+
+        ///Returns a table with the top flows collected so far that match the given display filter, or the one of
+        ///the report when no filter is given, without stopping the scanning.
+        pub fn live_view(&self, filter: Option<&str>) -> Result<String, SnifferError> {
+            let filter = match filter {
+                Some(filter) if !filter.trim().is_empty() => Some(Sniffer::parse_display_filter(filter)?),
+                _ => self.report_options.read().unwrap().display_filter.clone()
+            };
+            let mut flows = self.flow_table.snapshot();
+            if let Some(filter) = &filter {
+                flows = flows.filtered(|key, stats| filter.matches_flow(key, stats));
+            }
+            let mut view = format!("Flows: {}, Packets: {}, Bytes: {}", flows.len(), flows.get_total_packets(), flows.get_total_bytes());
+            if let Some(filter) = &filter {
+                view.push_str(format!(" (display filter: {})", filter).as_str());
             }
+            view.push('\n');
+            let (top_n, sort_key) = (self.get_top_n(), self.get_sort_key());
+            view.push_str(text::flows(flows.top_flows(top_n, sort_key)).as_str());
+            Ok(view)
         }
 
         ///Returns the time interval that has been set.
@@ -516,6 +875,228 @@ pub mod sniffer {
             self.time_interval = time_interval;
         }
 
+        ///Returns the number of entries shown in the summary sections of the report.
+        pub fn get_top_n(&self) -> usize {
+            self.report_options.read().unwrap().top_n
+        }
+
+        ///Sets the number of hosts, ports and conversations shown in the summary sections of the report.
+        pub fn set_top_n(&mut self, top_n: usize) -> Result<(), SnifferError> {
+            if top_n == 0 {
+                return Err(SnifferError::UserError("The number of entries of the summary must be positive ...".to_string()));
+            }
+            self.report_options.write().unwrap().top_n = top_n;
+            Ok(())
+        }
+
+        ///Returns the percentage of lost packets above which the report is flagged as unreliable.
+        pub fn get_drop_threshold(&self) -> f64 {
+            self.report_options.read().unwrap().drop_threshold
+        }
+
+        ///Sets the percentage of packets, lost by the interface, the kernel or the queue, above which the report
+        ///warns that it can't be trusted.
+        pub fn set_drop_threshold(&mut self, drop_threshold: f64) -> Result<(), SnifferError> {
+            if !(0.0..=100.0).contains(&drop_threshold) {
+                return Err(SnifferError::UserError("The drop threshold must be a percentage between 0 and 100 ...".to_string()));
+            }
+            self.report_options.write().unwrap().drop_threshold = drop_threshold;
+            Ok(())
+        }
+
+        ///Returns the key used to sort the flows and the summary sections of the report.
+        pub fn get_sort_key(&self) -> SortKey {
+            self.report_options.read().unwrap().sort_key
+        }
+
+        ///Sets the key used to sort the flows and the summary sections of the report.
+        pub fn set_sort_key(&mut self, sort_key: SortKey) {
+            self.report_options.write().unwrap().sort_key = sort_key;
+        }
+
+        ///Returns what the sections of the periodic reports contain.
+        pub fn get_interval_mode(&self) -> IntervalMode {
+            self.report_options.read().unwrap().interval_mode
+        }
+
+        ///Returns true if the sections of the periodic reports in delta mode end with the summary of the whole scanning.
+        pub fn get_interval_summary(&self) -> bool {
+            self.report_options.read().unwrap().interval_summary
+        }
+
+        ///Sets whether every section of the periodic reports contains the whole data collected so far or only the
+        ///traffic of its interval, in which case the summary of the whole scanning can be added after it.
+        pub fn set_interval_mode(&mut self, interval_mode: IntervalMode, interval_summary: bool) {
+            let mut options = self.report_options.write().unwrap();
+            options.interval_mode = interval_mode;
+            options.interval_summary = interval_summary;
+        }
+
+        ///Returns the format of the reports.
+        pub fn get_report_format(&self) -> ReportFormat {
+            self.report_options.read().unwrap().format
+        }
+
+        ///Sets the format of the report file: the text tables, JSON, CSV or a static HTML page. With a time interval,
+        ///every periodic report is a line of JSON (NDJSON) or adds the rows of its flows to the CSV, while the HTML page
+        ///is rewritten.
+        pub fn set_report_format(&mut self, format: ReportFormat) {
+            self.report_options.write().unwrap().format = format;
+        }
+
+        ///Adds an output of the reports, written together with the report file (if any) from the next scanning: the
+        ///formatter writes the reports that the sink receives. A scanning can then run also without a report file.
+        pub fn add_report_output(&mut self, formatter: Box<dyn ReportFormatter>, sink: Box<dyn ReportSink>) {
+            self.report_outputs.lock().unwrap().push(ReportOutput::new(formatter, sink));
+        }
+
+        ///Returns the number of the outputs added besides the report file.
+        pub fn get_report_outputs(&self) -> usize {
+            self.report_outputs.lock().unwrap().len()
+        }
+
+        ///Removes all the outputs added, the reports are then written only in the report file.
+        pub fn clear_report_outputs(&mut self) {
+            self.report_outputs.lock().unwrap().clear();
+        }
+
+        ///Returns the width of the throughput buckets, in seconds.
+        pub fn get_bucket_width(&self) -> u64 {
+            self.flow_table.setting(|table| table.get_bucket_width())
+        }
+
+        ///Sets the width of the buckets, in seconds, in which packets and bytes are collected over time.
+        ///It works only if the status is RunStatus::Stop.
+        pub fn set_bucket_width(&mut self, bucket_width: u64) -> Result<(), SnifferError> {
+            if bucket_width == 0 {
+                return Err(SnifferError::UserError("The width of the throughput buckets must be positive ...".to_string()));
+            }
+            if self.get_status() != RunStatus::Stop {
+                return Err(SnifferError::UserWarning("The width of the throughput buckets can't be changed while sniffing ...".to_string()));
+            }
+            self.flow_table.configure(|table| table.set_bucket_width(bucket_width));
+            Ok(())
+        }
+
+        ///Sets the time, in seconds, after which a flow without packets is closed and moved to the finished flows.
+        ///Zero disables the timeout.
+        pub fn set_idle_timeout(&mut self, idle_timeout: u64) {
+            self.flow_table.configure(|table| table.set_idle_timeout(idle_timeout));
+        }
+
+        ///Returns the idle timeout, in seconds.
+        pub fn get_idle_timeout(&self) -> u64 {
+            self.flow_table.setting(|table| table.get_idle_timeout())
+        }
+
+        ///Sets the maximum duration of a flow, in seconds, after which it is closed and moved to the finished flows.
+        ///Zero disables the timeout.
+        pub fn set_active_timeout(&mut self, active_timeout: u64) {
+            self.flow_table.configure(|table| table.set_active_timeout(active_timeout));
+        }
+
+        ///Returns the active timeout, in seconds.
+        pub fn get_active_timeout(&self) -> u64 {
+            self.flow_table.setting(|table| table.get_active_timeout())
+        }
+
+        ///Sets the maximum number of live flows and the policy used to evict them when the limit is reached.
+        ///Zero means no limit.
+        pub fn set_max_flows(&mut self, max_flows: usize, eviction_policy: EvictionPolicy) {
+            self.flow_table.set_max_flows(max_flows);
+            self.flow_table.configure(|table| table.set_eviction_policy(eviction_policy));
+        }
+
+        ///Returns the maximum number of live flows.
+        pub fn get_max_flows(&self) -> usize {
+            self.flow_table.get_max_flows()
+        }
+
+        ///Returns the policy used to evict the flows when the table is full.
+        pub fn get_eviction_policy(&self) -> EvictionPolicy {
+            self.flow_table.setting(|table| table.get_eviction_policy())
+        }
+
+        ///Returns the flows that have been closed by the timeouts or evicted, from the oldest to the newest.
+        pub fn get_finished_flows(&self) -> Vec<FinishedFlow> {
+            self.flow_table.snapshot().get_finished_flows()
+        }
+
+        ///Returns the throughput of the whole scanning, divided in buckets.
+        pub fn get_throughput(&self) -> Throughput {
+            self.flow_table.snapshot().get_throughput().clone()
+        }
+
+        ///Returns the throughput of the traffic exchanged with the given remote host, if any.
+        pub fn get_host_throughput(&self, host: &str) -> Option<Throughput> {
+            self.flow_table.snapshot().get_host_throughput(host)
+        }
+
+        ///Returns the distribution of the sizes of the frames collected so far.
+        pub fn get_frame_sizes(&self) -> Histogram {
+            self.flow_table.snapshot().get_frame_sizes().clone()
+        }
+
+        ///Returns the distribution of the time between two consecutive packets collected so far.
+        pub fn get_inter_arrival_times(&self) -> Histogram {
+            self.flow_table.snapshot().get_inter_arrival_times().clone()
+        }
+
+        ///Returns the maximum number of captured packets that can wait to be decoded.
+        pub fn get_queue_capacity(&self) -> usize {
+            self.queue_capacity
+        }
+
+        ///Sets the maximum number of captured packets that can wait to be decoded.
+        ///It works only if the status is RunStatus::Stop.
+        pub fn set_queue_capacity(&mut self, queue_capacity: usize) -> Result<(), SnifferError> {
+            if queue_capacity == 0 {
+                return Err(SnifferError::UserError("The capacity of the queue must be positive ...".to_string()));
+            }
+            if self.get_status() != RunStatus::Stop {
+                return Err(SnifferError::UserWarning("The capacity of the queue can't be changed while sniffing ...".to_string()));
+            }
+            self.queue_capacity = queue_capacity;
+            Ok(())
+        }
+
+        ///Returns what happens to the captured packets when the queue is full.
+        pub fn get_overflow_policy(&self) -> OverflowPolicy {
+            self.overflow_policy
+        }
+
+        ///Sets what happens to the captured packets when the queue is full: the capture either waits for the decoder
+        ///or drops the packet. It is applied from the next scanning.
+        pub fn set_overflow_policy(&mut self, overflow_policy: OverflowPolicy) {
+            self.overflow_policy = overflow_policy;
+        }
+
+        ///Returns the number of threads that decode the captured packets.
+        pub fn get_workers(&self) -> usize {
+            self.workers
+        }
+
+        ///Sets the number of threads that decode the captured packets, and the number of parts of the flow table.
+        ///The packets of a connection are always decoded by the same thread. It works only if the status is RunStatus::Stop.
+        pub fn set_workers(&mut self, workers: usize) -> Result<(), SnifferError> {
+            if workers == 0 {
+                return Err(SnifferError::UserError("The number of decoders must be positive ...".to_string()));
+            }
+            if self.get_status() != RunStatus::Stop {
+                return Err(SnifferError::UserWarning("The number of decoders can't be changed while sniffing ...".to_string()));
+            }
+            if workers != self.workers {
+                self.flow_table = Arc::new(self.flow_table.resize(workers));
+                self.workers = workers;
+            }
+            Ok(())
+        }
+
+        ///Returns the counters of the packets captured, queued, dropped and decoded in the current scanning.
+        pub fn get_pipeline_counters(&self) -> PipelineCounters {
+            self.pipeline.snapshot()
+        }
+
         ///Returns the filename that has been set.
         pub fn get_filename(&self) -> Option<String> {
             self.filename.clone()
@@ -548,19 +1129,305 @@ pub mod sniffer {
             *s = status;
         }
 
-        ///Returns the device that has been set.
-        fn get_device(&self) -> &Option<pcap::Device> {
-            &self.device
+        ///Returns the devices that have been set.
+        fn get_devices(&self) -> &Vec<pcap::Device> {
+            &self.devices
         }
 
-        ///Sets the device that will be used for the sniffing.
-        fn set_device(&mut self, device: Option<pcap::Device>) {
-            self.device = device;
+        ///Sets the devices that will be used for the sniffing.
+        fn set_devices(&mut self, devices: Vec<pcap::Device>) {
+            self.devices = devices;
         }
 
-        ///Gets the hashmap in which the sniffing results are being stored.
-        fn get_hashmap(&self) -> &Arc<Mutex<HashMap<(String, u16), (Protocol, usize, u64, u64)>>> {
-            &self.hashmap
+        ///Returns a snapshot of the flow table, that can be used to compute the rankings shown in the report.
+        pub fn get_flow_table(&self) -> FlowTable {
+            self.flow_table.snapshot()
+        }
+
+        ///Returns a snapshot of the flows collected so far, including the TCP performance statistics.
+        pub fn get_flows(&self) -> HashMap<FlowKey, FlowStats> {
+            self.flow_table.snapshot().get_flows()
+        }
+
+        ///Returns the scannings that haven't been discarded, from the oldest. The last one is the scanning in
+        ///execution, or the most recent one, whose data are also returned by the other methods of the sniffer.
+        pub fn get_sessions(&self) -> Vec<Session> {
+            self.sessions.lock().unwrap().clone()
+        }
+
+        ///Returns the scanning with the given id, if it hasn't been discarded.
+        pub fn get_session(&self, id: u64) -> Option<Session> {
+            self.sessions.lock().unwrap().iter().find(|session| session.get_id() == id).cloned()
+        }
+
+        ///Discards a finished scanning and its data. If it is the most recent one, the sniffer is left without data
+        ///until the next scanning.
+        pub fn discard_session(&mut self, id: u64) -> Result<(), SnifferError> {
+            let mut sessions = self.sessions.lock().unwrap();
+            let position = match sessions.iter().position(|session| session.get_id() == id) {
+                Some(position) => position,
+                None => return Err(SnifferError::UserWarning(format!("There is no session #{} ...", id)))
+            };
+            if !sessions[position].is_finished() {
+                return Err(SnifferError::UserWarning(format!("The session #{} is still running ...", id)));
+            }
+            sessions.remove(position);
+            drop(sessions);
+            if id == self.session {
+                self.clear_data();
+            }
+            Ok(())
+        }
+
+        ///Discards all the finished scannings and returns how many they were.
+        pub fn discard_sessions(&mut self) -> usize {
+            let finished: Vec<u64> = self.get_sessions().iter().filter(|session| session.is_finished()).map(|session| session.get_id()).collect();
+            finished.iter().for_each(|id| { let _ = self.discard_session(*id); });
+            finished.len()
+        }
+
+        ///Subscribes to the events of the given kinds, that are received through a channel of the given capacity. The
+        ///subscription lasts across the scannings, until it is dropped or cancelled with unsubscribe.
+        pub fn subscribe(&self, kinds: &[EventKind], capacity: usize) -> Subscription {
+            let subscription = self.events.subscribe(kinds, capacity);
+            self.track_flow_events();
+            subscription
+        }
+
+        ///Subscribes a callback to the events of the given kinds, and returns the id used to cancel the subscription.
+        ///The callback is called by the thread that decodes the packets, so it must return quickly.
+        pub fn subscribe_with<F: Fn(&SnifferEvent) + Send + Sync + 'static>(&self, kinds: &[EventKind], callback: F) -> u64 {
+            let id = self.events.subscribe_with(kinds, callback);
+            self.track_flow_events();
+            id
+        }
+
+        ///Cancels a subscription, returning false if it doesn't exist.
+        pub fn unsubscribe(&self, id: u64) -> bool {
+            let removed = self.events.unsubscribe(id);
+            self.track_flow_events();
+            removed
+        }
+
+        ///Opens an async stream of the events of the given kinds on the attached devices, with the capture settings,
+        ///the capture filter and the flow settings of the sniffer. The stream is independent from run: the packets
+        ///are decoded and aggregated by the task that polls it, and the capture ends when it is dropped. It must be
+        ///called inside a tokio runtime.
+        #[cfg(feature = "tokio")]
+        pub fn event_stream(&self, kinds: &[EventKind]) -> Result<crate::stream::EventStream, SnifferError> {
+            use crate::stream::{EventStream, PacketSource, DEFAULT_STREAM_CAPACITY};
+            let source = PacketSource::open(self.get_devices().clone(), &self.capture_config, self.filter.as_deref(), DEFAULT_STREAM_CAPACITY)?;
+            let mut table = self.flow_table.setting(|table| table.empty_like());
+            table.set_max_flows(self.flow_table.get_max_flows());
+            Ok(EventStream::new(source, kinds, table))
+        }
+
+        ///The flow table records the flows started and closed only while someone has subscribed to them.
+        fn track_flow_events(&self) {
+            let track = self.events.wants(EventKind::Flow);
+            self.flow_table.configure(|table| table.set_track_events(track));
+        }
+
+        ///Prepares the outputs of the reports of a new scanning: the report file, written in the format set, and the
+        ///outputs added.
+        fn start_outputs(&self) -> Result<(), SnifferError> {
+            let periodic = self.time_interval > 0;
+            *self.report_file.lock().unwrap() = self.filename.clone().map(|filename| ReportOutput::new(
+                self.report_options.read().unwrap().format.formatter(), Box::new(FileSink::new(filename, self.report_rotation))));
+            let mut report_file = self.report_file.lock().unwrap();
+            let mut outputs = self.report_outputs.lock().unwrap();
+            for output in report_file.iter_mut().chain(outputs.iter_mut()) {
+                if let Err(error) = output.start(periodic) {
+                    return Err(SnifferError::UserError(format!("The report can't be written: {} ...", error)));
+                }
+            }
+            Ok(())
+        }
+
+        ///Replaces the flows and the counters with empty ones, with the same settings.
+        fn clear_data(&mut self) {
+            self.flow_table = Arc::new(self.flow_table.empty_like());
+            self.pipeline = Arc::new(PipelineStats::new());
+            self.failures = Arc::new(DecodeFailures::new());
+            self.failures.set_sampling(self.failure_samples.is_some());
+        }
+    }
+
+    impl Reporter {
+        ///Stops the scanning, waits for all its threads and writes the report.
+        ///This function works only if the status is either RunStatus::Wait or RunStatus::Running. If the report can't
+        ///be written the scanning is stopped anyway, and the error is given also to whoever waits for the completion.
+        fn save(&self) -> Result<String, SnifferError> {
+            match &*self.status.0.lock().unwrap() {
+                RunStatus::Error(error) => return Err(SnifferError::UserError(error.to_string())),
+                RunStatus::Stop => return Err(SnifferError::UserWarning("The scanning is already stopped ...".to_string())),
+                _ => {}
+            }
+            if self.filename.is_none() && self.report_outputs.lock().unwrap().is_empty() {
+                return Err(SnifferError::UserError("The file doesn't exist ...".to_string()));
+            }
+            // the report is written when no packet is still waiting to be aggregated
+            if !self.shutdown() {
+                return Err(SnifferError::UserWarning("The scanning is already stopped ...".to_string()));
+            }
+            let result = self.write();
+            match &result {
+                Ok(_) => self.monitor.reach(StopReason::User),
+                Err(error) => self.monitor.fail(error.to_string())
+            }
+            self.finish();
+            result
+        }
+
+        fn write(&self) -> Result<String, SnifferError> {
+            let write = if self.time_interval == 0 {
+                let data = self.data(None, true);
+                self.write_outputs(|output| output.write_report(&data))
+            } else {
+                self.section(true)
+            };
+            if let Some(samples) = &self.failure_samples {
+                self.save_failure_samples(samples)?;
+            }
+            return match write {
+                Ok(_) => Ok("The report has been saved and the scanning has been stopped ...".to_string()),
+                Err(error) => Err(SnifferError::UserError(error.to_string()))
+            }
+        }
+
+        ///Returns the data of the report, with the traffic of an interval of the periodic reports if given.
+        fn data(&self, interval: Option<IntervalData>, whole: bool) -> ReportData {
+            let options = self.report_options.read().unwrap().clone();
+            // the shards are copied one at a time, so the capture goes on while the report is written
+            let mut flows = self.flow_table.snapshot();
+            if let Some(filter) = &options.display_filter {
+                flows = flows.filtered(|key, stats| filter.matches_flow(key, stats));
+            }
+            let session = self.sessions.lock().unwrap().iter().find(|session| session.get_id() == self.session).cloned();
+            ReportData { time: Local::now(), session, devices: self.devices.clone(), filter: self.filter.clone(),
+                capture_config: self.capture_config.clone(), flows, counters: self.pipeline.snapshot(),
+                interfaces: self.pipeline.interface_stats(), unclassified: self.failures.snapshot(), interval, whole,
+                summary: options.interval_summary, top_n: options.top_n, sort_key: options.sort_key,
+                drop_threshold: options.drop_threshold, display_filter: options.display_filter.as_ref().map(|filter| filter.to_string()) }
+        }
+
+        ///Returns the traffic since the previous section of the periodic reports, and starts a new interval.
+        fn next_interval(&self) -> IntervalData {
+            let current = self.flow_table.snapshot();
+            let now = Local::now();
+            let mut last_interval = self.last_interval.lock().unwrap();
+            let options = self.report_options.read().unwrap().clone();
+            let interval = match &options.display_filter {
+                Some(filter) => IntervalData::new(&last_interval.0.filtered(|key, stats| filter.matches_flow(key, stats)),
+                                                  &current.filtered(|key, stats| filter.matches_flow(key, stats)),
+                                                  last_interval.1, now, options.sort_key),
+                None => IntervalData::new(&last_interval.0, &current, last_interval.1, now, options.sort_key)
+            };
+            *last_interval = (current, now);
+            interval
+        }
+
+        ///Writes a section of the periodic reports in all the outputs: the whole report, or only the traffic since the
+        ///previous section in delta mode. The last section, written when the report is saved, always contains the
+        ///whole report.
+        fn section(&self, last: bool) -> std::io::Result<()> {
+            let delta = self.report_options.read().unwrap().interval_mode == IntervalMode::Delta;
+            let data = self.data(if delta { Some(self.next_interval()) } else { None }, !delta || last);
+            self.write_outputs(|output| output.write_section(&data))
+        }
+
+        ///Writes in the report file and in all the outputs added, also when one of them fails, and returns the first error.
+        fn write_outputs(&self, mut write: impl FnMut(&mut ReportOutput) -> std::io::Result<()>) -> std::io::Result<()> {
+            let mut result = Ok(());
+            let mut report_file = self.report_file.lock().unwrap();
+            let mut outputs = self.report_outputs.lock().unwrap();
+            for output in report_file.iter_mut().chain(outputs.iter_mut()) {
+                if let Err(error) = write(output) {
+                    result = result.and(Err(error));
+                }
+            }
+            result
+        }
+
+        ///Stops the scanning and waits for its threads: the capture threads notice the stop at the next read timeout
+        ///(or at once if paused), and the decoders end when they have emptied their queues.
+        ///Returns false if there was no scanning in execution.
+        fn shutdown(&self) -> bool {
+            let mut status = self.status.0.lock().unwrap();
+            if *status != RunStatus::Running && *status != RunStatus::Wait {
+                return false
+            }
+            *status = RunStatus::Stop;
+            self.monitor.set_stopping(true);
+            self.status.1.notify_all();
+            drop(status);
+            self.update_session(|session| session.close(Local::now()));
+
+            // the capture threads are joined before the decoders, whose queues are closed when the captures end
+            let threads: Vec<JoinHandle<()>> = self.threads.lock().unwrap().drain(..).collect();
+            for handle in threads {
+                // the thread of the stop conditions can't wait for itself, it ends as soon as the report is written
+                if handle.thread().id() != thread::current().id() {
+                    let _ = handle.join();
+                }
+            }
+            true
+        }
+
+        ///Signals to whoever waits for the completion that the scanning has ended.
+        fn finish(&self) {
+            self.update_session(|session| session.set_stop_reason(self.monitor.get_reason()));
+            self.monitor.set_stopping(false);
+            let _s = self.status.0.lock().unwrap();
+            self.status.1.notify_all();
+        }
+
+        fn update_session<F: FnOnce(&mut Session)>(&self, f: F) {
+            if let Some(session) = self.sessions.lock().unwrap().iter_mut().find(|session| session.get_id() == self.session) {
+                f(session);
+            }
+        }
+
+        ///Saves the final report after a stop condition has been reached.
+        fn complete(&self) {
+            let reason = self.monitor.get_reason().unwrap();
+            match self.save() {
+                Ok(_) => println!("The scanning is complete because {}, the report has been saved ...", reason),
+                // the user has saved the report in the meantime
+                Err(SnifferError::UserWarning(_)) => {},
+                Err(error) => println!("The scanning is complete because {}, but the report can't be saved: {}", reason, error)
+            }
+        }
+
+        ///Writes the samples of the frames that couldn't be decoded in a pcap file.
+        fn save_failure_samples(&self, filename: &str) -> Result<(), SnifferError> {
+            let capture = Capture::dead(Linktype::ETHERNET).map_err(SnifferError::PcapError)?;
+            let mut savefile = capture.savefile(filename).map_err(SnifferError::PcapError)?;
+            let mut samples: Vec<FailureSample> = self.failures.snapshot().iter().flat_map(|f| f.get_samples().to_vec()).collect();
+            samples.sort_by_key(|sample| sample.get_ts());
+            for sample in samples {
+                let ts = TimeVal::from(sample.get_ts());
+                let header = PacketHeader {
+                    ts: libc::timeval { tv_sec: ts.sec as libc::time_t, tv_usec: ts.u_sec as libc::suseconds_t },
+                    caplen: sample.get_data().len() as u32,
+                    len: sample.get_len(),
+                };
+                savefile.write(&Packet::new(&header, sample.get_data()));
+            }
+            savefile.flush().map_err(SnifferError::PcapError)
+        }
+    }
+
+    impl Drop for Sniffer {
+        ///Stops a scanning still in execution, without saving the report, and waits for its threads.
+        fn drop(&mut self) {
+            let reporter = self.reporter();
+            if reporter.shutdown() {
+                reporter.finish();
+            } else {
+                // the report may be being written after a stop condition
+                let _s = self.status.1.wait_while(self.status.0.lock().unwrap(), |_| self.monitor.is_stopping()).unwrap();
+            }
         }
     }
-}
\ No newline at end of file
+}
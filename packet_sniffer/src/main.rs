@@ -1,9 +1,16 @@
 use std::io::{stdin, stdout, Write};
 use pcap::Device;
 use ansi_term::Colour;
-use clap::{Parser};
+use clap::{ArgMatches, CommandFactory, FromArgMatches, Parser, ValueSource};
 use std::process::exit;
 use packet_sniffer::sniffer::{RunStatus, Sniffer, SnifferError};
+use packet_sniffer::flow::{EvictionPolicy, SortKey};
+use packet_sniffer::pipeline::{OverflowPolicy, DEFAULT_DROP_THRESHOLD, DEFAULT_QUEUE_CAPACITY};
+use packet_sniffer::capture::{CaptureConfig, TimestampPrecision};
+use packet_sniffer::dump::DumpFormat;
+use packet_sniffer::rotation::Rotation;
+use packet_sniffer::stop::StopConditions;
+use packet_sniffer::report::{IntervalMode, ReportFormat};
 
 
 #[derive(Parser, Debug)]
@@ -11,8 +18,108 @@ use packet_sniffer::sniffer::{RunStatus, Sniffer, SnifferError};
 struct Args {
     #[clap(short, long, value_parser, default_value_t = 0)]
     interval: u64,
+    /// What the periodic reports contain: all the data collected (cumulative) or the traffic of every interval (delta)
+    #[clap(long, value_parser, default_value = "cumulative")]
+    interval_mode: IntervalMode,
+    /// Add the summary of the whole scanning after every interval of the delta mode
+    #[clap(long, action)]
+    interval_summary: bool,
     #[clap(short, long, value_parser, default_value = "None")]
-    file: String
+    file: String,
+    /// Format of the report (text, json, csv or html; one line of JSON or the rows of the flows for every interval,
+    /// the HTML page only without interval)
+    #[clap(long, value_parser, default_value = "text")]
+    format: ReportFormat,
+    /// Number of hosts, ports and conversations shown in the summary of the report
+    #[clap(short, long, value_parser, default_value_t = 10)]
+    top: usize,
+    /// Key used to sort the report (bytes or packets)
+    #[clap(short, long, value_parser, default_value = "bytes")]
+    sort: SortKey,
+    /// Width of the throughput buckets (sec)
+    #[clap(long, value_parser, default_value_t = 1)]
+    bucket_width: u64,
+    /// Close the flows without packets for this time (sec, 0 to disable)
+    #[clap(long, value_parser, default_value_t = 0)]
+    idle_timeout: u64,
+    /// Close the flows that last longer than this time (sec, 0 to disable)
+    #[clap(long, value_parser, default_value_t = 0)]
+    active_timeout: u64,
+    /// Maximum number of live flows (0 for no limit)
+    #[clap(long, value_parser, default_value_t = 0)]
+    max_flows: usize,
+    /// Policy used to evict the flows when the limit is reached (lru or smallest)
+    #[clap(long, value_parser, default_value = "lru")]
+    eviction: EvictionPolicy,
+    /// Maximum number of captured packets waiting to be decoded
+    #[clap(long, value_parser, default_value_t = DEFAULT_QUEUE_CAPACITY)]
+    queue_capacity: usize,
+    /// What to do with the captured packets when the queue is full (block or drop)
+    #[clap(long, value_parser, default_value = "block")]
+    overflow: OverflowPolicy,
+    /// Number of threads decoding the packets (0 for one per CPU, up to 8)
+    #[clap(long, value_parser, default_value_t = 0)]
+    workers: usize,
+    /// Percentage of lost packets above which the report is flagged as unreliable
+    #[clap(long, value_parser, default_value_t = DEFAULT_DROP_THRESHOLD)]
+    drop_threshold: f64,
+    /// Pcap file in which a sample of the frames that can't be decoded is saved
+    #[clap(long, value_parser)]
+    failure_samples: Option<String>,
+    /// BPF expression restricting the captured packets (e.g. "not port 22")
+    #[clap(long, value_parser)]
+    filter: Option<String>,
+    /// Display filter selecting the flows of the report (e.g. "tcp and flow.bytes > 1MB")
+    #[clap(long, value_parser)]
+    display_filter: Option<String>,
+    /// File with the capture settings, one 'option = value' per line (overridden by the options below)
+    #[clap(long, value_parser)]
+    config: Option<String>,
+    /// Number of bytes kept for every packet
+    #[clap(long, value_parser)]
+    snaplen: Option<u32>,
+    /// Size of the kernel buffer (bytes)
+    #[clap(long, value_parser)]
+    buffer_size: Option<u32>,
+    /// Time the kernel waits for more packets before returning them (ms)
+    #[clap(long, value_parser)]
+    read_timeout: Option<u32>,
+    /// Deliver the packets as soon as they arrive (true or false)
+    #[clap(long, value_parser)]
+    immediate: Option<bool>,
+    /// Capture also the packets addressed to other hosts (true or false)
+    #[clap(long, value_parser)]
+    promisc: Option<bool>,
+    /// Precision of the timestamps (micro or nano)
+    #[clap(long, value_parser)]
+    precision: Option<TimestampPrecision>,
+    /// File in which the raw packets are saved, to be opened with Wireshark
+    #[clap(short, long, value_parser)]
+    write: Option<String>,
+    /// Format of the file of the raw packets (pcap or pcapng, by default from its extension)
+    #[clap(long, value_parser)]
+    write_format: Option<DumpFormat>,
+    /// Start a new file of raw packets after this size (MB, 0 to disable)
+    #[clap(short = 'C', long, value_parser, default_value_t = 0)]
+    rotate_size: u64,
+    /// Start a new file of raw packets after this time (sec, 0 to disable)
+    #[clap(short = 'G', long, value_parser, default_value_t = 0)]
+    rotate_seconds: u64,
+    /// Number of rotated files kept, the oldest are deleted (0 for no limit)
+    #[clap(short = 'W', long, value_parser, default_value_t = 0)]
+    rotate_files: usize,
+    /// Rotate also the periodic reports, in the same way
+    #[clap(long, action)]
+    rotate_reports: bool,
+    /// Stop and save the report after this number of packets (0 for no limit)
+    #[clap(short = 'c', long, value_parser, default_value_t = 0)]
+    count: u64,
+    /// Stop and save the report after this time of capture, pauses excluded (sec, 0 for no limit)
+    #[clap(long, value_parser, default_value_t = 0)]
+    duration: u64,
+    /// Stop and save the report after this number of captured bytes (0 for no limit)
+    #[clap(long, value_parser, default_value_t = 0)]
+    max_bytes: u64
 }
 
 fn main() {
@@ -20,6 +127,11 @@ fn main() {
     let mut cmd = String::new();
     let args = Args::parse();
 
+    if let Err(e) = configure(&mut sniffer, &args, &|_| true) {
+        print!("{}", Colour::Yellow.italic().paint(e.to_string()));
+        return;
+    }
+
     if args.file == "None" && args.interval == 0 {
         println!("Welcome to the Packet-Sniffer-M1 interface, write '{}' or '{}' to know the list of possible commands",
                  Colour::Red.italic().paint("?"), Colour::Red.italic().paint("help"));
@@ -36,6 +148,13 @@ fn main() {
             }
             devices();
             sniffing(&mut sniffer);
+            // with a stop condition nobody has to be at the keyboard, e.g. when the capture is scheduled
+            if sniffer.get_stop_conditions().is_enabled() {
+                match sniffer.wait_for_completion() {
+                    Ok(_) => exit(0),
+                    Err(e) => { println!("{}", e); exit(1) }
+                }
+            }
         }
     }
 
@@ -50,6 +169,7 @@ fn main() {
         match cmd.trim().to_ascii_lowercase().as_str() {
             "?" | "help" => { help() },
             "devices" => { devices() },
+            "sessions" => { sessions(&sniffer) },
             "exit" => { exit_prompt(&sniffer) },
             "pause" => {
                 let res = sniffer.pause();
@@ -66,14 +186,29 @@ fn main() {
                 }
             },
             "stop" => {
+                // the scanning may have been stopped by a stop condition in the meantime
                 match sniffer.save_report() {
                     Ok(m) => println!("{}", m),
+                    Err(SnifferError::UserWarning(w)) => println!("{}", w),
                     Err(e) => panic!("{}", e)
                 };
 
             },
-            x => {
-                let res = check_sniffing(x, &mut sniffer);
+            command if command.split_whitespace().next() == Some("discard") => {
+                match discard_command(command, &mut sniffer) {
+                    Ok(m) => println!("{}", m),
+                    Err(e) => println!("{}", e)
+                }
+            },
+            command if matches!(command.split_whitespace().next(), Some("filter") | Some("view")) => {
+                match display_command(cmd.trim(), &mut sniffer) {
+                    Ok(m) => println!("{}", m),
+                    Err(e) => println!("{}", e)
+                }
+            },
+            _ => {
+                // the original case is kept, since it matters for the file names and the filters
+                let res = check_sniffing(cmd.trim(), &mut sniffer);
                 match res {
                     Err(error) => { println!("{}", error); continue },
                     Ok(()) => {
@@ -101,7 +236,55 @@ fn help() {
     println!("-> {} {} {} {} {}", Colour::Red.paint("sniff"),
              Colour::Yellow.paint("--file"), Colour::Yellow.italic().paint("file_name"),
              Colour::Green.paint("[--interval"), Colour::Green.paint("time_interval (sec)]"));
+    println!("   {} {} {} {} {} {}",
+             Colour::Green.paint("[--top"), Colour::Green.paint("n]"),
+             Colour::Green.paint("[--sort"), Colour::Green.paint("bytes|packets]"),
+             Colour::Green.paint("[--bucket-width"), Colour::Green.paint("sec]"));
+    println!("   {} {} {} {} {} {} {} {}",
+             Colour::Green.paint("[--idle-timeout"), Colour::Green.paint("sec]"),
+             Colour::Green.paint("[--active-timeout"), Colour::Green.paint("sec]"),
+             Colour::Green.paint("[--max-flows"), Colour::Green.paint("n]"),
+             Colour::Green.paint("[--eviction"), Colour::Green.paint("lru|smallest]"));
+    println!("   {} {} {} {} {} {} {} {}",
+             Colour::Green.paint("[--queue-capacity"), Colour::Green.paint("n]"),
+             Colour::Green.paint("[--overflow"), Colour::Green.paint("block|drop]"),
+             Colour::Green.paint("[--workers"), Colour::Green.paint("n]"),
+             Colour::Green.paint("[--drop-threshold"), Colour::Green.paint("%]"));
+    println!("   {} {}",
+             Colour::Green.paint("[--failure-samples"), Colour::Green.paint("file.pcap]"));
+    println!("   {} {}",
+             Colour::Green.paint("[--filter"), Colour::Green.paint("\"bpf expression\"]"));
+    println!("   {} {}",
+             Colour::Green.paint("[--display-filter"), Colour::Green.paint("\"display filter\"]"));
+    println!("   {} {} {} {} {} {} {} {}",
+             Colour::Green.paint("[--config"), Colour::Green.paint("file]"),
+             Colour::Green.paint("[--snaplen"), Colour::Green.paint("bytes]"),
+             Colour::Green.paint("[--buffer-size"), Colour::Green.paint("bytes]"),
+             Colour::Green.paint("[--read-timeout"), Colour::Green.paint("ms]"));
+    println!("   {} {} {} {} {} {}",
+             Colour::Green.paint("[--immediate"), Colour::Green.paint("true|false]"),
+             Colour::Green.paint("[--promisc"), Colour::Green.paint("true|false]"),
+             Colour::Green.paint("[--precision"), Colour::Green.paint("micro|nano]"));
+    println!("   {} {} {} {}",
+             Colour::Green.paint("[--write"), Colour::Green.paint("file.pcap|file.pcapng]"),
+             Colour::Green.paint("[--write-format"), Colour::Green.paint("pcap|pcapng]"));
+    println!("   {} {} {} {} {} {} {}",
+             Colour::Green.paint("[--rotate-size"), Colour::Green.paint("MB]"),
+             Colour::Green.paint("[--rotate-seconds"), Colour::Green.paint("sec]"),
+             Colour::Green.paint("[--rotate-files"), Colour::Green.paint("n]"),
+             Colour::Green.paint("[--rotate-reports]"));
+    println!("   {} {} {} {} {} {}",
+             Colour::Green.paint("[--count"), Colour::Green.paint("packets]"),
+             Colour::Green.paint("[--duration"), Colour::Green.paint("sec]"),
+             Colour::Green.paint("[--max-bytes"), Colour::Green.paint("bytes]"));
+    println!("   (the options not given keep the values of the previous scanning, an empty file or filter removes it)");
+    println!("-> {} {} (Set the display filter of the report, without expression to remove it)",
+             Colour::Red.paint("filter"), Colour::Green.paint("[\"display filter\"]"));
+    println!("-> {} {} (Show the top flows collected so far that match the display filter)",
+             Colour::Red.paint("view"), Colour::Green.paint("[\"display filter\"]"));
     println!("-> {} (List of all the devices available)", Colour::Red.paint("devices"));
+    println!("-> {} (List of the scannings of this execution, with their data)", Colour::Red.paint("sessions"));
+    println!("-> {} {} (Discard a finished scanning, or all of them)", Colour::Red.paint("discard"), Colour::Green.paint("id|all"));
     println!("-> {} (Pause the sniffing if it is running)", Colour::Red.paint("pause"));
     println!("-> {} (Resume the sniffing)", Colour::Red.paint("resume"));
     println!("-> {} (Stop the sniffing)", Colour::Red.paint("stop"));
@@ -136,14 +319,76 @@ fn exit_prompt(sniffer: &Sniffer) {
     }
 }
 
+fn sessions(sniffer: &Sniffer) {
+    let sessions = sniffer.get_sessions();
+    if sessions.is_empty() {
+        println!("There is no session ...");
+    }
+    for session in sessions {
+        println!("{}", session);
+    }
+}
+
+/// Runs the 'discard' command, whose argument is the id of a session or 'all'.
+fn discard_command(command: &str, sniffer: &mut Sniffer) -> Result<String, SnifferError> {
+    let words: Vec<&str> = command.split_whitespace().collect();
+    match words.get(1) {
+        Some(&"all") => Ok(format!("{} sessions have been discarded ...", sniffer.discard_sessions())),
+        Some(id) => match id.trim_start_matches('#').parse::<u64>() {
+            Ok(id) => sniffer.discard_session(id).map(|_| format!("The session #{} has been discarded ...", id)),
+            Err(_) => Err(SnifferError::UserWarning("Please insert the id of a session or 'all' ...".to_string()))
+        },
+        None => Err(SnifferError::UserWarning("Please insert the id of a session or 'all' ...".to_string()))
+    }
+}
+
+/// Splits a command in words, like a shell: the text between double or single quotes is a single word.
+fn split_command(command: &str) -> Result<Vec<String>, SnifferError> {
+    let mut words = Vec::new();
+    let mut word: Option<String> = None;
+    let mut quote: Option<char> = None;
+    for c in command.chars() {
+        match quote {
+            Some(q) if c == q => quote = None,
+            Some(_) => word.get_or_insert_with(String::new).push(c),
+            None if c == '"' || c == '\'' => {
+                quote = Some(c);
+                word.get_or_insert_with(String::new);
+            },
+            None if c.is_whitespace() => words.extend(word.take()),
+            None => word.get_or_insert_with(String::new).push(c)
+        }
+    }
+    if quote.is_some() {
+        return Err(SnifferError::UserWarning("The command contains an unclosed quote ...".to_string()));
+    }
+    words.extend(word);
+    Ok(words)
+}
+
+/// Runs the 'filter' and 'view' commands, whose argument is a display filter (the quotes are optional).
+fn display_command(command: &str, sniffer: &mut Sniffer) -> Result<String, SnifferError> {
+    let words = split_command(command)?;
+    let filter = words[1..].join(" ");
+    if words[0].eq_ignore_ascii_case("view") {
+        return sniffer.live_view(Some(filter.as_str()));
+    }
+    sniffer.set_display_filter(filter.as_str())?;
+    return match sniffer.get_display_filter() {
+        Some(filter) => Ok(format!("The report will show only the flows that match: {}", filter)),
+        None => Ok("The display filter has been removed ...".to_string())
+    }
+}
+
 fn check_sniffing(command: &str, sniffer: &mut Sniffer) -> Result<(), SnifferError> {
-    return if command.starts_with("sniff") {
+    return if command.to_ascii_lowercase().starts_with("sniff") {
         let status = sniffer.get_status();
         match &status {
             RunStatus::Running | RunStatus::Wait => Err(SnifferError::UserWarning("Another scanning is already running ...".to_string())),
             RunStatus::Error(error) => Err(SnifferError::UserError(error.to_string())),
             _ => {
-                let split: Vec<&str> = command.split(" ").filter(|x| *x != "").collect();
+                let split = split_command(command)?;
+                let split: Vec<&str> = split.iter().map(|x| x.as_str()).collect();
                 let pos_file = split.iter().position(|x| *x == "--file");
                 if pos_file.is_none() {
                     return Err(SnifferError::UserWarning("The file argument is mandatory, please insert something ...".to_string()));
@@ -165,6 +410,11 @@ fn check_sniffing(command: &str, sniffer: &mut Sniffer) -> Result<(), SnifferErr
                         }
                     }
                 }
+                // the other options are the same accepted on the command line, the ones not given keep their values
+                match parse_command(&split) {
+                    Ok((args, matches)) => configure(sniffer, &args, &|id| matches.value_source(id) == Some(ValueSource::CommandLine))?,
+                    Err(error) => return Err(SnifferError::UserWarning(error.to_string()))
+                }
                 match sniffer.set_file((*split.get(pos_file.unwrap() + 1).unwrap().to_string()).to_string()) {
                     Ok(()) => Ok(()),
                     Err(error) => { Err(SnifferError::UserError(error.to_string())) }
@@ -176,9 +426,85 @@ fn check_sniffing(command: &str, sniffer: &mut Sniffer) -> Result<(), SnifferErr
     }
 }
 
+/// Parses the options of the sniff command, returning also which ones have been given.
+fn parse_command(words: &[&str]) -> Result<(Args, ArgMatches), clap::Error> {
+    let matches = Args::command().try_get_matches_from(words)?;
+    Ok((Args::from_arg_matches(&matches)?, matches))
+}
+
+/// Applies to the sniffer the options shared by the command line and the sniff command. Only the options for which
+/// `given` is true are applied, the others keep the values of the previous scanning (e.g. the display filter set with
+/// the 'filter' command). An empty file name or filter removes it.
+fn configure(sniffer: &mut Sniffer, args: &Args, given: &dyn Fn(&str) -> bool) -> Result<(), SnifferError> {
+    if given("top") { sniffer.set_top_n(args.top)?; }
+    if given("sort") { sniffer.set_sort_key(args.sort); }
+    if given("interval-mode") || given("interval-summary") {
+        let mode = if given("interval-mode") { args.interval_mode } else { sniffer.get_interval_mode() };
+        let summary = if given("interval-summary") { args.interval_summary } else { sniffer.get_interval_summary() };
+        sniffer.set_interval_mode(mode, summary);
+    }
+    if given("format") { sniffer.set_report_format(args.format); }
+    if given("bucket-width") { sniffer.set_bucket_width(args.bucket_width)?; }
+    if given("idle-timeout") { sniffer.set_idle_timeout(args.idle_timeout); }
+    if given("active-timeout") { sniffer.set_active_timeout(args.active_timeout); }
+    if given("max-flows") || given("eviction") {
+        let max_flows = if given("max-flows") { args.max_flows } else { sniffer.get_max_flows() };
+        let eviction = if given("eviction") { args.eviction } else { sniffer.get_eviction_policy() };
+        sniffer.set_max_flows(max_flows, eviction);
+    }
+    if given("queue-capacity") { sniffer.set_queue_capacity(args.queue_capacity)?; }
+    if given("overflow") { sniffer.set_overflow_policy(args.overflow); }
+    if given("drop-threshold") { sniffer.set_drop_threshold(args.drop_threshold)?; }
+    if given("failure-samples") { sniffer.set_failure_samples(args.failure_samples.clone().filter(|file| !file.is_empty())); }
+    if given("filter") { sniffer.set_filter(args.filter.as_deref().unwrap_or(""))?; }
+    if given("display-filter") { sniffer.set_display_filter(args.display_filter.as_deref().unwrap_or(""))?; }
+    if given("workers") && args.workers > 0 {
+        sniffer.set_workers(args.workers)?;
+    }
+    let mut capture = match &args.config {
+        Some(path) if given("config") => CaptureConfig::from_file(path)?,
+        _ => sniffer.get_capture_config()
+    };
+    if let Some(snaplen) = args.snaplen { capture.set_snaplen(snaplen)?; }
+    if let Some(buffer_size) = args.buffer_size { capture.set_buffer_size(Some(buffer_size))?; }
+    if let Some(read_timeout) = args.read_timeout { capture.set_timeout(read_timeout)?; }
+    if let Some(immediate) = args.immediate { capture.set_immediate(immediate); }
+    if let Some(promisc) = args.promisc { capture.set_promisc(promisc); }
+    if let Some(precision) = args.precision { capture.set_precision(precision); }
+    sniffer.set_capture_config(capture)?;
+    if given("write") || given("write-format") {
+        let filename = match given("write") {
+            true => args.write.clone().filter(|file| !file.is_empty()),
+            false => sniffer.get_dump_file().map(|(file, _)| file)
+        };
+        let format = match (args.write_format.filter(|_| given("write-format")), &filename) {
+            (Some(format), _) => format,
+            (None, Some(filename)) => DumpFormat::from_path(filename),
+            (None, None) => DumpFormat::Pcap
+        };
+        sniffer.set_dump_file(filename, format);
+    }
+    if ["rotate-size", "rotate-seconds", "rotate-files", "rotate-reports"].iter().any(|id| given(id)) {
+        let current = sniffer.get_dump_rotation();
+        let rotation = Rotation::new(if given("rotate-size") { args.rotate_size.saturating_mul(1_000_000) } else { current.get_max_bytes() },
+                                     if given("rotate-seconds") { args.rotate_seconds } else { current.get_max_seconds() },
+                                     if given("rotate-files") { args.rotate_files } else { current.get_max_files() });
+        let reports = (given("rotate-reports") && args.rotate_reports) || sniffer.get_report_rotation().is_enabled();
+        sniffer.set_dump_rotation(rotation);
+        sniffer.set_report_rotation(if reports { rotation } else { Rotation::default() });
+    }
+    if given("count") || given("duration") || given("max-bytes") {
+        let current = sniffer.get_stop_conditions();
+        sniffer.set_stop_conditions(StopConditions::new(if given("count") { args.count } else { current.get_max_packets() },
+                                                        if given("duration") { args.duration } else { current.get_max_seconds() },
+                                                        if given("max-bytes") { args.max_bytes } else { current.get_max_bytes() }))?;
+    }
+    Ok(())
+}
+
 fn sniffing(sniffer: &mut Sniffer) {
     let mut cmd = String::new();
-    print!("Which device would you sniff? ");
+    print!("Which device would you sniff (more than one can be separated by spaces)? ");
 
     loop {
         cmd.clear();
@@ -191,28 +517,82 @@ fn sniffing(sniffer: &mut Sniffer) {
                 break;
             },
             _ => {
-                for device in Device::list().unwrap() {
-                    if device.name == cmd.trim().to_string().as_str() {
-                        match sniffer.attach(device) {
-                            Ok(()) => (),
-                            Err(e) => { panic!("{}", e) },
-                        };
-                        if sniffer.get_time_interval() == 0 {
-                            match sniffer.run() {
-                                Err(e) => { panic!("{}", e) }
-                                _ => {}
-                            }
-                        } else {
-                            match sniffer.run_with_interval() {
-                                Err(e) => { panic!("{}", e) }
-                                _ => {}
-                            }
-                        }
-                        return;
+                // several devices, separated by spaces or commas, are sniffed at the same time
+                let names: Vec<&str> = cmd.split(|c: char| c == ',' || c.is_whitespace()).filter(|name| !name.is_empty()).collect();
+                let devices: Vec<Device> = Device::list().unwrap().into_iter().filter(|device| names.contains(&device.name.as_str())).collect();
+                if !names.is_empty() && devices.len() == names.len() {
+                    // the devices may not be opened, e.g. without the permissions or with a wrong capture setting
+                    let res = sniffer.attach_all(devices).and_then(|_| match sniffer.get_time_interval() {
+                        0 => sniffer.run(),
+                        _ => sniffer.run_with_interval()
+                    });
+                    match res {
+                        Ok(()) => return,
+                        Err(e) => println!("{}", e)
                     }
+                    print!("Which device would you sniff? ");
+                    continue;
                 }
                 print!("Insert a valid device name, which device would you sniff? ");
             }
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+    use packet_sniffer::capture::TimestampPrecision;
+    use packet_sniffer::report::{IntervalMode, ReportFormat};
+    use packet_sniffer::sniffer::Sniffer;
+    use crate::{configure, parse_command, split_command, Args};
+
+    #[test]
+    fn test_split_command() {
+        let words = split_command("sniff --file report.txt  --filter \"not port 22 and not host 10.0.0.5\" --top '5'").unwrap();
+        assert_eq!(words, vec!["sniff", "--file", "report.txt", "--filter", "not port 22 and not host 10.0.0.5", "--top", "5"]);
+        assert_eq!(split_command("sniff --filter \"\"").unwrap(), vec!["sniff", "--filter", ""]);
+        assert!(split_command("sniff --filter \"port 53").is_err());
+    }
+
+    #[test]
+    fn test_capture_options() {
+        let args = Args::try_parse_from(["sniff", "--promisc", "false", "--read-timeout", "200", "--precision", "nano"]).unwrap();
+        assert_eq!(args.promisc, Some(false));
+        assert_eq!(args.immediate, None);
+        assert_eq!(args.read_timeout, Some(200));
+        assert_eq!(args.precision, Some(TimestampPrecision::Nano));
+        assert!(!args.rotate_reports);
+        let args = Args::try_parse_from(["sniff", "-w", "uplink.pcapng", "-C", "100", "-W", "5", "--rotate-reports"]).unwrap();
+        assert_eq!((args.rotate_size, args.rotate_seconds, args.rotate_files), (100, 0, 5));
+        assert!(args.rotate_reports);
+        let args = Args::try_parse_from(["sniff", "--file", "hourly.txt", "-c", "1000", "--duration", "3600"]).unwrap();
+        assert_eq!((args.count, args.duration, args.max_bytes), (1000, 3600, 0));
+        assert_eq!(args.interval_mode, IntervalMode::Cumulative);
+        assert_eq!(args.format, ReportFormat::Text);
+        let args = Args::try_parse_from(["sniff", "--file", "live.txt", "-i", "10", "--interval-mode", "delta", "--interval-summary"]).unwrap();
+        assert_eq!(args.interval_mode, IntervalMode::Delta);
+        assert!(args.interval_summary);
+        let args = Args::try_parse_from(["sniff", "--file", "report.ndjson", "-i", "10", "--format", "json"]).unwrap();
+        assert_eq!(args.format, ReportFormat::Json);
+        let args = Args::try_parse_from(["sniff", "--file", "flows.csv", "--format", "csv"]).unwrap();
+        assert_eq!(args.format, ReportFormat::Csv);
+        let args = Args::try_parse_from(["sniff", "--file", "incident.html", "--format", "html"]).unwrap();
+        assert_eq!(args.format, ReportFormat::Html);
+        assert!(Args::try_parse_from(["sniff", "--format", "xml"]).is_err());
+    }
+
+    #[test]
+    fn test_sniff_keeps_options() {
+        let mut sniffer = Sniffer::new();
+        let (args, _) = parse_command(&["sniff", "--top", "5", "--idle-timeout", "30"]).unwrap();
+        configure(&mut sniffer, &args, &|_| true).unwrap();
+        sniffer.set_display_filter("tcp").unwrap();
+        // a new sniff command changes only the options it gives
+        let (args, matches) = parse_command(&["sniff", "--file", "report.txt", "--top", "3"]).unwrap();
+        configure(&mut sniffer, &args, &|id| matches.value_source(id) == Some(clap::ValueSource::CommandLine)).unwrap();
+        assert_eq!(sniffer.get_top_n(), 3);
+        assert_eq!(sniffer.get_idle_timeout(), 30);
+        assert_eq!(sniffer.get_display_filter(), Some("tcp".to_string()));
+    }
+}
@@ -0,0 +1,418 @@
+//! The pipeline module contains the settings and the counters of the queue that moves the captured packets
+//! from the capture thread to the decoder, together with the statistics of the capture reported by libpcap
+//! and of the packets that couldn't be decoded.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::mpsc::{SyncSender, TrySendError};
+use std::sync::Mutex;
+use crate::pkt_parser::{DecodeError, FailureReason, Layer};
+
+/// The default number of packets that can wait in the queue to be decoded.
+pub const DEFAULT_QUEUE_CAPACITY: usize = 65536;
+
+/// The default percentage of lost packets above which the report is flagged as unreliable.
+pub const DEFAULT_DROP_THRESHOLD: f64 = 1.0;
+
+/// The number of frames kept for every reason of the decode failures, when the samples are enabled.
+pub const MAX_FAILURE_SAMPLES: usize = 10;
+
+/// What the capture thread does when the queue is full.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    /// Waits until the decoder makes room in the queue: no packet is lost by the pipeline, but the kernel may drop them.
+    Block,
+    /// Discards the packet just captured and counts it as dropped.
+    DropNewest,
+}
+
+impl FromStr for OverflowPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "block" => Ok(OverflowPolicy::Block),
+            "drop" | "drop-newest" => Ok(OverflowPolicy::DropNewest),
+            _ => Err(format!("Unknown overflow policy '{}', use block or drop", s))
+        }
+    }
+}
+
+impl Display for OverflowPolicy {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OverflowPolicy::Block => write!(f, "block"),
+            OverflowPolicy::DropNewest => write!(f, "drop-newest"),
+        }
+    }
+}
+
+/// What happened to a packet given to the decoders.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Delivery {
+    /// The packet is in the queue of a decoder.
+    Queued,
+    /// The queue was full and the packet has been discarded, according to the overflow policy.
+    Dropped,
+    /// The decoder is gone and the capture should stop.
+    Disconnected,
+}
+
+/// The counters of the pipeline, shared between the capture thread, the decoder and the report.
+#[derive(Debug, Default)]
+pub struct PipelineStats {
+    captured: AtomicU64,
+    queued: AtomicU64,
+    dropped: AtomicU64,
+    processed: AtomicU64,
+    decode_errors: AtomicU64,
+    max_depth: AtomicU64,
+    interfaces: Mutex<BTreeMap<String, CaptureCounters>>,
+}
+
+impl PipelineStats {
+    pub fn new() -> Self {
+        PipelineStats::default()
+    }
+
+    /// Sends a captured packet to the decoder, following the overflow policy.
+    pub fn send<T>(&self, tx: &SyncSender<T>, item: T, policy: OverflowPolicy) -> Delivery {
+        self.captured.fetch_add(1, Ordering::Relaxed);
+        let sent = match policy {
+            OverflowPolicy::Block => tx.send(item).is_ok(),
+            OverflowPolicy::DropNewest => match tx.try_send(item) {
+                Ok(()) => true,
+                Err(TrySendError::Full(_)) => {
+                    self.dropped.fetch_add(1, Ordering::Relaxed);
+                    return Delivery::Dropped
+                },
+                Err(TrySendError::Disconnected(_)) => false
+            }
+        };
+        if !sent {
+            return Delivery::Disconnected
+        }
+        let queued = self.queued.fetch_add(1, Ordering::Relaxed) + 1;
+        let depth = queued.saturating_sub(self.processed.load(Ordering::Relaxed));
+        self.max_depth.fetch_max(depth, Ordering::Relaxed);
+        Delivery::Queued
+    }
+
+    /// Accounts a packet taken from the queue by the decoder.
+    pub fn processed(&self, decoded: bool) {
+        self.processed.fetch_add(1, Ordering::Relaxed);
+        if !decoded {
+            self.decode_errors.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Stores the last statistics read from libpcap (pcap_stats) for an interface: the packets received, the ones
+    /// dropped because the buffer was full and the ones dropped by the interface. They are counted from the start of
+    /// the capture.
+    pub fn capture_stats(&self, interface: &str, received: u32, dropped: u32, interface_dropped: u32) {
+        self.interfaces.lock().unwrap().insert(interface.to_string(), CaptureCounters {
+            received: received as u64, dropped: dropped as u64, interface_dropped: interface_dropped as u64 });
+    }
+
+    /// Returns the last statistics read from libpcap for every interface, sorted by name.
+    pub fn interface_stats(&self) -> Vec<(String, CaptureCounters)> {
+        self.interfaces.lock().unwrap().iter().map(|(name, counters)| (name.clone(), *counters)).collect()
+    }
+
+    /// Returns a copy of the counters.
+    pub fn snapshot(&self) -> PipelineCounters {
+        let queued = self.queued.load(Ordering::Relaxed);
+        let processed = self.processed.load(Ordering::Relaxed);
+        let interfaces = self.interfaces.lock().unwrap();
+        PipelineCounters {
+            captured: self.captured.load(Ordering::Relaxed),
+            queued,
+            dropped: self.dropped.load(Ordering::Relaxed),
+            processed,
+            decode_errors: self.decode_errors.load(Ordering::Relaxed),
+            depth: queued.saturating_sub(processed),
+            max_depth: self.max_depth.load(Ordering::Relaxed),
+            kernel_received: interfaces.values().map(|c| c.received).sum(),
+            kernel_dropped: interfaces.values().map(|c| c.dropped).sum(),
+            interface_dropped: interfaces.values().map(|c| c.interface_dropped).sum(),
+        }
+    }
+}
+
+/// The statistics of the capture on a single interface, as reported by libpcap.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CaptureCounters {
+    received: u64,
+    dropped: u64,
+    interface_dropped: u64,
+}
+
+impl CaptureCounters {
+    /// The packets received by libpcap.
+    pub fn get_received(&self) -> u64 {
+        self.received
+    }
+
+    /// The packets dropped by the kernel because the buffer was full.
+    pub fn get_dropped(&self) -> u64 {
+        self.dropped
+    }
+
+    /// The packets dropped by the network interface or its driver.
+    pub fn get_interface_dropped(&self) -> u64 {
+        self.interface_dropped
+    }
+}
+
+/// The value of the counters of the pipeline at a given time, with the statistics of libpcap summed over the interfaces.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PipelineCounters {
+    captured: u64,
+    queued: u64,
+    dropped: u64,
+    processed: u64,
+    decode_errors: u64,
+    depth: u64,
+    max_depth: u64,
+    kernel_received: u64,
+    kernel_dropped: u64,
+    interface_dropped: u64,
+}
+
+impl PipelineCounters {
+    /// The packets received from the capture.
+    pub fn get_captured(&self) -> u64 {
+        self.captured
+    }
+
+    /// The packets put in the queue.
+    pub fn get_queued(&self) -> u64 {
+        self.queued
+    }
+
+    /// The packets discarded because the queue was full.
+    pub fn get_dropped(&self) -> u64 {
+        self.dropped
+    }
+
+    /// The packets taken from the queue by the decoder.
+    pub fn get_processed(&self) -> u64 {
+        self.processed
+    }
+
+    /// The packets that couldn't be decoded.
+    pub fn get_decode_errors(&self) -> u64 {
+        self.decode_errors
+    }
+
+    /// The packets decoded, i.e. the processed ones without the decode errors.
+    pub fn get_decoded(&self) -> u64 {
+        self.processed.saturating_sub(self.decode_errors)
+    }
+
+    /// The packets still waiting in the queue.
+    pub fn get_depth(&self) -> u64 {
+        self.depth
+    }
+
+    /// The highest number of packets that waited in the queue at the same time.
+    pub fn get_max_depth(&self) -> u64 {
+        self.max_depth
+    }
+
+    /// The packets received by libpcap, as reported by pcap_stats.
+    pub fn get_kernel_received(&self) -> u64 {
+        self.kernel_received
+    }
+
+    /// The packets dropped by the kernel because the buffer was full.
+    pub fn get_kernel_dropped(&self) -> u64 {
+        self.kernel_dropped
+    }
+
+    /// The packets dropped by the network interface or its driver.
+    pub fn get_interface_dropped(&self) -> u64 {
+        self.interface_dropped
+    }
+
+    /// The packets lost before being decoded, by the interface, the kernel or the queue.
+    pub fn get_lost(&self) -> u64 {
+        self.kernel_dropped + self.interface_dropped + self.dropped
+    }
+
+    /// The percentage of the packets seen on the interface that have been lost before being decoded.
+    pub fn get_drop_rate(&self) -> f64 {
+        let seen = self.captured + self.kernel_dropped + self.interface_dropped;
+        if seen == 0 {
+            return 0.0
+        }
+        self.get_lost() as f64 * 100.0 / seen as f64
+    }
+}
+
+/// A raw frame that couldn't be decoded, kept for debugging.
+#[derive(Debug, Clone)]
+pub struct FailureSample {
+    ts: u64,
+    len: u32,
+    data: Vec<u8>,
+}
+
+impl FailureSample {
+    pub fn new(ts: u64, len: u32, data: Vec<u8>) -> Self {
+        FailureSample { ts, len, data }
+    }
+
+    /// The timestamp of the frame, in microseconds.
+    pub fn get_ts(&self) -> u64 { return self.ts }
+    /// The length of the frame on the wire.
+    pub fn get_len(&self) -> u32 { return self.len }
+    /// The captured bytes of the frame.
+    pub fn get_data(&self) -> &[u8] { return &self.data }
+}
+
+/// The packets that couldn't be decoded for the same reason, at the same layer.
+#[derive(Debug, Clone)]
+pub struct UnclassifiedTraffic {
+    layer: Layer,
+    reason: FailureReason,
+    packets: u64,
+    bytes: u64,
+    samples: Vec<FailureSample>,
+}
+
+impl UnclassifiedTraffic {
+    pub fn get_layer(&self) -> Layer { return self.layer }
+    pub fn get_reason(&self) -> &FailureReason { return &self.reason }
+    pub fn get_packets(&self) -> u64 { return self.packets }
+    /// The bytes of the frames on the wire.
+    pub fn get_bytes(&self) -> u64 { return self.bytes }
+    pub fn get_samples(&self) -> &[FailureSample] { return &self.samples }
+}
+
+/// The packets discarded by the decoders, grouped by layer and reason.
+#[derive(Debug, Default)]
+pub struct DecodeFailures {
+    failures: Mutex<HashMap<(Layer, FailureReason), UnclassifiedTraffic>>,
+    sampling: AtomicBool,
+}
+
+impl DecodeFailures {
+    pub fn new() -> Self {
+        DecodeFailures::default()
+    }
+
+    /// Returns true if the decoders should keep a copy of the frames that can't be decoded.
+    pub fn is_sampling(&self) -> bool {
+        self.sampling.load(Ordering::Relaxed)
+    }
+
+    /// Enables or disables the samples of the frames that can't be decoded.
+    pub fn set_sampling(&self, sampling: bool) {
+        self.sampling.store(sampling, Ordering::Relaxed);
+    }
+
+    /// Accounts a frame that couldn't be decoded, with its length on the wire and optionally a copy of it.
+    pub fn add(&self, error: &DecodeError, frame_len: u32, sample: Option<FailureSample>) {
+        let mut failures = self.failures.lock().unwrap();
+        let entry = failures.entry((error.layer, error.reason.clone())).or_insert_with(|| UnclassifiedTraffic {
+            layer: error.layer, reason: error.reason.clone(), packets: 0, bytes: 0, samples: Vec::new()
+        });
+        entry.packets += 1;
+        entry.bytes += frame_len as u64;
+        if let Some(sample) = sample {
+            if entry.samples.len() < MAX_FAILURE_SAMPLES {
+                entry.samples.push(sample);
+            }
+        }
+    }
+
+    /// Returns the failures, from the most frequent one.
+    pub fn snapshot(&self) -> Vec<UnclassifiedTraffic> {
+        let mut failures: Vec<UnclassifiedTraffic> = self.failures.lock().unwrap().values().cloned().collect();
+        failures.sort_by(|a, b| b.packets.cmp(&a.packets).then((a.layer, &a.reason).cmp(&(b.layer, &b.reason))));
+        failures
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc::sync_channel;
+    use crate::pipeline::{DecodeFailures, Delivery, FailureSample, OverflowPolicy, PipelineStats, MAX_FAILURE_SAMPLES};
+    use crate::pkt_parser::{DecodeError, EthernetHeader, FailureReason, Header, Ipv4Header, Layer};
+
+    #[test]
+    fn test_drop_newest() {
+        let stats = PipelineStats::new();
+        let (tx, rx) = sync_channel(2);
+        for i in 0..5 {
+            let expected = if i < 2 { Delivery::Queued } else { Delivery::Dropped };
+            assert_eq!(stats.send(&tx, i, OverflowPolicy::DropNewest), expected);
+        }
+        let counters = stats.snapshot();
+        assert_eq!(counters.get_captured(), 5);
+        assert_eq!(counters.get_queued(), 2);
+        assert_eq!(counters.get_dropped(), 3);
+        assert_eq!(counters.get_max_depth(), 2);
+
+        assert_eq!(rx.recv().unwrap(), 0);
+        stats.processed(true);
+        assert_eq!(rx.recv().unwrap(), 1);
+        stats.processed(false);
+        let counters = stats.snapshot();
+        assert_eq!(counters.get_processed(), 2);
+        assert_eq!(counters.get_decode_errors(), 1);
+        assert_eq!(counters.get_decoded(), 1);
+        assert_eq!(counters.get_depth(), 0);
+
+        drop(rx);
+        assert_eq!(stats.send(&tx, 5, OverflowPolicy::Block), Delivery::Disconnected);
+    }
+
+    #[test]
+    fn test_drop_rate() {
+        let stats = PipelineStats::new();
+        assert_eq!(stats.snapshot().get_drop_rate(), 0.0);
+        let (tx, _rx) = sync_channel(100);
+        for i in 0..90 {
+            stats.send(&tx, i, OverflowPolicy::Block);
+        }
+        stats.capture_stats("eth0", 50, 5, 0);
+        stats.capture_stats("eth1", 45, 3, 2);
+        let counters = stats.snapshot();
+        assert_eq!(counters.get_kernel_received(), 95);
+        assert_eq!(stats.interface_stats()[1].1.get_dropped(), 3);
+        assert_eq!(counters.get_lost(), 10);
+        assert_eq!(counters.get_drop_rate(), 10.0);
+    }
+
+    #[test]
+    fn test_decode_failures() {
+        let failures = DecodeFailures::new();
+        let arp = vec![255, 255, 255, 255, 255, 255, 80, 235, 113, 35, 142, 103, 8, 6, 0, 1, 8, 0, 6, 4, 0, 1];
+        let (result, _) = EthernetHeader::decode(vec![0; 10]);
+        failures.add(&result.unwrap_err(), 10, None);
+        for i in 0..(MAX_FAILURE_SAMPLES + 2) {
+            let error = DecodeError::unsupported(Layer::Network, "ARP".to_string(), "ARP".to_string());
+            failures.set_sampling(i % 2 == 0);
+            let sample = if failures.is_sampling() { Some(FailureSample::new(i as u64, 60, arp.clone())) } else { None };
+            failures.add(&error, 60, sample);
+        }
+        // ICMP over IPv4
+        let (result, _) = Ipv4Header::decode(vec![69, 0, 0, 28, 0, 0, 64, 0, 64, 1, 0, 0, 10, 0, 0, 1, 10, 0, 0, 2]);
+        failures.add(&result.unwrap_err(), 42, None);
+
+        let snapshot = failures.snapshot();
+        assert_eq!(snapshot.len(), 3);
+        assert_eq!(snapshot[0].get_layer(), Layer::Network);
+        assert_eq!(snapshot[0].get_reason().to_string(), "Unsupported ARP");
+        assert_eq!(snapshot[0].get_packets(), MAX_FAILURE_SAMPLES as u64 + 2);
+        assert_eq!(snapshot[0].get_bytes(), 60 * (MAX_FAILURE_SAMPLES as u64 + 2));
+        assert_eq!(snapshot[0].get_samples().len(), (MAX_FAILURE_SAMPLES + 2) / 2);
+        assert_eq!(snapshot[1].get_layer(), Layer::Link);
+        assert_eq!(*snapshot[1].get_reason(), FailureReason::Truncated);
+        assert_eq!(snapshot[2].get_reason().to_string(), "Unsupported ICMP");
+    }
+}
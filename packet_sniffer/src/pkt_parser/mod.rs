@@ -61,7 +61,10 @@ mod utils {
     }
 
     pub fn ipv6_address_to_string(address: &[u8]) -> String {
-        address.iter().hex_display().to_string().replace(" ", "")
+        // the same representation used by pcap for the device addresses, so they can be compared
+        let mut octets = [0u8; 16];
+        octets.copy_from_slice(&address[0..16]);
+        std::net::Ipv6Addr::from(octets).to_string()
     }
 }
 
@@ -72,9 +75,29 @@ pub trait Header: Debug + Clone {
 
 /// A custom error to be returned by a decode function. Some common error can be "next protocol not defined", or "cannot parse an header" because of
 /// damaged packet, so it can be good to discard the packet.
+/// Besides the message, it tells at which layer the decoding stopped and why, so that the discarded packets can be counted.
 #[derive(Debug, Clone)]
 pub struct DecodeError{
-    pub msg: String
+    pub msg: String,
+    pub layer: Layer,
+    pub reason: FailureReason,
+}
+
+impl DecodeError {
+    /// The packet is shorter than the header, or than the length declared by the header.
+    pub fn truncated(layer: Layer, msg: String) -> Self {
+        DecodeError { msg, layer, reason: FailureReason::Truncated }
+    }
+
+    /// The header contains an invalid value, e.g. a length smaller than the minimum.
+    pub fn invalid(layer: Layer, msg: String) -> Self {
+        DecodeError { msg, layer, reason: FailureReason::InvalidHeader }
+    }
+
+    /// The packet carries a protocol that can't be decoded, e.g. ARP or ICMP.
+    pub fn unsupported(layer: Layer, protocol: String, msg: String) -> Self {
+        DecodeError { msg, layer, reason: FailureReason::Unsupported(protocol) }
+    }
 }
 
 impl Display for DecodeError {
@@ -83,6 +106,81 @@ impl Display for DecodeError {
     }
 }
 
+/// The layer of the stack of the header that couldn't be decoded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Layer {
+    Link,
+    Network,
+    Transport,
+}
+
+impl Display for Layer {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Layer::Link => write!(f, "Link (L2)"),
+            Layer::Network => write!(f, "Network (L3)"),
+            Layer::Transport => write!(f, "Transport (L4)"),
+        }
+    }
+}
+
+/// Why a packet couldn't be decoded.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum FailureReason {
+    Truncated,
+    InvalidHeader,
+    /// The name (or the number) of the protocol that is not supported.
+    Unsupported(String),
+}
+
+impl Display for FailureReason {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            FailureReason::Truncated => write!(f, "Truncated"),
+            FailureReason::InvalidHeader => write!(f, "Invalid header"),
+            FailureReason::Unsupported(protocol) => write!(f, "Unsupported {}", protocol),
+        }
+    }
+}
+
+/// Returns the name of the most common EtherTypes that can't be decoded.
+pub fn ether_type_name(ether_type: u16) -> String {
+    match ether_type {
+        0x0806 => "ARP".to_string(),
+        0x8035 => "RARP".to_string(),
+        0x8100 => "VLAN (802.1Q)".to_string(),
+        0x88a8 => "QinQ (802.1ad)".to_string(),
+        0x8847 | 0x8848 => "MPLS".to_string(),
+        0x888e => "EAPOL".to_string(),
+        0x88cc => "LLDP".to_string(),
+        0x88f7 => "PTP".to_string(),
+        other if other < 0x0600 => "IEEE 802.3 frame".to_string(),
+        other => format!("EtherType 0x{:04x}", other),
+    }
+}
+
+/// Returns the name of the most common IP protocols that can't be decoded.
+pub fn ip_protocol_name(protocol: u8) -> String {
+    match protocol {
+        0 => "IPv6 Hop-by-Hop".to_string(),
+        1 => "ICMP".to_string(),
+        2 => "IGMP".to_string(),
+        4 => "IP in IP".to_string(),
+        41 => "IPv6 in IP".to_string(),
+        43 => "IPv6 Routing".to_string(),
+        44 => "IPv6 Fragment".to_string(),
+        47 => "GRE".to_string(),
+        50 => "ESP".to_string(),
+        51 => "AH".to_string(),
+        58 => "ICMPv6".to_string(),
+        89 => "OSPF".to_string(),
+        103 => "PIM".to_string(),
+        112 => "VRRP".to_string(),
+        132 => "SCTP".to_string(),
+        other => format!("IP protocol {}", other),
+    }
+}
+
 
 /// An Enum that describe the packet direction
 #[derive(Debug, Clone, PartialEq)]
@@ -122,7 +220,7 @@ pub struct EthernetHeader {
 impl Header for EthernetHeader {
     fn decode(data: Vec<u8>) -> (Result<Self, DecodeError>, Vec<u8>) {
         let len = data.len();
-        if len < 14 { return (Err(DecodeError{msg: "Cannot decode an ethernet packet because is not long enough.".to_string()}), data) }
+        if len < 14 { return (Err(DecodeError::truncated(Layer::Link, "Cannot decode an ethernet packet because is not long enough.".to_string())), data) }
         // Extracting data
         let eth_header = &data[0..14];
         let ether_type_vec = &eth_header[12..14];
@@ -134,7 +232,7 @@ impl Header for EthernetHeader {
             0x0806 => EtherType::ARP,
             0x86DD => EtherType::Ipv6,
             val => return (
-                Err(DecodeError{msg: format!("Cannot get the correct ether type, received 0x{:x}", val).to_string()}),
+                Err(DecodeError::unsupported(Layer::Network, ether_type_name(val), format!("Cannot get the correct ether type, received 0x{:x}", val))),
                 data
             )
         };
@@ -183,24 +281,31 @@ impl Header for Ipv4Header {
     fn decode(data: Vec<u8>) -> (Result<Self, DecodeError>, Vec<u8>) {
         let len = data.len();
         if len < 20 {
-            return (Err(DecodeError{msg: "Cannot decode ipv4 packet because is not long enough.".to_string()}), data)
+            return (Err(DecodeError::truncated(Layer::Network, "Cannot decode ipv4 packet because is not long enough.".to_string())), data)
         }
         let header_len = (data[0] & 0x0f ) as usize * 4;
+        if header_len < 20 || header_len > len {
+            return (Err(DecodeError::invalid(Layer::Network, format!("Invalid ipv4 header length, received {} bytes", header_len))), data)
+        }
 
         let protocol = match &data[9] {
             0x06 => Protocol::TCP,
             0x11 => Protocol::UDP,
             value => return (
-                Err(DecodeError{ msg: format!("Unable to identify level 4 protocol. Received 0x{:x}", value) }),
+                Err(DecodeError::unsupported(Layer::Transport, ip_protocol_name(*value), format!("Unable to identify level 4 protocol. Received 0x{:x}", value))),
                 data
             )
         };
 
+        // the total length field allows to drop the ethernet padding of short frames
+        let total_len = (((data[2] as u16) << 8) | data[3] as u16) as usize;
+        let end = if total_len >= header_len && total_len <= len { total_len } else { len };
+
         let src_address = utils::ipv4_address_to_string(&data[12..16]);
         let dest_address = utils::ipv4_address_to_string(&data[16..20]);
         (
             Ok(Ipv4Header{src: src_address, dest: dest_address, protocol}),
-            Vec::from(&data[header_len..len])
+            Vec::from(&data[header_len..end])
         )
     }
 }
@@ -224,21 +329,26 @@ pub struct Ipv6Header {
 impl Header for Ipv6Header {
     fn decode(data: Vec<u8>) -> (Result<Self, DecodeError>, Vec<u8>) {
         let len = data.len();
-        let protocol = match &data[9] {
+        if len < 40 {
+            return (Err(DecodeError::truncated(Layer::Network, "Cannot decode ipv6 packet because is not long enough.".to_string())), data)
+        }
+        let protocol = match &data[6] {
             0x06 => Protocol::TCP,
             0x11 => Protocol::UDP,
-            _ => Protocol::Unknown
-            /*return (
-                Err(DecodeError{ msg: format!("Unable to identify level 4 protocol. Received 0x{:x}", value) }),
+            value => return (
+                Err(DecodeError::unsupported(Layer::Transport, ip_protocol_name(*value), format!("Unable to identify level 4 protocol. Received 0x{:x}", value))),
                 data
-            )*/
+            )
         };
+        // as for ipv4, the payload length allows to drop the ethernet padding
+        let payload_len = (((data[4] as u16) << 8) | data[5] as u16) as usize;
+        let end = if 40 + payload_len <= len { 40 + payload_len } else { len };
 
-        let src_address = utils::ipv6_address_to_string(&data[8..20]);
-        let dest_address = utils::ipv6_address_to_string(&data[20..36]);
+        let src_address = utils::ipv6_address_to_string(&data[8..24]);
+        let dest_address = utils::ipv6_address_to_string(&data[24..40]);
         (
             Ok(Ipv6Header{src: src_address, dest: dest_address, protocol}),
-            Vec::from(&data[40..len])
+            Vec::from(&data[40..end])
         )
     }
 }
@@ -265,6 +375,9 @@ impl UDPHeader {
 
 impl Header for UDPHeader {
     fn decode(data: Vec<u8>) -> (Result<Self, DecodeError>, Vec<u8>) {
+        if data.len() < 8 {
+            return (Err(DecodeError::truncated(Layer::Transport, "Cannot decode udp packet because is not long enough.".to_string())), data)
+        }
         let src = ((data[0] as u16) << 8) | data[1] as u16;
         let dest = ((data[2] as u16) << 8) | data[3] as u16;
         (
@@ -279,22 +392,75 @@ impl Header for UDPHeader {
 pub struct TCPHeader {
     dest: u16,
     src: u16,
+    seq: u32,
+    ack: u32,
+    flags: u8,
+    window: u16,
 }
 
 impl Header for TCPHeader {
     fn decode(data: Vec<u8>) -> (Result<Self, DecodeError>, Vec<u8>) {
+        let len = data.len();
+        if len < 20 {
+            return (Err(DecodeError::truncated(Layer::Transport, "Cannot decode tcp packet because is not long enough.".to_string())), data)
+        }
+        let header_len = (data[12] >> 4) as usize * 4;
+        if header_len < 20 || header_len > len {
+            return (Err(DecodeError::invalid(Layer::Transport, format!("Invalid tcp data offset, received {} bytes", header_len))), data)
+        }
         let src = ((data[0] as u16) << 8) | data[1] as u16;
         let dest = ((data[2] as u16) << 8) | data[3] as u16;
+        let seq = u32::from_be_bytes([data[4], data[5], data[6], data[7]]);
+        let ack = u32::from_be_bytes([data[8], data[9], data[10], data[11]]);
+        let flags = data[13];
+        let window = ((data[14] as u16) << 8) | data[15] as u16;
         (
-            Ok(TCPHeader{dest, src}),
-            Vec::from(&data[20..])
+            Ok(TCPHeader{dest, src, seq, ack, flags, window}),
+            Vec::from(&data[header_len..])
         )
     }
 }
 
 impl TCPHeader {
+    pub const FIN: u8 = 0x01;
+    pub const SYN: u8 = 0x02;
+    pub const RST: u8 = 0x04;
+    pub const PSH: u8 = 0x08;
+    pub const ACK: u8 = 0x10;
+
     pub fn get_src_port(&self) -> u16 { return self.src }
     pub fn get_dest_port(&self) -> u16 { return self.dest }
+    pub fn get_seq(&self) -> u32 { return self.seq }
+    pub fn get_ack(&self) -> u32 { return self.ack }
+    pub fn get_flags(&self) -> u8 { return self.flags }
+    pub fn get_window(&self) -> u16 { return self.window }
+}
+
+/// The TCP fields of a packet needed to analyse the behaviour of a connection (RTT, retransmissions ...).
+/// The local port is the one of the sniffed host, so that different connections towards the same remote
+/// endpoint can be told apart.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TCPSegment {
+    local_port: u16,
+    seq: u32,
+    ack: u32,
+    flags: u8,
+    window: u16,
+    payload_len: usize,
+}
+
+impl TCPSegment {
+    pub fn new(header: &TCPHeader, local_port: u16, payload_len: usize) -> Self {
+        TCPSegment { local_port, seq: header.seq, ack: header.ack, flags: header.flags, window: header.window, payload_len }
+    }
+
+    pub fn get_local_port(&self) -> u16 { return self.local_port }
+    pub fn get_seq(&self) -> u32 { return self.seq }
+    pub fn get_ack(&self) -> u32 { return self.ack }
+    pub fn get_flags(&self) -> u8 { return self.flags }
+    pub fn get_window(&self) -> u16 { return self.window }
+    pub fn get_payload_len(&self) -> usize { return self.payload_len }
+    pub fn has_flag(&self, flag: u8) -> bool { return self.flags & flag != 0 }
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -334,12 +500,47 @@ pub struct PacketInfo {
     port: u16,
     protocol: Protocol,
     byte_transmitted: usize,
-    ts: TimeVal
+    ts: TimeVal,
+    direction: Direction,
+    tcp: Option<TCPSegment>,
+    frame_len: usize,
+    sni: Option<String>,
+    interface: Option<String>,
 }
 
 impl PacketInfo {
     pub fn new(address: String, port: u16, protocol: Protocol, byte_transmitted: usize, ts: TimeVal) -> Self {
-        PacketInfo { address, port, protocol, byte_transmitted, ts}
+        PacketInfo { address, port, protocol, byte_transmitted, ts, direction: Direction::Received, tcp: None, frame_len: 0, sni: None, interface: None }
+    }
+
+    /// Sets the direction of the packet with respect to the sniffed interface.
+    pub fn with_direction(mut self, direction: Direction) -> Self {
+        self.direction = direction;
+        self
+    }
+
+    /// Sets the length of the whole frame on the wire, headers included.
+    pub fn with_frame_len(mut self, frame_len: usize) -> Self {
+        self.frame_len = frame_len;
+        self
+    }
+
+    /// Attaches the TCP fields of the packet, used by the flow analysis.
+    pub fn with_tcp_segment(mut self, segment: TCPSegment) -> Self {
+        self.tcp = Some(segment);
+        self
+    }
+
+    /// Sets the server name requested by the TLS ClientHello carried by the packet.
+    pub fn with_sni(mut self, sni: Option<String>) -> Self {
+        self.sni = sni;
+        self
+    }
+
+    /// Sets the name of the interface on which the packet has been captured.
+    pub fn with_interface(mut self, interface: String) -> Self {
+        self.interface = Some(interface);
+        self
     }
 
     pub fn get_address(&self) -> String { return self.address.clone() }
@@ -347,6 +548,81 @@ impl PacketInfo {
     pub fn get_protocol(&self) -> Protocol { return self.protocol.clone() }
     pub fn get_byte_transmitted(&self) -> usize { return self.byte_transmitted }
     pub fn get_time_stamp(&self) -> TimeVal { return self.ts.clone() }
+    pub fn get_direction(&self) -> Direction { return self.direction.clone() }
+    pub fn get_tcp_segment(&self) -> Option<TCPSegment> { return self.tcp.clone() }
+    pub fn get_frame_len(&self) -> usize { return self.frame_len }
+    pub fn get_sni(&self) -> Option<String> { return self.sni.clone() }
+    pub fn get_interface(&self) -> Option<String> { return self.interface.clone() }
+}
+
+/// Extracts the Server Name Indication from a TCP payload that starts with a TLS ClientHello.
+/// Returns None for any other payload, or if the ClientHello is split in more segments.
+pub fn parse_tls_sni(payload: &[u8]) -> Option<String> {
+    let u16_at = |data: &[u8], i: usize| -> Option<usize> {
+        Some(((*data.get(i)? as usize) << 8) | *data.get(i + 1)? as usize)
+    };
+    // TLS record of type handshake, containing a ClientHello
+    if payload.len() < 9 || payload[0] != 0x16 || payload[1] != 0x03 || payload[5] != 0x01 {
+        return None
+    }
+    let hello = payload.get(9..)?;
+    // client version and random, then session id, cipher suites and compression methods
+    let mut i = 34;
+    i += 1 + *hello.get(i)? as usize;
+    i += 2 + u16_at(hello, i)?;
+    i += 1 + *hello.get(i)? as usize;
+    let extensions_end = (i + 2 + u16_at(hello, i)?).min(hello.len());
+    i += 2;
+    while i + 4 <= extensions_end {
+        let ext_type = u16_at(hello, i)?;
+        let ext_len = u16_at(hello, i + 2)?;
+        let ext = hello.get(i + 4..i + 4 + ext_len)?;
+        if ext_type == 0 {
+            // server name list: the first entry of type host name
+            if ext.len() < 5 || ext[2] != 0 {
+                return None
+            }
+            let name_len = u16_at(ext, 3)?;
+            let name = ext.get(5..5 + name_len)?;
+            return String::from_utf8(name.to_vec()).ok()
+        }
+        i += 4 + ext_len;
+    }
+    None
+}
+
+/// Returns the key of the flow a raw frame belongs to, that is the remote address and port, without decoding it.
+/// The direction is found against the addresses of the device that captured the frame, like the decoder does, so the
+/// key is the same used by the flow table. Frames that are not TCP or UDP over IP have no key.
+pub fn flow_key(data: &[u8], device: &Device) -> Option<(String, u16)> {
+    use std::net::IpAddr;
+
+    if data.len() < 14 {
+        return None
+    }
+    let ip = &data[14..];
+    let (protocol, src, dest, l4) = match (data[12], data[13]) {
+        (0x08, 0x00) if ip.len() >= 20 => {
+            let header_len = (ip[0] & 0x0f) as usize * 4;
+            let src: [u8; 4] = ip[12..16].try_into().ok()?;
+            let dest: [u8; 4] = ip[16..20].try_into().ok()?;
+            (ip[9], IpAddr::from(src), IpAddr::from(dest), &ip[header_len.min(ip.len())..])
+        },
+        (0x86, 0xdd) if ip.len() >= 40 => {
+            let src: [u8; 16] = ip[8..24].try_into().ok()?;
+            let dest: [u8; 16] = ip[24..40].try_into().ok()?;
+            (ip[6], IpAddr::from(src), IpAddr::from(dest), &ip[40..])
+        },
+        _ => return None
+    };
+    if (protocol != 0x06 && protocol != 0x11) || l4.len() < 4 {
+        return None
+    }
+    if device.addresses.iter().any(|a| a.addr == src) {
+        Some((dest.to_string(), u16::from_be_bytes([l4[2], l4[3]])))
+    } else {
+        Some((src.to_string(), u16::from_be_bytes([l4[0], l4[1]])))
+    }
 }
 
 #[cfg(test)]
@@ -363,6 +639,40 @@ mod tests {
         assert_eq!(ethernet_header.get_ether_type(), EtherType::Ipv6);
     }
 
+    #[test]
+    fn test_ipv6_packet() {
+        let data = vec![51, 51, 0, 1, 0, 2, 80, 235, 113, 35, 142, 103, 134, 221, 96, 9, 31, 94, 0, 103, 17, 1, 254, 128, 0, 0, 0, 0, 0, 0, 5, 194, 180, 157, 9, 91, 63, 25, 255, 2, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 0, 2, 2, 34, 2, 35, 0, 103, 0, 211, 1, 228, 89, 38, 0, 8, 0, 2, 12, 31, 0, 1, 0, 14, 0, 1, 0, 1, 42, 94, 58, 157, 80, 235, 113, 35, 142, 103, 0, 3, 0, 12, 10, 80, 235, 113, 0, 0, 0, 0, 0, 0, 0, 0, 0, 39, 0, 17, 0, 15, 68, 69, 83, 75, 84, 79, 80, 45, 83, 86, 65, 65, 84, 84, 52, 0, 16, 0, 14, 0, 0, 1, 55, 0, 8, 77, 83, 70, 84, 32, 53, 46, 48, 0, 6, 0, 8, 0, 17, 0, 23, 0, 24, 0, 39];
+        let (_ethernet_header_res, eth_payload) = EthernetHeader::decode(data);
+        let (ipv6_header_res, ipv6_payload) = Ipv6Header::decode(eth_payload);
+        let ipv6_header = ipv6_header_res.unwrap();
+        assert_eq!(ipv6_header.get_protocol(), Protocol::UDP);
+        assert_eq!(ipv6_header.get_src_address(), "fe80::5c2:b49d:95b:3f19".to_string());
+        assert_eq!(ipv6_header.get_dest_address(), "ff02::1:2".to_string());
+        let (udp_header_res, _payload) = UDPHeader::decode(ipv6_payload);
+        let udp_header = udp_header_res.unwrap();
+        assert_eq!(udp_header.get_src_port(), 546);
+        assert_eq!(udp_header.get_dest_port(), 547);
+    }
+
+    #[test]
+    fn test_short_headers() {
+        // an ipv4 header that declares less than 20 bytes
+        let (result, _) = Ipv4Header::decode(vec![0x44, 0, 0, 20, 0, 0, 64, 0, 64, 17, 0, 0, 10, 0, 0, 1, 10, 0, 0, 2]);
+        assert_eq!(result.unwrap_err().reason, FailureReason::InvalidHeader);
+        // the ethernet padding after the total length is not part of the payload
+        let mut data = vec![0x45, 0, 0, 28, 0, 0, 64, 0, 64, 17, 0, 0, 10, 0, 0, 1, 10, 0, 0, 2, 0, 53, 0, 53, 0, 8, 0, 0];
+        data.extend([0; 18]);
+        let (result, payload) = Ipv4Header::decode(data);
+        assert!(result.is_ok());
+        assert_eq!(payload.len(), 8);
+        let (result, _) = Ipv6Header::decode(vec![0x60; 39]);
+        let error = result.unwrap_err();
+        assert_eq!((error.layer, error.reason), (Layer::Network, FailureReason::Truncated));
+        let (result, _) = UDPHeader::decode(vec![0, 53, 0, 53]);
+        let error = result.unwrap_err();
+        assert_eq!((error.layer, error.reason), (Layer::Transport, FailureReason::Truncated));
+    }
+
     #[test]
     #[should_panic]
     fn test_empty_packet() {
@@ -394,6 +704,46 @@ mod tests {
         assert_eq!(udp_header.get_dest_port(), 59968);
     }
 
+    #[test]
+    fn test_tls_sni() {
+        let mut hello = vec![0x03, 0x03];
+        hello.extend([0u8; 32]);
+        hello.push(0); // session id
+        hello.extend([0, 2, 0x13, 0x01]); // cipher suites
+        hello.extend([1, 0]); // compression methods
+        let name = b"www.example.com";
+        let mut sni = vec![0, 0, 0, (name.len() + 5) as u8, 0, (name.len() + 3) as u8, 0, 0, name.len() as u8];
+        sni.extend(name);
+        let other = vec![0, 0x2b, 0, 3, 2, 0x03, 0x04];
+        hello.extend([0, (other.len() + sni.len()) as u8]);
+        hello.extend(other);
+        hello.extend(sni);
+        let mut payload = vec![0x16, 0x03, 0x01, 0, (hello.len() + 4) as u8, 0x01, 0, 0, hello.len() as u8];
+        payload.extend(hello);
+
+        assert_eq!(parse_tls_sni(&payload), Some("www.example.com".to_string()));
+        assert_eq!(parse_tls_sni(&payload[..payload.len() - 4]), None);
+        assert_eq!(parse_tls_sni(b"GET / HTTP/1.1"), None);
+    }
+
+    #[test]
+    fn test_flow_key() {
+        let data = vec![152, 0, 106, 4, 85, 32, 80, 235, 113, 35, 142, 103, 8, 0, 69, 0, 0, 40, 134, 79, 64, 0, 128, 6, 0, 0, 192, 168, 1, 21, 149, 154, 167, 92, 220, 49, 1, 187, 135, 216, 62, 67, 24, 80, 57, 27, 80, 20, 0, 0, 254, 206, 0, 0];
+        let mut reply = data.clone();
+        reply[26..30].copy_from_slice(&data[30..34]);
+        reply[30..34].copy_from_slice(&data[26..30]);
+        reply[34..36].copy_from_slice(&data[36..38]);
+        reply[36..38].copy_from_slice(&data[34..36]);
+        let mut device = Device::from("eth0");
+        device.addresses.push(pcap::Address { addr: "192.168.1.21".parse().unwrap(), netmask: None, broadcast_addr: None, dst_addr: None });
+        // both the directions of a connection have the key of the remote endpoint
+        assert_eq!(flow_key(&data, &device), Some(("149.154.167.92".to_string(), 443)));
+        assert_eq!(flow_key(&reply, &device), flow_key(&data, &device));
+        // like the decoder, without the local address the source is the remote endpoint
+        assert_eq!(flow_key(&data, &Device::from("eth0")), Some(("192.168.1.21".to_string(), 56369)));
+        assert_eq!(flow_key(&data[..20], &device), None);
+    }
+
     #[test]
     fn test_whole_packet_2() {
         let data = vec![152, 0, 106, 4, 85, 32, 80, 235, 113, 35, 142, 103, 8, 0, 69, 0, 0, 40, 134, 79, 64, 0, 128, 6, 0, 0, 192, 168, 1, 21, 149, 154, 167, 92, 220, 49, 1, 187, 135, 216, 62, 67, 24, 80, 57, 27, 80, 20, 0, 0, 254, 206, 0, 0];
@@ -415,5 +765,9 @@ mod tests {
 
         assert_eq!(tcp_header.get_src_port(), 56369);
         assert_eq!(tcp_header.get_dest_port(), 443);
+        assert_eq!(tcp_header.get_seq(), 0x87d83e43);
+        assert_eq!(tcp_header.get_ack(), 0x1850391b);
+        assert_eq!(tcp_header.get_flags(), TCPHeader::ACK | TCPHeader::RST);
+        assert_eq!(tcp_header.get_window(), 0);
     }
 }
\ No newline at end of file
@@ -0,0 +1,165 @@
+//! csv
+//! This module writes the flows of a report as CSV, one row for every flow with all its statistics, so that the
+//! reports can be loaded in a spreadsheet or a dataframe. The closed flows kept in the log have their own rows, with
+//! the reason of the closing in the `closed` column. In the periodic reports every section adds its rows under the
+//! same header, with the time of the section in the `interval` column and, in delta mode, the traffic of the interval
+//! in the `interval_packets` and `interval_bytes` columns.
+
+use std::collections::HashMap;
+use crate::flow::{service_name, FlowKey, FlowStats};
+use crate::report::json::format_timestamp;
+use crate::report::{ReportData, ReportFormatter};
+
+/// The columns of the statistics of a flow, in the order of the rows.
+const COLUMNS: [&str; 35] = ["address", "port", "protocol", "service", "sni", "closed", "packets", "bytes",
+    "packets_transmitted", "bytes_transmitted", "packets_received", "bytes_received", "first_ts", "last_ts",
+    "first_time", "last_time", "duration_us", "interfaces", "frame_size_min", "frame_size_max", "frame_size_mean",
+    "frame_size_p50", "frame_size_p95", "inter_arrival_us_mean", "inter_arrival_us_p50", "inter_arrival_us_p95",
+    "rtt_us", "rtt_min_us", "rtt_max_us", "rtt_samples", "handshake_rtt_us", "retransmissions", "duplicate_acks",
+    "out_of_order", "zero_window"];
+
+/// The columns of the periodic reports, before the ones of the statistics.
+const INTERVAL_COLUMNS: [&str; 3] = ["interval", "interval_packets", "interval_bytes"];
+
+/// The formatter of the CSV reports, the periodic reports have the columns of the interval.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CsvFormatter;
+
+impl ReportFormatter for CsvFormatter {
+    fn heading(&self, _data: &ReportData, periodic: bool) -> String {
+        header(periodic)
+    }
+
+    fn report(&self, data: &ReportData) -> String {
+        rows(data, false)
+    }
+
+    fn section(&self, data: &ReportData) -> String {
+        rows(data, true)
+    }
+}
+
+/// Returns the header of the CSV, with the columns of the interval first for the periodic reports.
+pub fn header(interval: bool) -> String {
+    let mut columns = if interval { INTERVAL_COLUMNS.to_vec() } else { Vec::new() };
+    columns.extend(COLUMNS);
+    let mut header = columns.join(",");
+    header.push('\n');
+    header
+}
+
+/// Returns the rows of the flows of a report. With the traffic of an interval (delta mode) only the flows that have
+/// exchanged packets in the interval are written, otherwise all the flows collected. The counters of the statistics
+/// are always the ones of the whole flow, while the packets and the bytes of the interval are written on the first
+/// row of every flow key, so that they can be summed over the rows and the sections.
+pub fn rows(data: &ReportData, interval: bool) -> String {
+    let flows = data.get_flows();
+    let mut finished = flows.get_finished_flows();
+    let mut live = flows.sorted_flows(data.get_sort_key());
+    let mut traffic: HashMap<&FlowKey, (u64, usize)> = HashMap::new();
+    if let Some(delta) = data.get_interval() {
+        traffic = delta.get_flows().iter().map(|flow| (flow.get_key(), (flow.get_packets(), flow.get_bytes()))).collect();
+        let start = delta.get_start().timestamp_micros().max(0) as u64;
+        live.retain(|(key, _)| traffic.contains_key(key));
+        finished.retain(|flow| traffic.contains_key(flow.get_key()) && flow.get_stats().get_last_ts() >= start);
+    }
+
+    let label = if interval { Some(data.get_time().to_rfc3339_opts(chrono::SecondsFormat::Micros, false)) } else { None };
+    let mut csv = String::new();
+    for (key, stats) in live {
+        let interval = label.as_deref().map(|label| (label, traffic.remove(key)));
+        csv.push_str(row(interval, key, stats, None).as_str());
+    }
+    for flow in &finished {
+        let interval = label.as_deref().map(|label| (label, traffic.remove(flow.get_key())));
+        csv.push_str(row(interval, flow.get_key(), flow.get_stats(), Some(flow.get_reason().to_string())).as_str());
+    }
+    csv
+}
+
+/// Returns the row of a flow, the values that can't be measured are left empty. The periodic reports give the time of
+/// the section and, in delta mode, the packets and the bytes of the flow in the interval.
+pub fn row(interval: Option<(&str, Option<(u64, usize)>)>, key: &FlowKey, stats: &FlowStats, closed: Option<String>) -> String {
+    let protocol = stats.get_protocol();
+    let interfaces = stats.get_interfaces().iter().map(|(name, (packets, bytes))| format!("{}:{}:{}", name, packets, bytes))
+        .collect::<Vec<String>>().join(";");
+    let sizes = stats.get_frame_sizes();
+    let inter_arrival = stats.get_inter_arrival_times();
+    let tcp = stats.get_tcp_stats();
+    let mut fields = Vec::new();
+    if let Some((label, traffic)) = interval {
+        fields.extend([label.to_string(), optional(traffic.map(|t| t.0)), optional(traffic.map(|t| t.1 as u64))]);
+    }
+    fields.extend([key.0.clone(), key.1.to_string(), protocol.to_string(), service_name(key.1, &protocol).unwrap_or_default().to_string(),
+        stats.get_sni().unwrap_or_default(), closed.unwrap_or_default(), stats.get_packets().to_string(),
+        stats.get_bytes().to_string(), stats.get_transmitted().0.to_string(), stats.get_transmitted().1.to_string(),
+        stats.get_received().0.to_string(), stats.get_received().1.to_string(), stats.get_first_ts().to_string(),
+        stats.get_last_ts().to_string(), format_timestamp(stats.get_first_ts()), format_timestamp(stats.get_last_ts()),
+        stats.get_last_ts().saturating_sub(stats.get_first_ts()).to_string(), interfaces, optional(sizes.get_min()),
+        optional(sizes.get_max()), optional(sizes.get_mean()), optional(sizes.percentile(50.0)),
+        optional(sizes.percentile(95.0)), optional(inter_arrival.get_mean()), optional(inter_arrival.percentile(50.0)),
+        optional(inter_arrival.percentile(95.0)), optional(tcp.and_then(|tcp| tcp.get_rtt())),
+        optional(tcp.and_then(|tcp| tcp.get_rtt_min())), optional(tcp.and_then(|tcp| tcp.get_rtt_max())),
+        optional(tcp.map(|tcp| tcp.get_rtt_samples())), optional(tcp.and_then(|tcp| tcp.get_handshake_rtt())),
+        optional(tcp.map(|tcp| tcp.get_retransmissions())), optional(tcp.map(|tcp| tcp.get_duplicate_acks())),
+        optional(tcp.map(|tcp| tcp.get_out_of_order())), optional(tcp.map(|tcp| tcp.get_zero_window()))]);
+    let mut row = fields.iter().map(|field| escape(field)).collect::<Vec<String>>().join(",");
+    row.push('\n');
+    row
+}
+
+fn optional(value: Option<u64>) -> String {
+    value.map(|value| value.to_string()).unwrap_or_default()
+}
+
+/// Quotes a field that contains a separator, a quote or a line break, doubling its quotes.
+fn escape(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Local;
+    use crate::flow::SortKey;
+    use crate::pkt_parser::{PacketInfo, Protocol, TimeVal};
+    use crate::report::{IntervalData, ReportData};
+    use crate::report::csv::{escape, header, rows};
+
+    #[test]
+    fn test_rows() {
+        let mut flows = ReportData::test_flows();
+        let columns = header(false).trim_end().split(',').count();
+        let csv = rows(&ReportData::for_test(flows.clone(), None), false);
+        let lines: Vec<Vec<&str>> = csv.lines().map(|line| line.split(',').collect()).collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines.iter().all(|line| line.len() == columns));
+        assert_eq!(&lines[0][..8], &["10.0.0.1", "443", "TCP", "https", "", "", "2", "1500"]);
+        assert_eq!(&lines[0][8..13], &["1", "500", "1", "1000", "1000000"]);
+        assert_eq!(lines[0][16], "250000");
+        assert_eq!(lines[1][26..], vec![""; 9]);
+
+        // in delta mode only the flows of the interval are written, with the time of the section
+        let previous = flows.clone();
+        flows.update(&PacketInfo::new("10.0.0.2".to_string(), 53, Protocol::UDP, 80, TimeVal::from(2_000_000)));
+        let now = Local::now();
+        let interval = IntervalData::new(&previous, &flows, now - chrono::Duration::seconds(2), now, SortKey::Bytes);
+        let csv = rows(&ReportData::for_test(flows, Some(interval)), true);
+        let lines: Vec<Vec<&str>> = csv.lines().map(|line| line.split(',').collect()).collect();
+        assert_eq!(lines.len(), 1);
+        assert_eq!(lines[0].len(), header(true).trim_end().split(',').count());
+        assert_eq!(&lines[0][1..5], &["1", "80", "10.0.0.2", "53"]);
+        // the statistics are still the ones of the whole flow
+        assert_eq!(&lines[0][9..11], &["2", "160"]);
+    }
+
+    #[test]
+    fn test_escape() {
+        assert_eq!(escape("example.com"), "example.com");
+        assert_eq!(escape("a,b"), "\"a,b\"");
+        assert_eq!(escape("say \"hi\""), "\"say \"\"hi\"\"\"");
+    }
+}
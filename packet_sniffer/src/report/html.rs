@@ -0,0 +1,278 @@
+//! html
+//! This module writes the data of a report as a single static HTML page, to share the report outside the terminal.
+//! The page doesn't load anything from the network: the style and the script that sorts the tables are embedded, and
+//! the charts (the protocol share, the top talkers and the throughput over time) are drawn as inline SVG.
+
+use std::f64::consts::PI;
+use std::fmt::Write;
+use chrono::SecondsFormat;
+use crate::flow::{service_name, FlowKey, FlowStats, RankEntry, SortKey};
+use crate::report::json::format_timestamp;
+use crate::report::{ReportData, ReportFormatter};
+use crate::stats::{format_duration, format_time, Throughput};
+
+/// The colours of the slices and of the bars, used in turn.
+const PALETTE: [&str; 8] = ["#4e79a7", "#f28e2b", "#e15759", "#76b7b2", "#59a14f", "#edc948", "#b07aa1", "#9c755f"];
+
+const STYLE: &str = "body{font-family:sans-serif;margin:2em;color:#222}h1{margin-bottom:0}.muted{color:#666}
+table{border-collapse:collapse;margin:1em 0;font-size:0.9em}th,td{border:1px solid #ccc;padding:4px 8px;text-align:left}
+th{background:#f0f0f0}table.sortable th{cursor:pointer}table.sortable th:after{content:' \\2195';color:#999}
+td.n{text-align:right}.charts{display:flex;flex-wrap:wrap;gap:2em}.chart{margin:1em 0}
+.warning{background:#fdecea;border:1px solid #e15759;padding:8px}.legend span{display:inline-block;width:10px;height:10px;margin-right:4px}";
+
+/// Sorts a table by the column of the clicked header, numerically when all its values are numbers.
+const SCRIPT: &str = "document.querySelectorAll('table.sortable').forEach(function(table){
+table.querySelectorAll('th').forEach(function(th,column){var ascending=false;th.addEventListener('click',function(){
+ascending=!ascending;var body=table.tBodies[0];var rows=Array.prototype.slice.call(body.rows);
+var value=function(row){var cell=row.cells[column];return cell.hasAttribute('data-value')?cell.getAttribute('data-value'):cell.textContent;};
+var numeric=rows.every(function(row){return value(row)===''||!isNaN(value(row));});
+rows.sort(function(a,b){var x=value(a),y=value(b);var order=numeric?(Number(x)-Number(y)):x.localeCompare(y);return ascending?order:-order;});
+rows.forEach(function(row){body.appendChild(row);});});});});";
+
+/// The formatter of the HTML reports. A page can't be appended to another, so the periodic reports rewrite the page
+/// at every interval.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct HtmlFormatter;
+
+impl ReportFormatter for HtmlFormatter {
+    fn report(&self, data: &ReportData) -> String {
+        document(data)
+    }
+
+    fn section(&self, data: &ReportData) -> String {
+        document(data)
+    }
+
+    fn is_appendable(&self) -> bool {
+        false
+    }
+}
+
+/// Returns the HTML page of a report.
+pub fn document(data: &ReportData) -> String {
+    let mut html = String::new();
+    html.push_str("<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n<title>Packet sniffer report</title>\n");
+    let _ = writeln!(html, "<style>{}</style>\n</head>\n<body>", STYLE);
+    let _ = writeln!(html, "<h1>Packet sniffer report</h1>\n<p class=\"muted\">{}</p>",
+                     escape(&data.get_time().to_rfc3339_opts(SecondsFormat::Secs, false)));
+    html.push_str(session(data).as_str());
+    if data.is_unreliable() {
+        let _ = writeln!(html, "<p class=\"warning\">{:.2}% of the packets have been lost, more than the threshold of {}%: \
+            the report can't be trusted.</p>", data.get_counters().get_drop_rate(), data.get_drop_threshold());
+    }
+
+    let flows = data.get_flows();
+    let key = data.get_sort_key();
+    let _ = writeln!(html, "<h2>Summary</h2>\n<table><tr><th>Flows</th><th>Packets</th><th>Bytes</th><th>Closed flows</th></tr>\
+        <tr><td class=\"n\">{}</td><td class=\"n\">{}</td><td class=\"n\">{}</td><td class=\"n\">{}</td></tr></table>",
+                     flows.len(), flows.get_total_packets(), flows.get_total_bytes(), flows.get_closed());
+    html.push_str("<div class=\"charts\">\n");
+    let share = flows.protocol_share();
+    html.push_str(pie("Protocols by bytes", &share, SortKey::Bytes).as_str());
+    html.push_str(pie("Protocols by packets", &share, SortKey::Packets).as_str());
+    html.push_str(bars(format!("Top talkers by {}", key).as_str(), &flows.top_hosts(data.get_top_n(), key), key).as_str());
+    html.push_str("</div>\n");
+    html.push_str(throughput(flows.get_throughput()).as_str());
+
+    if let Some(interval) = data.get_interval() {
+        let seconds = interval.get_seconds();
+        let _ = writeln!(html, "<h2>Interval</h2>\n<p>From {} to {}: {} packets and {} bytes ({:.2} packets/s, {:.2} bytes/s), \
+            {} new flows, {} closed.</p>", escape(&interval.get_start().to_rfc3339_opts(SecondsFormat::Secs, false)),
+                         escape(&interval.get_end().to_rfc3339_opts(SecondsFormat::Secs, false)), interval.get_packets(),
+                         interval.get_bytes(), interval.get_packets() as f64 / seconds, interval.get_bytes() as f64 / seconds,
+                         interval.get_new_flows(), interval.get_closed());
+    }
+
+    let _ = writeln!(html, "<h2>Flows</h2>\n<table class=\"sortable\">\n<thead>{}</thead>\n<tbody>", FLOW_HEADER);
+    for (key, stats) in flows.sorted_flows(key) {
+        html.push_str(flow(key, stats, None).as_str());
+    }
+    html.push_str("</tbody>\n</table>\n");
+    let finished = flows.get_finished_flows();
+    if !finished.is_empty() {
+        let _ = writeln!(html, "<h2>Closed flows</h2>\n<table class=\"sortable\">\n<thead>{}<th>Reason</th></tr></thead>\n<tbody>",
+                         FLOW_HEADER.trim_end_matches("</tr>"));
+        for finished in &finished {
+            html.push_str(flow(finished.get_key(), finished.get_stats(), Some(finished.get_reason().to_string())).as_str());
+        }
+        html.push_str("</tbody>\n</table>\n");
+    }
+    let unclassified = data.get_unclassified();
+    if !unclassified.is_empty() {
+        html.push_str("<h2>Unclassified traffic</h2>\n<table class=\"sortable\">\n<thead><tr><th>Layer</th><th>Reason</th>\
+            <th>Packets</th><th>Bytes</th></tr></thead>\n<tbody>\n");
+        for failure in unclassified {
+            let _ = writeln!(html, "<tr><td>{}</td><td>{}</td><td class=\"n\">{}</td><td class=\"n\">{}</td></tr>",
+                             escape(&failure.get_layer().to_string()), escape(&failure.get_reason().to_string()),
+                             failure.get_packets(), failure.get_bytes());
+        }
+        html.push_str("</tbody>\n</table>\n");
+    }
+    let _ = writeln!(html, "<script>{}</script>\n</body>\n</html>", SCRIPT);
+    html
+}
+
+const FLOW_HEADER: &str = "<tr><th>Address</th><th>Port</th><th>Protocol</th><th>Service</th><th>SNI</th><th>Packets</th>\
+    <th>Bytes</th><th>Sent</th><th>Received</th><th>First</th><th>Last</th><th>Duration</th><th>RTT</th><th>Retransmissions</th></tr>";
+
+fn session(data: &ReportData) -> String {
+    let mut rows = Vec::new();
+    if let Some(session) = data.get_session() {
+        rows.push(("Session".to_string(), format!("#{}", session.get_id())));
+        rows.push(("Start".to_string(), session.get_start().to_rfc3339_opts(SecondsFormat::Secs, false)));
+        if let Some(end) = session.get_end() {
+            rows.push(("End".to_string(), end.to_rfc3339_opts(SecondsFormat::Secs, false)));
+        }
+        if let Some(reason) = session.get_stop_reason() {
+            rows.push(("Stop reason".to_string(), reason.to_string()));
+        }
+    }
+    let devices = data.get_devices().iter().map(|device| device.name.clone()).collect::<Vec<String>>().join(", ");
+    rows.push(("Devices".to_string(), devices));
+    rows.push(("Capture filter".to_string(), data.get_filter().unwrap_or_else(|| "none".to_string())));
+    if let Some(filter) = data.get_display_filter() {
+        rows.push(("Display filter".to_string(), filter));
+    }
+    let mut html = "<table>\n".to_string();
+    for (name, value) in rows {
+        let _ = writeln!(html, "<tr><th>{}</th><td>{}</td></tr>", name, escape(&value));
+    }
+    html.push_str("</table>\n");
+    html
+}
+
+/// Returns the row of a flow, the sortable values are in the `data-value` attribute of the formatted cells.
+fn flow(key: &FlowKey, stats: &FlowStats, reason: Option<String>) -> String {
+    let protocol = stats.get_protocol();
+    let (sent, received) = (stats.get_transmitted(), stats.get_received());
+    let rtt = stats.get_tcp_stats().and_then(|tcp| tcp.get_rtt());
+    let duration = stats.get_last_ts().saturating_sub(stats.get_first_ts());
+    let mut row = format!("<tr><td>{}</td><td class=\"n\">{}</td><td>{}</td><td>{}</td><td>{}</td><td class=\"n\">{}</td>\
+        <td class=\"n\">{}</td><td class=\"n\" data-value=\"{}\">{} pkts / {} B</td><td class=\"n\" data-value=\"{}\">{} pkts / {} B</td>\
+        <td data-value=\"{}\" title=\"{}\">{}</td><td data-value=\"{}\" title=\"{}\">{}</td><td class=\"n\" data-value=\"{}\">{}</td>\
+        <td class=\"n\" data-value=\"{}\">{}</td><td class=\"n\">{}</td>",
+        escape(&key.0), key.1, escape(&protocol.to_string()), service_name(key.1, &protocol).unwrap_or(""),
+        escape(&stats.get_sni().unwrap_or_default()), stats.get_packets(), stats.get_bytes(), sent.1, sent.0, sent.1,
+        received.1, received.0, received.1, stats.get_first_ts(), format_timestamp(stats.get_first_ts()),
+        format_time(stats.get_first_ts()), stats.get_last_ts(), format_timestamp(stats.get_last_ts()),
+        format_time(stats.get_last_ts()), duration, format_duration(duration), rtt.map(|rtt| rtt.to_string()).unwrap_or_default(),
+        rtt.map(format_duration).unwrap_or_default(),
+        stats.get_tcp_stats().map(|tcp| tcp.get_retransmissions().to_string()).unwrap_or_default());
+    if let Some(reason) = reason {
+        let _ = write!(row, "<td>{}</td>", escape(&reason));
+    }
+    row.push_str("</tr>\n");
+    row
+}
+
+fn value(entry: &RankEntry, key: SortKey) -> f64 {
+    match key {
+        SortKey::Bytes => entry.get_bytes() as f64,
+        SortKey::Packets => entry.get_packets() as f64
+    }
+}
+
+/// Returns a pie chart of the entries, with a legend of their share.
+fn pie(title: &str, entries: &[RankEntry], key: SortKey) -> String {
+    let total: f64 = entries.iter().map(|entry| value(entry, key)).sum();
+    let mut svg = format!("<div class=\"chart\"><h3>{}</h3>\n<svg width=\"200\" height=\"200\" viewBox=\"-1 -1 2 2\" \
+        style=\"transform:rotate(-90deg)\">\n", escape(title));
+    let mut legend = "<div class=\"legend\">".to_string();
+    let mut angle = 0.0;
+    for (i, entry) in entries.iter().enumerate().filter(|(_, entry)| value(entry, key) > 0.0) {
+        let colour = PALETTE[i % PALETTE.len()];
+        let share = value(entry, key) / total;
+        if share >= 1.0 {
+            let _ = writeln!(svg, "<circle r=\"1\" fill=\"{}\"/>", colour);
+        } else {
+            let end = angle + share * 2.0 * PI;
+            let _ = writeln!(svg, "<path d=\"M {:.4} {:.4} A 1 1 0 {} 1 {:.4} {:.4} L 0 0\" fill=\"{}\"/>", angle.cos(), angle.sin(),
+                             if share > 0.5 { 1 } else { 0 }, end.cos(), end.sin(), colour);
+            angle = end;
+        }
+        let _ = write!(legend, "<div><span style=\"background:{}\"></span>{} {:.1}%</div>", colour, escape(&entry.get_name()),
+                       share * 100.0);
+    }
+    if total == 0.0 {
+        svg.push_str("<circle r=\"1\" fill=\"#ddd\"/>\n");
+    }
+    svg.push_str("</svg>\n");
+    svg.push_str(legend.as_str());
+    svg.push_str("</div></div>\n");
+    svg
+}
+
+/// Returns a horizontal bar chart of the entries.
+fn bars(title: &str, entries: &[RankEntry], key: SortKey) -> String {
+    let max = entries.iter().map(|entry| value(entry, key)).fold(0.0, f64::max).max(1.0);
+    let mut svg = format!("<div class=\"chart\"><h3>{}</h3>\n<svg width=\"520\" height=\"{}\">\n", escape(title), entries.len() * 24 + 4);
+    for (i, entry) in entries.iter().enumerate() {
+        let y = i * 24;
+        let _ = writeln!(svg, "<text x=\"0\" y=\"{}\" font-size=\"12\">{}</text><rect x=\"180\" y=\"{}\" width=\"{:.1}\" height=\"18\" \
+            fill=\"{}\"/><text x=\"{:.1}\" y=\"{}\" font-size=\"12\">{}</text>", y + 14, escape(&entry.get_name()), y + 2,
+                         value(entry, key) / max * 260.0, PALETTE[0], value(entry, key) / max * 260.0 + 184.0, y + 14, value(entry, key));
+    }
+    svg.push_str("</svg></div>\n");
+    svg
+}
+
+/// Returns the chart of the bytes per second over the scanning.
+fn throughput(series: &Throughput) -> String {
+    let mut html = "<h2>Throughput</h2>\n".to_string();
+    let buckets = series.get_buckets();
+    if buckets.is_empty() {
+        html.push_str("<p class=\"muted\">No traffic.</p>\n");
+        return html
+    }
+    let (width, height) = (800.0, 200.0);
+    let rates: Vec<f64> = buckets.iter().map(|bucket| bucket.get_bytes() as f64 / series.get_width() as f64).collect();
+    let max = rates.iter().cloned().fold(0.0, f64::max).max(1.0);
+    let step = width / (rates.len().max(2) - 1) as f64;
+    let points = rates.iter().enumerate().map(|(i, rate)| format!("{:.1},{:.1}", i as f64 * step, height - rate / max * height))
+        .collect::<Vec<String>>().join(" ");
+    let _ = writeln!(html, "<svg width=\"{}\" height=\"{}\" viewBox=\"-60 -10 {} {}\">\n\
+        <line x1=\"0\" y1=\"{}\" x2=\"{}\" y2=\"{}\" stroke=\"#999\"/><line x1=\"0\" y1=\"0\" x2=\"0\" y2=\"{}\" stroke=\"#999\"/>\n\
+        <text x=\"-5\" y=\"10\" font-size=\"12\" text-anchor=\"end\">{:.0} B/s</text><text x=\"-5\" y=\"{}\" font-size=\"12\" text-anchor=\"end\">0</text>\n\
+        <text x=\"0\" y=\"{}\" font-size=\"12\">{}</text><text x=\"{}\" y=\"{}\" font-size=\"12\" text-anchor=\"end\">{}</text>\n\
+        <polyline points=\"{}\" fill=\"none\" stroke=\"{}\" stroke-width=\"2\"/>\n</svg>",
+                     width + 80.0, height + 40.0, width + 80.0, height + 40.0, height, width, height, height, max, height,
+                     height + 20.0, format_time(buckets[0].get_start()), width, height + 20.0,
+                     format_time(buckets[buckets.len() - 1].get_start()), points, PALETTE[0]);
+    html
+}
+
+/// Escapes the characters that have a meaning in HTML.
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::flow::FlowTable;
+    use crate::pkt_parser::{PacketInfo, Protocol, TimeVal};
+    use crate::report::ReportData;
+    use crate::report::html::{document, escape};
+
+    #[test]
+    fn test_document() {
+        let mut flows = FlowTable::new();
+        flows.update(&PacketInfo::new("10.0.0.1".to_string(), 443, Protocol::TCP, 1000, TimeVal::from(1_000_000)));
+        flows.update(&PacketInfo::new("10.0.0.1".to_string(), 443, Protocol::TCP, 500, TimeVal::from(2_250_000)));
+        flows.update(&PacketInfo::new("10.0.0.2".to_string(), 53, Protocol::UDP, 80, TimeVal::from(2_500_000)));
+        let mut data = ReportData::for_test(flows, None);
+        data.filter = Some("port <1024".to_string());
+        let html = document(&data);
+        assert!(html.starts_with("<!DOCTYPE html>") && html.trim_end().ends_with("</html>"));
+        // nothing is loaded from the network
+        assert!(!html.contains("src=") && !html.contains("href=") && !html.contains("://"));
+        assert!(html.contains("port &lt;1024"));
+        assert_eq!(html.matches("<path ").count(), 4);
+        assert!(html.contains("<polyline"));
+        assert_eq!(html.matches("<tr><td>10.0.0.").count(), 2);
+    }
+
+    #[test]
+    fn test_escape() {
+        assert_eq!(escape("<b>\"a&b\"</b>"), "&lt;b&gt;&quot;a&amp;b&quot;&lt;/b&gt;");
+    }
+}
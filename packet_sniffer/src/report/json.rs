@@ -0,0 +1,302 @@
+//! json
+//! This module writes the data of a report as a JSON document, for the tools that process the reports. The values
+//! keep their types: the counters are numbers, the timestamps are given both as microseconds since the epoch and as
+//! RFC 3339 strings, and the values that can't be measured (e.g. the RTT of a flow without ACKs) are null.
+
+use chrono::{Local, SecondsFormat, TimeZone};
+use serde_json::{json, Map, Value};
+use crate::flow::{service_name, FinishedFlow, FlowKey, FlowStats, RankEntry};
+use crate::pkt_parser::TimeVal;
+use crate::report::{IntervalData, ReportData, ReportFormatter};
+use crate::stats::{Histogram, Throughput};
+
+/// The formatter of the JSON reports: a document for the final report, a line of JSON for every section of the
+/// periodic reports.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct JsonFormatter;
+
+impl ReportFormatter for JsonFormatter {
+    fn report(&self, data: &ReportData) -> String {
+        serde_json::to_string_pretty(&document(data)).unwrap()
+    }
+
+    fn section(&self, data: &ReportData) -> String {
+        format!("{}\n", document(data))
+    }
+}
+
+/// Formats a timestamp, in microseconds since the epoch, as an RFC 3339 local time.
+pub fn format_timestamp(ts: u64) -> String {
+    let tv = TimeVal::from(ts);
+    Local.timestamp_opt(tv.sec as i64, tv.u_sec * 1000).unwrap().to_rfc3339_opts(SecondsFormat::Micros, false)
+}
+
+/// Returns the JSON document of a report. The sections that the report doesn't contain, like the flows of an
+/// interval of the periodic reports in delta mode, are left out.
+pub fn document(data: &ReportData) -> Value {
+    let mut document = Map::new();
+    document.insert("time".to_string(), json!(data.get_time().to_rfc3339_opts(SecondsFormat::Micros, false)));
+    document.insert("session".to_string(), session(data));
+    document.insert("devices".to_string(), Value::Array(data.get_devices().iter().map(|device| json!({
+        "name": device.name,
+        "description": device.desc,
+        "addresses": device.addresses.iter().map(|a| json!({
+            "addr": a.addr.to_string(),
+            "netmask": a.netmask.map(|n| n.to_string()),
+            "broadcast": a.broadcast_addr.map(|b| b.to_string()),
+        })).collect::<Vec<Value>>(),
+    })).collect()));
+    if let Some(interval) = data.get_interval() {
+        document.insert("interval".to_string(), self::interval(interval));
+    }
+    if data.has_summary() {
+        document.insert("summary".to_string(), summary(data));
+    }
+    if data.is_whole() {
+        document.insert("capture".to_string(), capture(data));
+        let flows = data.get_flows();
+        document.insert("throughput".to_string(), throughput(flows.get_throughput()));
+        document.insert("distributions".to_string(), json!({
+            "frame_sizes": histogram(flows.get_frame_sizes()),
+            "inter_arrival_times": histogram(flows.get_inter_arrival_times()),
+        }));
+        document.insert("closed_flows".to_string(), json!({
+            "idle_timeout": flows.get_idle_closed(),
+            "active_timeout": flows.get_active_closed(),
+            "evicted": flows.get_evicted(),
+            "not_kept": flows.get_finished_dropped(),
+            "flows": flows.get_finished_flows().iter().map(finished_flow).collect::<Vec<Value>>(),
+        }));
+        document.insert("unclassified".to_string(), Value::Array(data.get_unclassified().iter().map(|failure| json!({
+            "layer": failure.get_layer().to_string(),
+            "reason": failure.get_reason().to_string(),
+            "packets": failure.get_packets(),
+            "bytes": failure.get_bytes(),
+        })).collect()));
+        document.insert("flows".to_string(), Value::Array(flows.sorted_flows(data.get_sort_key()).into_iter()
+            .map(|(key, stats)| flow(key, stats)).collect()));
+    }
+    Value::Object(document)
+}
+
+fn session(data: &ReportData) -> Value {
+    let config = data.get_capture_config();
+    let mut session = json!({
+        "filter": data.get_filter(),
+        "display_filter": data.get_display_filter(),
+        "capture_settings": {
+            "snaplen": config.get_snaplen(),
+            "buffer_size": config.get_buffer_size(),
+            "timeout_ms": config.get_timeout(),
+            "immediate": config.get_immediate(),
+            "promisc": config.get_promisc(),
+            "precision": config.get_precision().to_string(),
+        },
+    });
+    if let Some(current) = data.get_session() {
+        let settings = current.get_settings();
+        let stop = settings.get_stop_conditions();
+        let fields = session.as_object_mut().unwrap();
+        fields.insert("id".to_string(), json!(current.get_id()));
+        fields.insert("start".to_string(), json!(current.get_start().to_rfc3339_opts(SecondsFormat::Micros, false)));
+        fields.insert("end".to_string(), json!(current.get_end().map(|end| end.to_rfc3339_opts(SecondsFormat::Micros, false))));
+        fields.insert("time_interval".to_string(), json!(settings.get_time_interval()));
+        fields.insert("stop_conditions".to_string(), json!({
+            "max_packets": stop.get_max_packets(),
+            "max_seconds": stop.get_max_seconds(),
+            "max_bytes": stop.get_max_bytes(),
+        }));
+        fields.insert("stop_reason".to_string(), json!(current.get_stop_reason().map(|reason| reason.to_string())));
+    }
+    session
+}
+
+fn capture(data: &ReportData) -> Value {
+    let counters = data.get_counters();
+    json!({
+        "kernel_received": counters.get_kernel_received(),
+        "kernel_dropped": counters.get_kernel_dropped(),
+        "interface_dropped": counters.get_interface_dropped(),
+        "captured": counters.get_captured(),
+        "queued": counters.get_queued(),
+        "dropped": counters.get_dropped(),
+        "processed": counters.get_processed(),
+        "decode_errors": counters.get_decode_errors(),
+        "decoded": counters.get_decoded(),
+        "lost": counters.get_lost(),
+        "drop_rate": counters.get_drop_rate(),
+        "drop_threshold": data.get_drop_threshold(),
+        "unreliable": data.is_unreliable(),
+        "queue_depth": counters.get_depth(),
+        "max_queue_depth": counters.get_max_depth(),
+        "interfaces": data.get_interfaces().iter().map(|(name, interface)| json!({
+            "name": name,
+            "received": interface.get_received(),
+            "dropped": interface.get_dropped(),
+            "interface_dropped": interface.get_interface_dropped(),
+        })).collect::<Vec<Value>>(),
+    })
+}
+
+fn summary(data: &ReportData) -> Value {
+    let flows = data.get_flows();
+    let (n, key) = (data.get_top_n(), data.get_sort_key());
+    json!({
+        "sort_key": key.to_string(),
+        "flows": flows.len(),
+        "packets": flows.get_total_packets(),
+        "bytes": flows.get_total_bytes(),
+        "protocol_share": ranking(flows.protocol_share()),
+        "interface_share": ranking(flows.interface_share()),
+        "top_hosts": ranking(flows.top_hosts(n, key)),
+        "top_ports": ranking(flows.top_ports(n, key)),
+        "top_conversations": flows.top_flows(n, key).into_iter().map(|(key, stats)| json!({
+            "address": key.0,
+            "port": key.1,
+            "protocol": stats.get_protocol().to_string(),
+            "packets": stats.get_packets(),
+            "bytes": stats.get_bytes(),
+        })).collect::<Vec<Value>>(),
+    })
+}
+
+fn ranking(entries: Vec<RankEntry>) -> Value {
+    Value::Array(entries.iter().map(|entry| json!({
+        "name": entry.get_name(),
+        "flows": entry.get_flows(),
+        "packets": entry.get_packets(),
+        "bytes": entry.get_bytes(),
+    })).collect())
+}
+
+fn interval(interval: &IntervalData) -> Value {
+    let seconds = interval.get_seconds();
+    json!({
+        "start": interval.get_start().to_rfc3339_opts(SecondsFormat::Micros, false),
+        "end": interval.get_end().to_rfc3339_opts(SecondsFormat::Micros, false),
+        "seconds": seconds,
+        "packets": interval.get_packets(),
+        "bytes": interval.get_bytes(),
+        "packets_per_sec": interval.get_packets() as f64 / seconds,
+        "bytes_per_sec": interval.get_bytes() as f64 / seconds,
+        "new_flows": interval.get_new_flows(),
+        "closed_flows": interval.get_closed(),
+        "flows": interval.get_flows().iter().map(|delta| json!({
+            "address": delta.get_key().0,
+            "port": delta.get_key().1,
+            "protocol": delta.get_protocol().to_string(),
+            "packets": delta.get_packets(),
+            "bytes": delta.get_bytes(),
+            "packets_per_sec": delta.get_packets() as f64 / seconds,
+            "bytes_per_sec": delta.get_bytes() as f64 / seconds,
+            "new": delta.is_started(),
+        })).collect::<Vec<Value>>(),
+    })
+}
+
+fn throughput(series: &Throughput) -> Value {
+    json!({
+        "bucket_width": series.get_width(),
+        "buckets": series.get_buckets().iter().map(|bucket| json!({
+            "start": bucket.get_start(),
+            "packets": bucket.get_packets(),
+            "bytes": bucket.get_bytes(),
+        })).collect::<Vec<Value>>(),
+    })
+}
+
+fn histogram(histogram: &Histogram) -> Value {
+    json!({
+        "samples": histogram.get_samples(),
+        "min": histogram.get_min(),
+        "max": histogram.get_max(),
+        "mean": histogram.get_mean(),
+        "p50": histogram.percentile(50.0),
+        "p95": histogram.percentile(95.0),
+        "p99": histogram.percentile(99.0),
+        "buckets": histogram.get_buckets().into_iter().map(|(label, count)| json!({ "bucket": label, "packets": count }))
+            .collect::<Vec<Value>>(),
+    })
+}
+
+/// Returns a flow with all its statistics. The times of the TCP statistics are in microseconds.
+pub fn flow(key: &FlowKey, stats: &FlowStats) -> Value {
+    let protocol = stats.get_protocol();
+    json!({
+        "address": key.0,
+        "port": key.1,
+        "protocol": protocol.to_string(),
+        "service": service_name(key.1, &protocol),
+        "sni": stats.get_sni(),
+        "packets": stats.get_packets(),
+        "bytes": stats.get_bytes(),
+        "transmitted": { "packets": stats.get_transmitted().0, "bytes": stats.get_transmitted().1 },
+        "received": { "packets": stats.get_received().0, "bytes": stats.get_received().1 },
+        "first_ts": stats.get_first_ts(),
+        "last_ts": stats.get_last_ts(),
+        "first_time": format_timestamp(stats.get_first_ts()),
+        "last_time": format_timestamp(stats.get_last_ts()),
+        "duration_us": stats.get_last_ts().saturating_sub(stats.get_first_ts()),
+        "interfaces": stats.get_interfaces().iter().map(|(name, (packets, bytes))| json!({
+            "name": name,
+            "packets": packets,
+            "bytes": bytes,
+        })).collect::<Vec<Value>>(),
+        "frame_sizes": histogram(stats.get_frame_sizes()),
+        "inter_arrival_times": histogram(stats.get_inter_arrival_times()),
+        "tcp": stats.get_tcp_stats().map(|tcp| json!({
+            "rtt_us": tcp.get_rtt(),
+            "rtt_min_us": tcp.get_rtt_min(),
+            "rtt_max_us": tcp.get_rtt_max(),
+            "rtt_samples": tcp.get_rtt_samples(),
+            "handshake_rtt_us": tcp.get_handshake_rtt(),
+            "retransmissions": tcp.get_retransmissions(),
+            "duplicate_acks": tcp.get_duplicate_acks(),
+            "out_of_order": tcp.get_out_of_order(),
+            "zero_window": tcp.get_zero_window(),
+        })),
+    })
+}
+
+fn finished_flow(finished: &FinishedFlow) -> Value {
+    let mut flow = flow(finished.get_key(), finished.get_stats());
+    flow.as_object_mut().unwrap().insert("reason".to_string(), json!(finished.get_reason().to_string()));
+    flow
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Local;
+    use crate::flow::SortKey;
+    use crate::pkt_parser::{PacketInfo, Protocol, TimeVal};
+    use crate::report::{IntervalData, ReportData};
+    use crate::report::json::document;
+
+    #[test]
+    fn test_document() {
+        let mut flows = ReportData::test_flows();
+        let mut data = ReportData::for_test(flows.clone(), None);
+        data.filter = Some("not port 22".to_string());
+        let report = document(&data);
+        assert_eq!(report["session"]["filter"], "not port 22");
+        assert_eq!(report["summary"]["packets"], 3);
+        assert_eq!(report["capture"]["unreliable"], false);
+        let flow = &report["flows"][0];
+        assert_eq!((flow["address"].as_str(), flow["port"].as_u64(), flow["bytes"].as_u64()), (Some("10.0.0.1"), Some(443), Some(1500)));
+        assert_eq!((flow["first_ts"].as_u64(), flow["duration_us"].as_u64()), (Some(1_000_000), Some(250_000)));
+        assert_eq!(flow["service"], "https");
+        assert!(flow["tcp"]["rtt_us"].is_null());
+        assert!(report["flows"][1]["tcp"].is_null());
+
+        // an interval in delta mode contains only its traffic
+        let previous = flows.clone();
+        flows.update(&PacketInfo::new("10.0.0.2".to_string(), 53, Protocol::UDP, 80, TimeVal::from(2_000_000)));
+        let now = Local::now();
+        let interval = IntervalData::new(&previous, &flows, now - chrono::Duration::seconds(2), now, SortKey::Bytes);
+        let report = document(&ReportData::for_test(flows, Some(interval)));
+        assert!(report.get("flows").is_none() && report.get("summary").is_none());
+        assert_eq!(report["interval"]["bytes"], 80);
+        assert_eq!(report["interval"]["packets_per_sec"], 0.5);
+        assert_eq!(report["interval"]["flows"][0]["new"], false);
+    }
+}
@@ -0,0 +1,245 @@
+//! The report module contains the settings that choose how the collected data are written in the reports, and the
+//! data a report is written from, taken when the report is saved or at every interval of the periodic reports.
+//! Besides the text tables, the data can be written as JSON (see the [json] module), one document for the final
+//! report and one line (NDJSON) for every interval of the periodic reports, or as CSV (see the [csv] module), one row
+//! for every flow. The final report can also be a static HTML page with charts (see the [html] module).
+//!
+//! A report is written by a [ReportFormatter], that turns the data into text, to a [sink::ReportSink], that decides
+//! where the text goes: the applications can implement both, and a scanning can write to several outputs at once.
+
+pub mod csv;
+pub mod html;
+pub mod json;
+pub mod sink;
+pub mod text;
+
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+use chrono::{DateTime, Local};
+use pcap::Device;
+use crate::capture::CaptureConfig;
+use crate::flow::{FlowDelta, FlowTable, SortKey};
+use crate::pipeline::{CaptureCounters, PipelineCounters, UnclassifiedTraffic};
+use crate::session::Session;
+
+/// What the sections of the periodic reports contain.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IntervalMode {
+    /// Every section is a whole report of the data collected since the start of the scanning.
+    Cumulative,
+    /// Every section contains only the traffic of its interval, with the rates, optionally followed by the summary
+    /// of the whole scanning.
+    Delta,
+}
+
+impl FromStr for IntervalMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "cumulative" => Ok(IntervalMode::Cumulative),
+            "delta" => Ok(IntervalMode::Delta),
+            other => Err(format!("Unknown interval mode '{}', use cumulative or delta ...", other))
+        }
+    }
+}
+
+impl Display for IntervalMode {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            IntervalMode::Cumulative => write!(f, "cumulative"),
+            IntervalMode::Delta => write!(f, "delta")
+        }
+    }
+}
+
+/// The format of the reports.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReportFormat {
+    /// The tables meant to be read in a terminal.
+    Text,
+    /// A JSON document, or a line of JSON for every interval of the periodic reports.
+    Json,
+    /// A row for every flow, the periodic reports add the rows of every interval to the same table.
+    Csv,
+    /// A static HTML page with the charts and the sortable tables of the flows, rewritten at every interval of the
+    /// periodic reports.
+    Html,
+}
+
+impl FromStr for ReportFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "text" | "txt" => Ok(ReportFormat::Text),
+            "json" => Ok(ReportFormat::Json),
+            "csv" => Ok(ReportFormat::Csv),
+            "html" | "htm" => Ok(ReportFormat::Html),
+            other => Err(format!("Unknown report format '{}', use text, json, csv or html ...", other))
+        }
+    }
+}
+
+impl ReportFormat {
+    /// Returns the formatter that writes the reports in this format.
+    pub fn formatter(&self) -> Box<dyn ReportFormatter> {
+        match self {
+            ReportFormat::Text => Box::new(text::TextFormatter),
+            ReportFormat::Json => Box::new(json::JsonFormatter),
+            ReportFormat::Csv => Box::new(csv::CsvFormatter),
+            ReportFormat::Html => Box::new(html::HtmlFormatter)
+        }
+    }
+}
+
+impl Display for ReportFormat {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ReportFormat::Text => write!(f, "text"),
+            ReportFormat::Json => write!(f, "json"),
+            ReportFormat::Csv => write!(f, "csv"),
+            ReportFormat::Html => write!(f, "html")
+        }
+    }
+}
+
+/// Turns the data of a report into the text written by a sink.
+pub trait ReportFormatter: Send {
+    /// Returns what every destination starts with, before the final report or before the first section of the
+    /// periodic reports (e.g. after a rotation of the files). By default nothing.
+    fn heading(&self, _data: &ReportData, _periodic: bool) -> String {
+        String::new()
+    }
+
+    /// Returns the final report of a scanning without time interval.
+    fn report(&self, data: &ReportData) -> String;
+
+    /// Returns a section of the periodic reports, that the sink appends to the previous ones.
+    fn section(&self, data: &ReportData) -> String;
+
+    /// Returns false if the reports can't be appended to each other, like a single document: every section of the
+    /// periodic reports then replaces the whole report, written with `report`.
+    fn is_appendable(&self) -> bool {
+        true
+    }
+}
+
+/// The traffic exchanged during an interval of the periodic reports.
+#[derive(Debug, Clone)]
+pub struct IntervalData {
+    start: DateTime<Local>,
+    end: DateTime<Local>,
+    flows: Vec<FlowDelta>,
+    closed: u64,
+}
+
+impl IntervalData {
+    /// Compares the snapshots of the flows taken at the start and at the end of the interval.
+    pub fn new(previous: &FlowTable, current: &FlowTable, start: DateTime<Local>, end: DateTime<Local>, sort_key: SortKey) -> Self {
+        IntervalData { start, end, flows: current.delta(previous, sort_key), closed: current.get_closed().saturating_sub(previous.get_closed()) }
+    }
+
+    pub fn get_start(&self) -> DateTime<Local> { return self.start }
+    pub fn get_end(&self) -> DateTime<Local> { return self.end }
+    /// The flows that have exchanged packets in the interval, sorted by the key of the report.
+    pub fn get_flows(&self) -> &[FlowDelta] { return &self.flows }
+    /// The flows closed by the timeouts or evicted during the interval.
+    pub fn get_closed(&self) -> u64 { return self.closed }
+
+    /// Returns the length of the interval in seconds, never zero so that it can divide the rates.
+    pub fn get_seconds(&self) -> f64 {
+        ((self.end - self.start).num_milliseconds() as f64 / 1000.0).max(0.001)
+    }
+
+    pub fn get_packets(&self) -> u64 {
+        self.flows.iter().map(|f| f.get_packets()).sum()
+    }
+
+    pub fn get_bytes(&self) -> usize {
+        self.flows.iter().map(|f| f.get_bytes()).sum()
+    }
+
+    /// Returns the number of flows started during the interval.
+    pub fn get_new_flows(&self) -> usize {
+        self.flows.iter().filter(|f| f.is_started()).count()
+    }
+}
+
+/// The data a report is written from. The flows are the ones selected by the display filter, if any.
+#[derive(Debug, Clone)]
+pub struct ReportData {
+    pub(crate) time: DateTime<Local>,
+    pub(crate) session: Option<Session>,
+    pub(crate) devices: Vec<Device>,
+    pub(crate) filter: Option<String>,
+    pub(crate) capture_config: CaptureConfig,
+    pub(crate) flows: FlowTable,
+    pub(crate) counters: PipelineCounters,
+    pub(crate) interfaces: Vec<(String, CaptureCounters)>,
+    pub(crate) unclassified: Vec<UnclassifiedTraffic>,
+    pub(crate) interval: Option<IntervalData>,
+    pub(crate) whole: bool,
+    pub(crate) summary: bool,
+    pub(crate) top_n: usize,
+    pub(crate) sort_key: SortKey,
+    pub(crate) drop_threshold: f64,
+    pub(crate) display_filter: Option<String>,
+}
+
+impl ReportData {
+    /// The time the data have been taken.
+    pub fn get_time(&self) -> DateTime<Local> { return self.time }
+    /// The scanning the data belong to.
+    pub fn get_session(&self) -> Option<&Session> { return self.session.as_ref() }
+    pub fn get_devices(&self) -> &[Device] { return &self.devices }
+    /// The BPF expression applied to the capture.
+    pub fn get_filter(&self) -> Option<String> { return self.filter.clone() }
+    pub fn get_capture_config(&self) -> &CaptureConfig { return &self.capture_config }
+    /// The flows collected since the start of the scanning.
+    pub fn get_flows(&self) -> &FlowTable { return &self.flows }
+    pub fn get_counters(&self) -> PipelineCounters { return self.counters }
+    /// The statistics of the capture of every interface.
+    pub fn get_interfaces(&self) -> &[(String, CaptureCounters)] { return &self.interfaces }
+    pub fn get_unclassified(&self) -> &[UnclassifiedTraffic] { return &self.unclassified }
+    /// The traffic of the interval, for the sections of the periodic reports in delta mode.
+    pub fn get_interval(&self) -> Option<&IntervalData> { return self.interval.as_ref() }
+    /// True if the report contains all the data collected, false if it is only the traffic of an interval.
+    pub fn is_whole(&self) -> bool { return self.whole }
+    /// True if the report contains the summary of the whole scanning.
+    pub fn has_summary(&self) -> bool { return self.whole || self.summary }
+    /// The number of entries of the summary sections.
+    pub fn get_top_n(&self) -> usize { return self.top_n }
+    pub fn get_sort_key(&self) -> SortKey { return self.sort_key }
+    /// The percentage of lost packets above which the report can't be trusted.
+    pub fn get_drop_threshold(&self) -> f64 { return self.drop_threshold }
+    pub fn get_display_filter(&self) -> Option<String> { return self.display_filter.clone() }
+
+    /// Returns true if more packets than the threshold have been lost.
+    pub fn is_unreliable(&self) -> bool {
+        self.counters.get_drop_rate() > self.drop_threshold
+    }
+}
+
+#[cfg(test)]
+impl ReportData {
+    /// Returns the data of a report of the given flows, with the default settings, for the tests of the formatters.
+    pub(crate) fn for_test(flows: FlowTable, interval: Option<IntervalData>) -> Self {
+        ReportData { time: Local::now(), session: None, devices: Vec::new(), filter: None, capture_config: CaptureConfig::default(),
+            flows, counters: PipelineCounters::default(), interfaces: Vec::new(), unclassified: Vec::new(),
+            whole: interval.is_none(), interval, summary: false, top_n: 10, sort_key: SortKey::Bytes, drop_threshold: 1.0,
+            display_filter: None }
+    }
+
+    /// Returns the flows of the tests of the formatters: a TCP flow with a packet in each direction, a quarter
+    /// of a second apart, and a UDP flow with a single packet.
+    pub(crate) fn test_flows() -> FlowTable {
+        use crate::pkt_parser::{Direction, PacketInfo, Protocol, TimeVal};
+        let mut flows = FlowTable::new();
+        flows.update(&PacketInfo::new("10.0.0.1".to_string(), 443, Protocol::TCP, 1000, TimeVal::from(1_000_000)));
+        flows.update(&PacketInfo::new("10.0.0.1".to_string(), 443, Protocol::TCP, 500, TimeVal::from(1_250_000))
+            .with_direction(Direction::Transmitted));
+        flows.update(&PacketInfo::new("10.0.0.2".to_string(), 53, Protocol::UDP, 80, TimeVal::from(1_500_000)));
+        flows
+    }
+}
@@ -0,0 +1,214 @@
+//! sink
+//! This module contains the destinations of the reports. A sink receives the text written by a formatter and either
+//! replaces what it contains, with the final report, or appends the sections of the periodic reports. The reports can
+//! be written in a file, optionally rotated, or given to a callback; the applications can implement their own sinks.
+//! ```rust,no_run
+//! use packet_sniffer::report::ReportFormat;
+//! use packet_sniffer::report::sink::{CallbackSink, FileSink};
+//! use packet_sniffer::rotation::Rotation;
+//! use packet_sniffer::sniffer::Sniffer;
+//! let mut sniffer = Sniffer::new();
+//! sniffer.add_report_output(ReportFormat::Json.formatter(), Box::new(FileSink::new("report.json".to_string(), Rotation::default())));
+//! sniffer.add_report_output(ReportFormat::Text.formatter(), Box::new(CallbackSink::new(|report: &str| println!("{}", report))));
+//! ```
+
+use std::fmt::{Debug, Formatter};
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::io::Write;
+use crate::report::{ReportData, ReportFormatter};
+use crate::rotation::{RotatingFiles, Rotation};
+
+/// A destination of the reports.
+pub trait ReportSink: Send {
+    /// Prepares the sink for a new scanning, with or without periodic reports. By default nothing is done.
+    fn start(&mut self, _periodic: bool) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Writes a whole report, replacing what the sink contains.
+    fn replace(&mut self, report: &str) -> io::Result<()>;
+
+    /// Appends a section of the periodic reports. The heading goes before the section when it starts a new
+    /// destination, e.g. the first file or a new one after a rotation.
+    fn append(&mut self, heading: &str, section: &str) -> io::Result<()>;
+}
+
+/// Writes the reports in a file. The periodic reports can be rotated: the files are then named with the time they
+/// have been started, and every one begins with the heading so that it can be read on its own.
+#[derive(Debug)]
+pub struct FileSink {
+    filename: String,
+    rotation: Rotation,
+    files: RotatingFiles,
+}
+
+impl FileSink {
+    pub fn new(filename: String, rotation: Rotation) -> Self {
+        FileSink { files: RotatingFiles::new(filename.clone(), rotation), filename, rotation }
+    }
+
+    pub fn get_filename(&self) -> &str {
+        &self.filename
+    }
+
+    /// Returns the file written by the last section of the periodic reports, if any.
+    pub fn get_current(&self) -> Option<String> {
+        self.files.current()
+    }
+}
+
+impl ReportSink for FileSink {
+    fn start(&mut self, periodic: bool) -> io::Result<()> {
+        self.files = RotatingFiles::new(self.filename.clone(), self.rotation);
+        if periodic && self.rotation.is_enabled() {
+            // the reports are written in files named with the time, the one created by set_file stays empty
+            let _ = std::fs::remove_file(&self.filename);
+        }
+        Ok(())
+    }
+
+    fn replace(&mut self, report: &str) -> io::Result<()> {
+        let mut file = File::create(self.files.current().unwrap_or(self.filename.clone()))?;
+        file.write_all(report.as_bytes())
+    }
+
+    fn append(&mut self, heading: &str, section: &str) -> io::Result<()> {
+        let size = self.files.current().and_then(|name| std::fs::metadata(name).ok()).map_or(0, |m| m.len());
+        let mut report = String::new();
+        if self.files.is_due(size) {
+            self.files.open_next()?;
+            report.push_str(heading);
+        }
+        report.push_str(section);
+        let mut file = OpenOptions::new().create(true).append(true).open(self.files.current().unwrap())?;
+        file.write_all(report.as_bytes())
+    }
+}
+
+/// Gives the reports to a function, e.g. to send them over the network. The heading is given together with the
+/// first section of every scanning.
+pub struct CallbackSink<F: FnMut(&str) + Send> {
+    callback: F,
+    started: bool,
+}
+
+impl<F: FnMut(&str) + Send> CallbackSink<F> {
+    pub fn new(callback: F) -> Self {
+        CallbackSink { callback, started: false }
+    }
+}
+
+impl<F: FnMut(&str) + Send> Debug for CallbackSink<F> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CallbackSink").field("started", &self.started).finish()
+    }
+}
+
+impl<F: FnMut(&str) + Send> ReportSink for CallbackSink<F> {
+    fn start(&mut self, _periodic: bool) -> io::Result<()> {
+        self.started = false;
+        Ok(())
+    }
+
+    fn replace(&mut self, report: &str) -> io::Result<()> {
+        (self.callback)(report);
+        Ok(())
+    }
+
+    fn append(&mut self, heading: &str, section: &str) -> io::Result<()> {
+        if self.started {
+            (self.callback)(section);
+        } else {
+            self.started = true;
+            (self.callback)(format!("{}{}", heading, section).as_str());
+        }
+        Ok(())
+    }
+}
+
+/// A formatter and the sink its reports are written to.
+pub struct ReportOutput {
+    formatter: Box<dyn ReportFormatter>,
+    sink: Box<dyn ReportSink>,
+}
+
+impl ReportOutput {
+    pub fn new(formatter: Box<dyn ReportFormatter>, sink: Box<dyn ReportSink>) -> Self {
+        ReportOutput { formatter, sink }
+    }
+
+    /// Prepares the sink for a new scanning.
+    pub fn start(&mut self, periodic: bool) -> io::Result<()> {
+        self.sink.start(periodic)
+    }
+
+    /// Writes the final report of a scanning without time interval, replacing what the sink contains.
+    pub fn write_report(&mut self, data: &ReportData) -> io::Result<()> {
+        let mut report = self.formatter.heading(data, false);
+        report.push_str(self.formatter.report(data).as_str());
+        self.sink.replace(report.as_str())
+    }
+
+    /// Writes a section of the periodic reports: it is appended to the previous ones, or replaces the whole report
+    /// if the format can't be appended to.
+    pub fn write_section(&mut self, data: &ReportData) -> io::Result<()> {
+        if self.formatter.is_appendable() {
+            self.sink.append(self.formatter.heading(data, true).as_str(), self.formatter.section(data).as_str())
+        } else {
+            self.write_report(data)
+        }
+    }
+}
+
+impl Debug for ReportOutput {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ReportOutput").field("appendable", &self.formatter.is_appendable()).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+    use crate::flow::FlowTable;
+    use crate::report::{ReportData, ReportFormat};
+    use crate::report::sink::{CallbackSink, FileSink, ReportOutput, ReportSink};
+    use crate::rotation::Rotation;
+
+    #[test]
+    fn test_callback_output() {
+        let data = ReportData::for_test(FlowTable::new(), None);
+        let reports = Arc::new(Mutex::new(Vec::new()));
+        let received = reports.clone();
+        let sink = CallbackSink::new(move |report: &str| received.lock().unwrap().push(report.to_string()));
+        let mut output = ReportOutput::new(ReportFormat::Csv.formatter(), Box::new(sink));
+        output.start(true).unwrap();
+        output.write_section(&data).unwrap();
+        output.write_section(&data).unwrap();
+        // only the first section starts with the header
+        assert_eq!(reports.lock().unwrap().clone(), vec![crate::report::csv::header(true), String::new()]);
+
+        let reports = Arc::new(Mutex::new(Vec::new()));
+        let received = reports.clone();
+        let sink = CallbackSink::new(move |report: &str| received.lock().unwrap().push(report.to_string()));
+        let mut output = ReportOutput::new(ReportFormat::Html.formatter(), Box::new(sink));
+        output.write_section(&data).unwrap();
+        output.write_section(&data).unwrap();
+        // the page is written whole every time
+        assert!(reports.lock().unwrap().iter().all(|report| report.starts_with("<!DOCTYPE html>")));
+    }
+
+    #[test]
+    fn test_file_sink() {
+        let filename = std::env::temp_dir().join(format!("sink_{}.txt", std::process::id())).to_string_lossy().to_string();
+        let mut sink = FileSink::new(filename.clone(), Rotation::default());
+        sink.start(true).unwrap();
+        sink.append("heading\n", "first\n").unwrap();
+        sink.append("heading\n", "second\n").unwrap();
+        assert_eq!(std::fs::read_to_string(&filename).unwrap(), "heading\nfirst\nsecond\n");
+        // the final report replaces the whole content
+        sink.replace("report\n").unwrap();
+        assert_eq!(std::fs::read_to_string(&filename).unwrap(), "report\n");
+        std::fs::remove_file(&filename).unwrap();
+    }
+}
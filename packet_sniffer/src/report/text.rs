@@ -0,0 +1,363 @@
+//! text
+//! This module writes the data of a report as the tables meant to be read in a terminal: the description of the
+//! scanning, followed by the capture statistics, the summary, the throughput, the distributions, the closed flows,
+//! the unclassified traffic and the table of all the flows.
+
+use chrono::{Local, TimeZone};
+use prettytable::{Cell, Row, Table};
+use crate::flow::{FlowKey, FlowStats, FlowTable, RankEntry, SortKey};
+use crate::pipeline::{CaptureCounters, PipelineCounters, UnclassifiedTraffic};
+use crate::pkt_parser::TimeVal;
+use crate::report::{IntervalData, ReportData, ReportFormatter};
+use crate::stats::{format_time, Histogram, Throughput};
+
+/// The formatter of the text reports. The sections of the periodic reports in delta mode contain the traffic of
+/// their interval, and the last one is followed by the whole report.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TextFormatter;
+
+impl ReportFormatter for TextFormatter {
+    fn heading(&self, data: &ReportData, _periodic: bool) -> String {
+        heading(data)
+    }
+
+    fn report(&self, data: &ReportData) -> String {
+        center(data)
+    }
+
+    fn section(&self, data: &ReportData) -> String {
+        let mut section = String::new();
+        if let Some(interval) = data.get_interval() {
+            section.push_str(self::interval(interval, data).as_str());
+        }
+        if data.is_whole() {
+            section.push_str(center(data).as_str());
+        }
+        section
+    }
+}
+
+/// Formats a time expressed in microseconds as milliseconds, used for the RTT columns.
+fn format_ms(value: Option<u64>) -> String {
+    match value {
+        Some(us) => format!("{:.3}", us as f64 / 1000.0),
+        None => "-".to_string()
+    }
+}
+
+/// Formats the ratio between a value and the total as a percentage.
+fn format_percentage(value: f64, total: f64) -> String {
+    if total == 0.0 { "-".to_string() } else { format!("{:.2}%", value * 100.0 / total) }
+}
+
+fn heading(data: &ReportData) -> String {
+    let (devices, filter, capture_config) = (data.get_devices(), data.get_filter(), data.get_capture_config());
+    let mut string = "Scanning on: ".to_string();
+    devices.iter().for_each(|device| {
+        string.push_str("\n\t- Interface ");
+        string.push_str(device.name.as_str());
+    });
+    string.push_str(format!("\n\t- Capture settings: {}", capture_config).as_str());
+    if let Some(filter) = filter {
+        string.push_str(format!("\n\t- Capture filter: {}", filter).as_str());
+    }
+    string.push_str("\nAddresses: ");
+    devices.iter().for_each(|device| device.addresses.iter().for_each(|a| {
+        string.push_str("\n\t- ");
+        string.push_str(a.addr.to_string().as_str());
+        if devices.len() > 1 {
+            string.push_str(format!(" ({})", device.name).as_str());
+        }
+    }));
+    return string
+}
+
+fn ranking(title: String, entries: Vec<RankEntry>, total_bytes: usize, total_packets: u64) -> String {
+    let mut table = Table::new();
+    table.add_row(row!["#", "Name", "Flows", "Packets", "% Packets", "Bytes", "% Bytes"]);
+    for (i, entry) in entries.iter().enumerate() {
+        table.add_row(Row::new(vec![
+            Cell::new((i + 1).to_string().as_str()),
+            Cell::new(entry.get_name().as_str()),
+            Cell::new(entry.get_flows().to_string().as_str()),
+            Cell::new(entry.get_packets().to_string().as_str()),
+            Cell::new(format_percentage(entry.get_packets() as f64, total_packets as f64).as_str()),
+            Cell::new(entry.get_bytes().to_string().as_str()),
+            Cell::new(format_percentage(entry.get_bytes() as f64, total_bytes as f64).as_str()),
+        ]));
+    }
+    let mut ranking = format!("\n{}:\n", title);
+    ranking.push_str(table.to_string().as_str());
+    return ranking
+}
+
+fn summary(flows: &FlowTable, data: &ReportData) -> String {
+    let total_bytes = flows.get_total_bytes();
+    let total_packets = flows.get_total_packets();
+    let mut summary = format!("\n\nSummary (sorted by {}): \n\t- Flows: {}\n\t- Packets: {}\n\t- Bytes: {}\n",
+                              data.get_sort_key(), flows.len(), total_packets, total_bytes);
+    if let Some(filter) = data.get_display_filter() {
+        summary.push_str(format!("\t- Display filter: {}\n", filter).as_str());
+    }
+    summary.push_str(ranking("Protocol share".to_string(), flows.protocol_share(), total_bytes, total_packets).as_str());
+    let interfaces = flows.interface_share();
+    if interfaces.len() > 1 {
+        summary.push_str(ranking("Interface share".to_string(), interfaces, total_bytes, total_packets).as_str());
+    }
+    summary.push_str(ranking(format!("Top {} hosts", data.get_top_n()),
+                                      flows.top_hosts(data.get_top_n(), data.get_sort_key()), total_bytes, total_packets).as_str());
+    summary.push_str(ranking(format!("Top {} ports/services", data.get_top_n()),
+                                      flows.top_ports(data.get_top_n(), data.get_sort_key()), total_bytes, total_packets).as_str());
+
+    let mut table = Table::new();
+    table.add_row(row!["#", "IP Address", "Port", "Protocol", "Packets", "Bytes", "% Bytes"]);
+    for (i, (key, value)) in flows.top_flows(data.get_top_n(), data.get_sort_key()).iter().enumerate() {
+        table.add_row(Row::new(vec![
+            Cell::new((i + 1).to_string().as_str()),
+            Cell::new(key.0.as_str()),
+            Cell::new(key.1.to_string().as_str()),
+            Cell::new(value.get_protocol().to_string().as_str()),
+            Cell::new(value.get_packets().to_string().as_str()),
+            Cell::new(value.get_bytes().to_string().as_str()),
+            Cell::new(format_percentage(value.get_bytes() as f64, total_bytes as f64).as_str()),
+        ]));
+    }
+    summary.push_str(format!("\nTop {} conversations:\n", data.get_top_n()).as_str());
+    summary.push_str(table.to_string().as_str());
+    return summary
+}
+
+fn throughput(flows: &FlowTable, data: &ReportData) -> String {
+    let series = flows.get_throughput();
+    let mut throughput = format!("\n\nThroughput (buckets of {} sec): \n", series.get_width());
+    if let Some(peak) = series.get_peak() {
+        throughput.push_str(format!("\t- Peak: {} bytes/sec at {}\n", peak.get_bytes() / series.get_width(),
+                                    format_time(peak.get_start())).as_str());
+    }
+    let mut table = Table::new();
+    table.add_row(row!["Time", "Packets", "Bytes", "Bytes/sec"]);
+    for bucket in series.get_buckets() {
+        table.add_row(Row::new(vec![
+            Cell::new(format_time(bucket.get_start()).as_str()),
+            Cell::new(bucket.get_packets().to_string().as_str()),
+            Cell::new(bucket.get_bytes().to_string().as_str()),
+            Cell::new((bucket.get_bytes() / series.get_width()).to_string().as_str()),
+        ]));
+    }
+    throughput.push_str(table.to_string().as_str());
+
+    throughput.push_str(format!("\nThroughput of the top {} hosts (CSV):\n", data.get_top_n()).as_str());
+    throughput.push_str(Throughput::csv_header(Some("host")).as_str());
+    for host in flows.top_hosts(data.get_top_n(), data.get_sort_key()) {
+        if let Some(series) = flows.get_host_throughput(host.get_name().as_str()) {
+            throughput.push_str(series.to_csv(Some(host.get_name().as_str())).as_str());
+        }
+    }
+    return throughput
+}
+
+fn percentiles(histogram: &Histogram) -> Vec<String> {
+    [50.0, 95.0, 99.0].iter()
+        .map(|p| histogram.percentile(*p).map_or("-".to_string(), |v| histogram.get_unit().format(v)))
+        .collect()
+}
+
+fn histogram(title: &str, histogram: &Histogram) -> String {
+    let p = percentiles(histogram);
+    let mut result = format!("\n{} (p50: {}, p95: {}, p99: {}):\n", title, p[0], p[1], p[2]);
+    let mut table = Table::new();
+    table.add_row(row!["Bucket", "Packets", "% Packets"]);
+    for (label, count) in histogram.get_buckets() {
+        table.add_row(Row::new(vec![
+            Cell::new(label.as_str()),
+            Cell::new(count.to_string().as_str()),
+            Cell::new(format_percentage(count as f64, histogram.get_samples() as f64).as_str()),
+        ]));
+    }
+    result.push_str(table.to_string().as_str());
+    return result
+}
+
+fn distributions(flows: &FlowTable, data: &ReportData) -> String {
+    let mut distributions = "\n\nDistributions: ".to_string();
+    distributions.push_str(histogram("Frame sizes", flows.get_frame_sizes()).as_str());
+    distributions.push_str(histogram("Inter-arrival times", flows.get_inter_arrival_times()).as_str());
+
+    let mut table = Table::new();
+    table.add_row(row!["IP Address", "Port", "Size p50", "Size p95", "Size p99", "Inter-arrival p50", "Inter-arrival p95", "Inter-arrival p99"]);
+    for (key, value) in flows.top_flows(data.get_top_n(), data.get_sort_key()) {
+        let mut cells = vec![Cell::new(key.0.as_str()), Cell::new(key.1.to_string().as_str())];
+        percentiles(value.get_frame_sizes()).iter()
+            .chain(percentiles(value.get_inter_arrival_times()).iter())
+            .for_each(|p| cells.push(Cell::new(p.as_str())));
+        table.add_row(Row::new(cells));
+    }
+    distributions.push_str(format!("\nDistributions of the top {} conversations:\n", data.get_top_n()).as_str());
+    distributions.push_str(table.to_string().as_str());
+    return distributions
+}
+
+fn finished_flows(flows: &FlowTable, data: &ReportData) -> String {
+    let mut finished = flows.get_finished_flows();
+    let mut result = format!("\n\nClosed flows: \n\t- Idle timeout: {}\n\t- Active timeout: {}\n\t- Evicted: {}\n\t- Live flows: {}",
+                             flows.get_idle_closed(), flows.get_active_closed(), flows.get_evicted(), flows.len());
+    if flows.get_max_flows() > 0 {
+        result.push_str(format!(" (max {}, eviction policy: {})", flows.get_max_flows(), flows.get_eviction_policy()).as_str());
+    }
+    if flows.get_finished_dropped() > 0 {
+        result.push_str(format!("\n\t- Closed flows not kept in the log: {}", flows.get_finished_dropped()).as_str());
+    }
+    result.push_str("\n");
+    if finished.is_empty() {
+        return result
+    }
+    finished.sort_by_key(|f| std::cmp::Reverse(match data.get_sort_key() {
+        SortKey::Bytes => f.get_stats().get_bytes() as u64,
+        SortKey::Packets => f.get_stats().get_packets()
+    }));
+    let mut table = Table::new();
+    table.add_row(row!["IP Address", "Port", "Protocol", "Packets", "Bytes Transmitted", "First Timestamp", "Last Timestamp", "Reason"]);
+    for flow in finished.iter().take(data.get_top_n()) {
+        let stats = flow.get_stats();
+        table.add_row(Row::new(vec![
+            Cell::new(flow.get_key().0.as_str()),
+            Cell::new(flow.get_key().1.to_string().as_str()),
+            Cell::new(stats.get_protocol().to_string().as_str()),
+            Cell::new(stats.get_packets().to_string().as_str()),
+            Cell::new(stats.get_bytes().to_string().as_str()),
+            Cell::new(format_time(stats.get_first_ts()).as_str()),
+            Cell::new(format_time(stats.get_last_ts()).as_str()),
+            Cell::new(flow.get_reason().to_string().as_str()),
+        ]));
+    }
+    result.push_str(format!("Top {} closed flows:\n", data.get_top_n()).as_str());
+    result.push_str(table.to_string().as_str());
+    return result
+}
+
+fn pipeline(counters: PipelineCounters, interfaces: Vec<(String, CaptureCounters)>, data: &ReportData) -> String {
+    let mut pipeline = format!("\n\nCapture: \n\t- Received by libpcap: {}\n\t- Dropped by the kernel: {}\n\t- Dropped by the interface: {}\n\t- Decoded: {}\n\t- Lost: {} ({:.2}%)\n",
+                               counters.get_kernel_received(), counters.get_kernel_dropped(), counters.get_interface_dropped(),
+                               counters.get_decoded(), counters.get_lost(), counters.get_drop_rate());
+    if interfaces.len() > 1 {
+        for (name, interface) in interfaces {
+            pipeline.push_str(format!("\t- {}: received {}, dropped by the kernel {}, dropped by the interface {}\n", name,
+                                      interface.get_received(), interface.get_dropped(), interface.get_interface_dropped()).as_str());
+        }
+    }
+    if counters.get_drop_rate() > data.get_drop_threshold() {
+        pipeline.push_str(format!("\t- Warning: more than {}% of the packets has been lost, the report is incomplete and can't be trusted\n",
+                                  data.get_drop_threshold()).as_str());
+    }
+    pipeline.push_str(format!("\nPipeline: \n\t- Captured: {}\n\t- Queued: {}\n\t- Dropped: {} ({})\n\t- Decode errors: {}\n\t- Queue depth: {} (max {})\n",
+                              counters.get_captured(), counters.get_queued(), counters.get_dropped(),
+                              format_percentage(counters.get_dropped() as f64, counters.get_captured() as f64),
+                              counters.get_decode_errors(), counters.get_depth(), counters.get_max_depth()).as_str());
+    return pipeline
+}
+
+fn unclassified(failures: Vec<UnclassifiedTraffic>, counters: &PipelineCounters) -> String {
+    let packets: u64 = failures.iter().map(|f| f.get_packets()).sum();
+    let bytes: u64 = failures.iter().map(|f| f.get_bytes()).sum();
+    let mut unclassified = format!("\n\nUnclassified traffic: \n\t- Packets: {} ({} of the processed packets)\n\t- Bytes: {}\n",
+                                   packets, format_percentage(packets as f64, counters.get_processed() as f64), bytes);
+    if failures.is_empty() {
+        return unclassified
+    }
+    let mut table = Table::new();
+    table.add_row(row!["Layer", "Reason", "Packets", "% Packets", "Bytes", "% Bytes"]);
+    for failure in failures {
+        table.add_row(Row::new(vec![
+            Cell::new(failure.get_layer().to_string().as_str()),
+            Cell::new(failure.get_reason().to_string().as_str()),
+            Cell::new(failure.get_packets().to_string().as_str()),
+            Cell::new(format_percentage(failure.get_packets() as f64, packets as f64).as_str()),
+            Cell::new(failure.get_bytes().to_string().as_str()),
+            Cell::new(format_percentage(failure.get_bytes() as f64, bytes as f64).as_str()),
+        ]));
+    }
+    unclassified.push_str(table.to_string().as_str());
+    return unclassified
+}
+
+fn center(data: &ReportData) -> String {
+    let mut center = "\n\nScanning: \n\t- Update Time: ".to_string();
+    center.push_str(data.get_time().to_string().as_str());
+    let hm = data.get_flows();
+    center.push_str(pipeline(data.get_counters(), data.get_interfaces().to_vec(), data).as_str());
+    center.push_str(summary(hm, data).as_str());
+    center.push_str(throughput(hm, data).as_str());
+    center.push_str(distributions(hm, data).as_str());
+    center.push_str(finished_flows(hm, data).as_str());
+    center.push_str(unclassified(data.get_unclassified().to_vec(), &data.get_counters()).as_str());
+    center.push_str("\nFlows:\n");
+    center.push_str(flows(hm.sorted_flows(data.get_sort_key())).as_str());
+    return center
+}
+
+fn interval(interval: &IntervalData, data: &ReportData) -> String {
+    let seconds = interval.get_seconds();
+    let (packets, bytes) = (interval.get_packets(), interval.get_bytes());
+    let mut text = format!("\n\nInterval: \n\t- From: {}\n\t- To: {} ({:.1} sec)\n\t- Packets: {} ({:.1}/sec)\n\t- Bytes: {} ({:.1}/sec)\n\t- Active flows: {} ({} new)\n\t- Closed flows: {}\n",
+                           interval.get_start().format("%Y-%m-%d %H:%M:%S"), interval.get_end().format("%Y-%m-%d %H:%M:%S"), seconds,
+                           packets, packets as f64 / seconds, bytes, bytes as f64 / seconds,
+                           interval.get_flows().len(), interval.get_new_flows(), interval.get_closed());
+    if let Some(filter) = data.get_display_filter() {
+        text.push_str(format!("\t- Display filter: {}\n", filter).as_str());
+    }
+    let mut table = Table::new();
+    table.add_row(row!["IP Address", "Port", "Protocol", "Packets", "Bytes", "Packets/sec", "Bytes/sec", "New"]);
+    for delta in interval.get_flows() {
+        table.add_row(Row::new(vec![
+            Cell::new(delta.get_key().0.as_str()),
+            Cell::new(delta.get_key().1.to_string().as_str()),
+            Cell::new(delta.get_protocol().to_string().as_str()),
+            Cell::new(delta.get_packets().to_string().as_str()),
+            Cell::new(delta.get_bytes().to_string().as_str()),
+            Cell::new(format!("{:.1}", delta.get_packets() as f64 / seconds).as_str()),
+            Cell::new(format!("{:.1}", delta.get_bytes() as f64 / seconds).as_str()),
+            Cell::new(if delta.is_started() { "yes" } else { "" }),
+        ]));
+    }
+    text.push_str("\nFlows of the interval:\n");
+    text.push_str(table.to_string().as_str());
+    // the whole report that may follow has its own summary
+    if data.has_summary() && !data.is_whole() {
+        text.push_str("\n\nSince the start of the scanning:");
+        text.push_str(summary(data.get_flows(), data).as_str());
+    }
+    return text
+}
+
+/// Returns the table of the given flows, also used by the live view.
+pub(crate) fn flows(flows: Vec<(&FlowKey, &FlowStats)>) -> String {
+    let mut table = Table::new();
+    table.add_row(row!["IP Address", "Port", "Protocol", "TLS SNI", "Bytes Transmitted", "First Timestamp", "Last Timestamp",
+        "RTT (ms)", "Handshake RTT (ms)", "Retransmissions", "Dup ACKs", "Out of Order", "Zero Window"]);
+    for (key, value) in flows {
+        let first = TimeVal::from(value.get_first_ts());
+        let last = TimeVal::from(value.get_last_ts());
+        let mut cells = vec![
+            Cell::new(key.0.as_str()),
+            Cell::new(key.1.to_string().as_str()),
+            Cell::new(value.get_protocol().to_string().as_str()),
+            Cell::new(value.get_sni().unwrap_or_else(|| "-".to_string()).as_str()),
+            Cell::new(value.get_bytes().to_string().as_str()),
+            Cell::new(format!("{}", Local.timestamp_opt(first.sec as i64, first.u_sec * 1000).unwrap().format("%H:%M:%S %f ns")).as_str()),
+            Cell::new(format!("{}", Local.timestamp_opt(last.sec as i64, last.u_sec * 1000).unwrap().format("%H:%M:%S %f ns")).as_str()),
+        ];
+        match value.get_tcp_stats() {
+            Some(tcp) => {
+                cells.push(Cell::new(format_ms(tcp.get_rtt()).as_str()));
+                cells.push(Cell::new(format_ms(tcp.get_handshake_rtt()).as_str()));
+                cells.push(Cell::new(tcp.get_retransmissions().to_string().as_str()));
+                cells.push(Cell::new(tcp.get_duplicate_acks().to_string().as_str()));
+                cells.push(Cell::new(tcp.get_out_of_order().to_string().as_str()));
+                cells.push(Cell::new(tcp.get_zero_window().to_string().as_str()));
+            },
+            None => (0..6).for_each(|_| cells.push(Cell::new("-")))
+        }
+        table.add_row(Row::new(cells));
+    }
+    return table.to_string()
+}
@@ -0,0 +1,158 @@
+//! The rotation module splits a long output, like the capture file or the periodic reports, in a ring of files:
+//! a new file is started when the current one reaches a size or an age, its name is the one chosen by the user with
+//! the time it has been opened (e.g. `capture-20240131-101500.pcapng`), and only the most recent files are kept,
+//! like `tcpdump -C -G -W`.
+
+use std::collections::VecDeque;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::time::{Duration, Instant};
+use chrono::{DateTime, Local};
+
+/// When a file is closed to start a new one, and how many files are kept.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Rotation {
+    max_bytes: u64,
+    max_seconds: u64,
+    max_files: usize,
+}
+
+impl Rotation {
+    /// Creates a rotation after `max_bytes` bytes or `max_seconds` seconds, keeping at most `max_files` files.
+    /// A zero disables the corresponding limit, and without a size or an age the output is never rotated.
+    pub fn new(max_bytes: u64, max_seconds: u64, max_files: usize) -> Self {
+        Rotation { max_bytes, max_seconds, max_files }
+    }
+
+    /// Returns true if the output is split in several files.
+    pub fn is_enabled(&self) -> bool {
+        self.max_bytes > 0 || self.max_seconds > 0
+    }
+
+    pub fn get_max_bytes(&self) -> u64 { return self.max_bytes }
+    pub fn get_max_seconds(&self) -> u64 { return self.max_seconds }
+    pub fn get_max_files(&self) -> usize { return self.max_files }
+}
+
+/// Returns the name of a rotated file: the time, and a sequence number if more files are opened in the same second,
+/// are put between the name and the extension.
+pub fn rotated_name(base: &str, time: DateTime<Local>, sequence: usize) -> String {
+    let path = Path::new(base);
+    let stem = path.file_stem().map_or(String::new(), |stem| stem.to_string_lossy().to_string());
+    let mut name = format!("{}-{}", stem, time.format("%Y%m%d-%H%M%S"));
+    if sequence > 0 {
+        name.push_str(format!("-{}", sequence).as_str());
+    }
+    if let Some(extension) = path.extension() {
+        name.push('.');
+        name.push_str(extension.to_string_lossy().as_ref());
+    }
+    path.with_file_name(name).to_string_lossy().to_string()
+}
+
+/// The files of a rotated output.
+#[derive(Debug, Clone)]
+pub struct RotatingFiles {
+    base: String,
+    rotation: Rotation,
+    files: VecDeque<String>,
+    current: Option<String>,
+    opened: Instant,
+    last: Option<(i64, usize)>,
+}
+
+impl RotatingFiles {
+    pub fn new(base: String, rotation: Rotation) -> Self {
+        RotatingFiles { base, rotation, files: VecDeque::new(), current: None, opened: Instant::now(), last: None }
+    }
+
+    /// Returns the file in use, if one has been started.
+    pub fn current(&self) -> Option<String> {
+        self.current.clone()
+    }
+
+    /// Returns the files written so far that have not been deleted, from the oldest.
+    pub fn get_files(&self) -> Vec<String> {
+        match self.rotation.is_enabled() {
+            true => self.files.iter().cloned().collect(),
+            false => self.current.iter().cloned().collect()
+        }
+    }
+
+    /// Returns true if no file has been started, or if the current one, that has the given size, has to be closed.
+    pub fn is_due(&self, size: u64) -> bool {
+        match self.current {
+            None => true,
+            Some(_) => (self.rotation.max_bytes > 0 && size >= self.rotation.max_bytes)
+                || (self.rotation.max_seconds > 0 && self.opened.elapsed() >= Duration::from_secs(self.rotation.max_seconds))
+        }
+    }
+
+    /// Starts a new file and returns its name, deleting the oldest files beyond the limit. Without rotation the
+    /// name is the one chosen by the user.
+    pub fn open_next(&mut self) -> io::Result<String> {
+        self.opened = Instant::now();
+        if !self.rotation.is_enabled() {
+            self.current = Some(self.base.clone());
+            return Ok(self.base.clone())
+        }
+        let now = Local::now();
+        let sequence = match self.last {
+            Some((second, sequence)) if second == now.timestamp() => sequence + 1,
+            _ => 0
+        };
+        self.last = Some((now.timestamp(), sequence));
+        let name = rotated_name(&self.base, now, sequence);
+        self.files.push_back(name.clone());
+        self.current = Some(name.clone());
+        while self.rotation.max_files > 0 && self.files.len() > self.rotation.max_files {
+            let oldest = self.files.pop_front().unwrap();
+            match fs::remove_file(&oldest) {
+                Err(error) if error.kind() != io::ErrorKind::NotFound => return Err(error),
+                _ => {}
+            }
+        }
+        Ok(name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use chrono::{Local, TimeZone};
+    use crate::rotation::{rotated_name, RotatingFiles, Rotation};
+
+    #[test]
+    fn test_rotated_name() {
+        let time = Local.ymd_opt(2024, 1, 31).unwrap().and_hms_opt(10, 15, 0).unwrap();
+        assert_eq!(rotated_name("captures/uplink.pcapng", time, 0), "captures/uplink-20240131-101500.pcapng");
+        assert_eq!(rotated_name("report", time, 2), "report-20240131-101500-2");
+    }
+
+    #[test]
+    fn test_ring_of_files() {
+        let base = std::env::temp_dir().join("packet_sniffer_ring.txt").to_string_lossy().to_string();
+        let mut files = RotatingFiles::new(base.clone(), Rotation::new(100, 0, 2));
+        assert!(files.is_due(0));
+        let mut names = Vec::new();
+        for _ in 0..3 {
+            let name = files.open_next().unwrap();
+            names.push(name.clone());
+            fs::write(&name, "report").unwrap();
+            assert!(!files.is_due(99));
+            assert!(files.is_due(100));
+        }
+        // the first file has been deleted, and the names are unique even if opened in the same second
+        let kept = files.get_files();
+        assert_eq!(kept, names[1..].to_vec());
+        assert!(fs::metadata(&names[0]).is_err());
+        assert_ne!(kept[0], kept[1]);
+        assert!(kept.iter().all(|name| fs::metadata(name).is_ok()));
+        kept.iter().for_each(|name| fs::remove_file(name).unwrap());
+
+        let mut single = RotatingFiles::new(base.clone(), Rotation::default());
+        assert_eq!(single.open_next().unwrap(), base);
+        assert!(!single.is_due(u64::MAX));
+    }
+}
@@ -0,0 +1,152 @@
+//! The session module keeps the history of the scannings of a sniffer: every run starts a new session, with its own
+//! flows and counters, and the finished sessions can still be inspected until they are discarded.
+
+use std::fmt::{Display, Formatter};
+use std::sync::Arc;
+use chrono::{DateTime, Local};
+use crate::capture::CaptureConfig;
+use crate::flow::{FlowTable, ShardedFlowTable};
+use crate::pipeline::{DecodeFailures, PipelineCounters, PipelineStats, UnclassifiedTraffic};
+use crate::stop::{StopConditions, StopReason};
+
+/// The settings a session has been started with.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SessionSettings {
+    devices: Vec<String>,
+    filename: Option<String>,
+    time_interval: u64,
+    filter: Option<String>,
+    capture_config: CaptureConfig,
+    stop_conditions: StopConditions,
+}
+
+impl SessionSettings {
+    pub fn new(devices: Vec<String>, filename: Option<String>, time_interval: u64, filter: Option<String>,
+               capture_config: CaptureConfig, stop_conditions: StopConditions) -> Self {
+        SessionSettings { devices, filename, time_interval, filter, capture_config, stop_conditions }
+    }
+
+    /// The names of the interfaces sniffed.
+    pub fn get_devices(&self) -> &[String] { return &self.devices }
+    /// The file of the report.
+    pub fn get_filename(&self) -> Option<String> { return self.filename.clone() }
+    /// The interval of the periodic reports, in seconds, or zero.
+    pub fn get_time_interval(&self) -> u64 { return self.time_interval }
+    /// The BPF expression applied to the capture.
+    pub fn get_filter(&self) -> Option<String> { return self.filter.clone() }
+    pub fn get_capture_config(&self) -> &CaptureConfig { return &self.capture_config }
+    pub fn get_stop_conditions(&self) -> StopConditions { return self.stop_conditions }
+}
+
+/// A scanning, running or finished, with the data collected.
+#[derive(Debug, Clone)]
+pub struct Session {
+    id: u64,
+    start: DateTime<Local>,
+    end: Option<DateTime<Local>>,
+    settings: SessionSettings,
+    stop_reason: Option<StopReason>,
+    flow_table: Arc<ShardedFlowTable>,
+    pipeline: Arc<PipelineStats>,
+    failures: Arc<DecodeFailures>,
+}
+
+impl Session {
+    /// Creates a session started now, that collects its data in the given tables.
+    pub(crate) fn new(id: u64, settings: SessionSettings, flow_table: Arc<ShardedFlowTable>, pipeline: Arc<PipelineStats>,
+                      failures: Arc<DecodeFailures>) -> Self {
+        Session { id, start: Local::now(), end: None, settings, stop_reason: None, flow_table, pipeline, failures }
+    }
+
+    pub fn get_id(&self) -> u64 { return self.id }
+    pub fn get_start(&self) -> DateTime<Local> { return self.start }
+    /// The time the capture has been stopped, if the session is finished.
+    pub fn get_end(&self) -> Option<DateTime<Local>> { return self.end }
+    pub fn get_settings(&self) -> &SessionSettings { return &self.settings }
+    /// Why the session has ended, if it is finished and its report has been written.
+    pub fn get_stop_reason(&self) -> Option<StopReason> { return self.stop_reason }
+
+    pub fn is_finished(&self) -> bool {
+        self.end.is_some()
+    }
+
+    /// Returns how long the session has lasted, or has been running until now.
+    pub fn get_duration(&self) -> chrono::Duration {
+        self.end.unwrap_or_else(Local::now) - self.start
+    }
+
+    /// Returns a snapshot of the flows collected by the session.
+    pub fn get_flow_table(&self) -> FlowTable {
+        self.flow_table.snapshot()
+    }
+
+    /// Returns the counters of the packets captured, queued, dropped and decoded by the session.
+    pub fn get_pipeline_counters(&self) -> PipelineCounters {
+        self.pipeline.snapshot()
+    }
+
+    /// Returns the packets of the session that couldn't be decoded.
+    pub fn get_unclassified_traffic(&self) -> Vec<UnclassifiedTraffic> {
+        self.failures.snapshot()
+    }
+
+    pub(crate) fn close(&mut self, end: DateTime<Local>) {
+        self.end = Some(end);
+    }
+
+    pub(crate) fn set_stop_reason(&mut self, stop_reason: Option<StopReason>) {
+        self.stop_reason = stop_reason;
+    }
+}
+
+impl Display for Session {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "#{} on {}: {} ", self.id, self.settings.devices.join(", "), self.start.format("%Y-%m-%d %H:%M:%S"))?;
+        match self.end {
+            Some(end) => write!(f, "- {} ({} sec)", end.format("%H:%M:%S"), self.get_duration().num_seconds())?,
+            None => write!(f, "(running for {} sec)", self.get_duration().num_seconds())?
+        }
+        let flows = self.flow_table.snapshot();
+        write!(f, ", {} packets in {} flows", flows.get_total_packets(), flows.len())?;
+        if let Some(filename) = &self.settings.filename {
+            write!(f, ", report in {}", filename)?;
+        }
+        if let Some(reason) = self.stop_reason {
+            write!(f, ", ended because {}", reason)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use chrono::Local;
+    use crate::capture::CaptureConfig;
+    use crate::flow::ShardedFlowTable;
+    use crate::pipeline::{DecodeFailures, PipelineStats};
+    use crate::pkt_parser::{PacketInfo, Protocol, TimeVal};
+    use crate::session::{Session, SessionSettings};
+    use crate::stop::{StopConditions, StopReason};
+
+    #[test]
+    fn test_session() {
+        let settings = SessionSettings::new(vec!["eth0".to_string(), "wlan0".to_string()], Some("report.txt".to_string()), 0,
+                                            None, CaptureConfig::default(), StopConditions::new(2, 0, 0));
+        let flow_table = Arc::new(ShardedFlowTable::new(2));
+        let mut session = Session::new(1, settings, flow_table.clone(), Arc::new(PipelineStats::new()), Arc::new(DecodeFailures::new()));
+        for port in [443, 80] {
+            flow_table.update(&PacketInfo::new("10.0.0.1".to_string(), port, Protocol::TCP, 100, TimeVal::from(1_000_000)));
+        }
+        assert!(!session.is_finished());
+        assert!(session.to_string().contains("running for"));
+
+        session.close(Local::now());
+        session.set_stop_reason(Some(StopReason::PacketCount(2)));
+        assert!(session.is_finished());
+        assert_eq!(session.get_flow_table().len(), 2);
+        let text = session.to_string();
+        assert!(text.starts_with("#1 on eth0, wlan0: "));
+        assert!(text.ends_with("(0 sec), 2 packets in 2 flows, report in report.txt, ended because 2 packets have been captured"));
+    }
+}
@@ -0,0 +1,364 @@
+//! stats
+//! This module defines the structures used to describe how the traffic is distributed over time.
+//! All of them have a bounded size, so they can be kept for the whole duration of a scanning.
+
+use std::collections::BTreeMap;
+use chrono::{Local, TimeZone};
+use crate::pkt_parser::TimeVal;
+
+/// Upper bounds of the standard frame size buckets, in bytes. Bigger frames are jumbo frames.
+const FRAME_SIZE_BOUNDS: [u64; 6] = [64, 128, 256, 512, 1024, 1518];
+/// Upper bounds of the inter-arrival time buckets, in microseconds.
+const INTER_ARRIVAL_BOUNDS: [u64; 7] = [10, 100, 1_000, 10_000, 100_000, 1_000_000, 10_000_000];
+/// Number of sub buckets for every power of two used to estimate the percentiles (a relative error of about 6%).
+const SUB_BUCKETS: u64 = 16;
+
+/// The amount of traffic observed in a time interval.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Bucket {
+    start: u64,
+    packets: u64,
+    bytes: u64,
+}
+
+impl Bucket {
+    /// Returns the beginning of the interval, in microseconds since the epoch.
+    pub fn get_start(&self) -> u64 { return self.start }
+    pub fn get_packets(&self) -> u64 { return self.packets }
+    pub fn get_bytes(&self) -> u64 { return self.bytes }
+}
+
+/// A time series of packets and bytes, divided in buckets of the same width.
+/// The series covers the whole scanning with a bounded number of buckets: when the limit is reached the width is
+/// doubled and the adjacent buckets are merged, so the resolution decreases as the scanning goes on.
+#[derive(Debug, Clone)]
+pub struct Throughput {
+    width: u64,
+    max_buckets: usize,
+    start: Option<u64>,
+    buckets: Vec<(u64, u64)>,
+}
+
+impl Throughput {
+    /// Creates an empty series, the width is expressed in seconds.
+    pub fn new(width: u64, max_buckets: usize) -> Self {
+        Throughput { width: width.max(1) * 1_000_000, max_buckets: max_buckets.max(2), start: None, buckets: Vec::new() }
+    }
+
+    /// Accounts a packet, the timestamp is expressed in microseconds since the epoch.
+    pub fn add(&mut self, ts: u64, bytes: usize) {
+        self.add_bucket(ts, 1, bytes as u64);
+    }
+
+    fn add_bucket(&mut self, ts: u64, packets: u64, bytes: u64) {
+        let start = *self.start.get_or_insert(ts - ts % self.width);
+        // packets are not always in order (e.g. from different interfaces), the late ones go in the first bucket
+        let mut index = (ts.saturating_sub(start) / self.width) as usize;
+        while index >= self.max_buckets {
+            self.downsample();
+            index = (ts.saturating_sub(self.start.unwrap()) / self.width) as usize;
+        }
+        if index >= self.buckets.len() {
+            self.buckets.resize(index + 1, (0, 0));
+        }
+        self.buckets[index].0 += packets;
+        self.buckets[index].1 += bytes;
+    }
+
+    /// Adds the traffic of another series, e.g. collected by another thread. The result has the coarser of the two
+    /// resolutions.
+    pub fn merge(&mut self, other: &Throughput) {
+        let other_start = match other.start {
+            Some(start) => start,
+            None => return
+        };
+        if self.start.is_none_or(|start| other_start < start) {
+            // the earliest series is the base, so no bucket ends before the beginning
+            let mut merged = other.clone();
+            merged.max_buckets = self.max_buckets;
+            merged.merge(self);
+            *self = merged;
+            return
+        }
+        let mut other = other.clone();
+        while self.width < other.width { self.downsample() }
+        while other.width < self.width { other.downsample() }
+        for bucket in other.get_buckets() {
+            self.add_bucket(bucket.start, bucket.packets, bucket.bytes);
+        }
+    }
+
+    /// Doubles the width of the buckets, merging them two by two.
+    fn downsample(&mut self) {
+        let start = self.start.unwrap();
+        let width = self.width * 2;
+        let new_start = start - start % width;
+        let offset = ((start - new_start) / self.width) as usize;
+        let mut buckets = vec![(0, 0); (self.buckets.len() + offset).div_ceil(2)];
+        for (i, b) in self.buckets.iter().enumerate() {
+            let merged = &mut buckets[(i + offset) / 2];
+            merged.0 += b.0;
+            merged.1 += b.1;
+        }
+        self.width = width;
+        self.start = Some(new_start);
+        self.buckets = buckets;
+    }
+
+    /// Returns the current width of the buckets, in seconds.
+    pub fn get_width(&self) -> u64 {
+        self.width / 1_000_000
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.buckets.is_empty()
+    }
+
+    /// Returns the buckets from the first packet to the last one, including the empty ones.
+    pub fn get_buckets(&self) -> Vec<Bucket> {
+        let start = self.start.unwrap_or(0);
+        self.buckets.iter().enumerate()
+            .map(|(i, b)| Bucket { start: start + i as u64 * self.width, packets: b.0, bytes: b.1 })
+            .collect()
+    }
+
+    /// Returns the bucket with the highest number of bytes, if any.
+    pub fn get_peak(&self) -> Option<Bucket> {
+        self.get_buckets().into_iter().max_by_key(|b| b.bytes)
+    }
+
+    /// Exports the series as CSV, one row for every bucket. The label, when given, is added as first column.
+    pub fn to_csv(&self, label: Option<&str>) -> String {
+        let mut csv = String::new();
+        for b in self.get_buckets() {
+            if let Some(label) = label {
+                csv.push_str(format!("{},", label).as_str());
+            }
+            csv.push_str(format!("{},{},{},{},{:.2}\n", format_time(b.start), b.start, b.packets, b.bytes,
+                                 b.bytes as f64 / self.get_width() as f64).as_str());
+        }
+        csv
+    }
+
+    /// The header of the CSV produced by to_csv.
+    pub fn csv_header(label: Option<&str>) -> String {
+        match label {
+            Some(label) => format!("{},time,timestamp_us,packets,bytes,bytes_per_sec\n", label),
+            None => "time,timestamp_us,packets,bytes,bytes_per_sec\n".to_string()
+        }
+    }
+}
+
+/// The unit of the values collected in a histogram, used to label its buckets.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HistogramUnit {
+    Bytes,
+    Microseconds,
+}
+
+impl HistogramUnit {
+    pub fn format(&self, value: u64) -> String {
+        match self {
+            HistogramUnit::Bytes => format!("{} B", value),
+            HistogramUnit::Microseconds => format_duration(value)
+        }
+    }
+}
+
+/// A distribution of values. The values are counted in a few fixed buckets, to be shown in the report, and in a
+/// finer logarithmic scale, used to estimate the percentiles with a bounded amount of memory.
+#[derive(Debug, Clone)]
+pub struct Histogram {
+    unit: HistogramUnit,
+    bounds: Vec<u64>,
+    counts: Vec<u64>,
+    samples: u64,
+    sum: u64,
+    min: u64,
+    max: u64,
+    fine: BTreeMap<u32, u64>,
+}
+
+impl Histogram {
+    /// Creates an empty histogram with the given upper bounds; the last bucket collects the values above all of them.
+    pub fn new(unit: HistogramUnit, bounds: &[u64]) -> Self {
+        Histogram { unit, bounds: bounds.to_vec(), counts: vec![0; bounds.len() + 1], samples: 0, sum: 0, min: u64::MAX,
+            max: 0, fine: BTreeMap::new() }
+    }
+
+    /// A histogram of frame sizes with the standard buckets (up to 64, 128, 256, 512, 1024, 1518 bytes and jumbo).
+    pub fn frame_sizes() -> Self {
+        Histogram::new(HistogramUnit::Bytes, &FRAME_SIZE_BOUNDS)
+    }
+
+    /// A histogram of inter-arrival times, from 10 us to 10 sec.
+    pub fn inter_arrival_times() -> Self {
+        Histogram::new(HistogramUnit::Microseconds, &INTER_ARRIVAL_BOUNDS)
+    }
+
+    /// Returns the index of the fine bucket of a value: the small values have their own bucket, the others are
+    /// grouped in SUB_BUCKETS buckets for every power of two.
+    fn fine_bucket(value: u64) -> u32 {
+        if value < 2 * SUB_BUCKETS {
+            return value as u32
+        }
+        let exp = 63 - value.leading_zeros() as u64;
+        let sub = (value >> (exp - 4)) & (SUB_BUCKETS - 1);
+        (2 * SUB_BUCKETS + (exp - 5) * SUB_BUCKETS + sub) as u32
+    }
+
+    pub fn add(&mut self, value: u64) {
+        let bucket = self.bounds.iter().position(|b| value <= *b).unwrap_or(self.bounds.len());
+        self.counts[bucket] += 1;
+        self.samples += 1;
+        self.sum += value;
+        self.min = self.min.min(value);
+        self.max = self.max.max(value);
+        *self.fine.entry(Histogram::fine_bucket(value)).or_insert(0) += 1;
+    }
+
+    /// Adds the values of another histogram with the same buckets.
+    pub fn merge(&mut self, other: &Histogram) {
+        if self.bounds != other.bounds { return }
+        self.counts.iter_mut().zip(other.counts.iter()).for_each(|(a, b)| *a += b);
+        self.samples += other.samples;
+        self.sum += other.sum;
+        self.min = self.min.min(other.min);
+        self.max = self.max.max(other.max);
+        for (index, count) in other.fine.iter() {
+            *self.fine.entry(*index).or_insert(0) += count;
+        }
+    }
+
+    /// Returns the estimation of the given percentile (between 0 and 100), if there is at least a value.
+    pub fn percentile(&self, percentile: f64) -> Option<u64> {
+        if self.samples == 0 { return None }
+        let rank = ((percentile / 100.0) * self.samples as f64).ceil().max(1.0) as u64;
+        if rank >= self.samples { return Some(self.max) }
+        let mut count = 0;
+        for (index, n) in self.fine.iter() {
+            count += n;
+            if count >= rank {
+                let value = Histogram::representative(*index);
+                return Some(value.clamp(self.min, self.max))
+            }
+        }
+        Some(self.max)
+    }
+
+    /// Returns the value in the middle of a fine bucket.
+    fn representative(index: u32) -> u64 {
+        let index = index as u64;
+        if index < 2 * SUB_BUCKETS { return index }
+        let exp = (index - 2 * SUB_BUCKETS) / SUB_BUCKETS + 5;
+        let sub = (index - 2 * SUB_BUCKETS) % SUB_BUCKETS;
+        let shift = exp - 4;
+        ((SUB_BUCKETS + sub) << shift) + (1 << shift) / 2
+    }
+
+    /// Returns the fixed buckets, with a label describing their range, and the number of values in each of them.
+    pub fn get_buckets(&self) -> Vec<(String, u64)> {
+        let mut buckets = Vec::new();
+        for (i, count) in self.counts.iter().enumerate() {
+            let label = if i == 0 {
+                format!("<= {}", self.unit.format(self.bounds[0]))
+            } else if i == self.bounds.len() {
+                format!("> {}", self.unit.format(self.bounds[i - 1]))
+            } else {
+                format!("{} - {}", self.unit.format(self.bounds[i - 1] + 1), self.unit.format(self.bounds[i]))
+            };
+            buckets.push((label, *count));
+        }
+        buckets
+    }
+
+    pub fn get_unit(&self) -> HistogramUnit { return self.unit }
+    pub fn get_samples(&self) -> u64 { return self.samples }
+    pub fn get_min(&self) -> Option<u64> { if self.samples == 0 { None } else { Some(self.min) } }
+    pub fn get_max(&self) -> Option<u64> { if self.samples == 0 { None } else { Some(self.max) } }
+    pub fn get_mean(&self) -> Option<u64> { self.sum.checked_div(self.samples) }
+}
+
+/// Formats a duration expressed in microseconds with the most suitable unit.
+pub fn format_duration(us: u64) -> String {
+    if us >= 1_000_000 && us.is_multiple_of(1_000_000) {
+        format!("{} s", us / 1_000_000)
+    } else if us >= 1_000_000 {
+        format!("{:.2} s", us as f64 / 1_000_000.0)
+    } else if us >= 1_000 && us.is_multiple_of(1_000) {
+        format!("{} ms", us / 1_000)
+    } else if us >= 1_000 {
+        format!("{:.2} ms", us as f64 / 1_000.0)
+    } else {
+        format!("{} us", us)
+    }
+}
+
+/// Formats a timestamp expressed in microseconds since the epoch as a local time.
+pub fn format_time(ts: u64) -> String {
+    let tv = TimeVal::from(ts);
+    format!("{}", Local.timestamp_opt(tv.sec as i64, tv.u_sec * 1000).unwrap().format("%H:%M:%S"))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::stats::{Histogram, Throughput};
+
+    #[test]
+    fn test_throughput_buckets() {
+        let mut series = Throughput::new(1, 10);
+        series.add(10_000_000, 100);
+        series.add(10_500_000, 50);
+        series.add(12_100_000, 10);
+        let buckets = series.get_buckets();
+        assert_eq!(buckets.len(), 3);
+        assert_eq!((buckets[0].get_start(), buckets[0].get_packets(), buckets[0].get_bytes()), (10_000_000, 2, 150));
+        assert_eq!(buckets[1].get_packets(), 0);
+        assert_eq!(buckets[2].get_bytes(), 10);
+    }
+
+    #[test]
+    fn test_throughput_is_bounded() {
+        let mut series = Throughput::new(1, 4);
+        for i in 0..100u64 {
+            series.add(11_000_000 + i * 1_000_000, 1);
+        }
+        assert!(series.get_buckets().len() <= 4);
+        assert_eq!(series.get_width(), 32);
+        assert_eq!(series.get_buckets().iter().map(|b| b.get_bytes()).sum::<u64>(), 100);
+        assert!(series.get_buckets()[0].get_start() <= 11_000_000);
+    }
+
+    #[test]
+    fn test_throughput_merge() {
+        let mut first = Throughput::new(1, 8);
+        first.add(20_000_000, 10);
+        let mut second = Throughput::new(1, 8);
+        second.add(10_000_000, 5);
+        second.add(20_500_000, 1);
+        first.merge(&second);
+        let buckets = first.get_buckets();
+        assert_eq!(first.get_width(), 2);
+        assert_eq!(buckets[0].get_start(), 10_000_000);
+        assert_eq!(buckets.last().unwrap().get_bytes(), 11);
+        assert_eq!(buckets.iter().map(|b| b.get_packets()).sum::<u64>(), 3);
+    }
+
+    #[test]
+    fn test_histogram_percentiles() {
+        let mut sizes = Histogram::frame_sizes();
+        for size in 1..=1000u64 {
+            sizes.add(size);
+        }
+        sizes.add(9000);
+        let buckets = sizes.get_buckets();
+        assert_eq!(buckets[0], ("<= 64 B".to_string(), 64));
+        assert_eq!(buckets[6], ("> 1518 B".to_string(), 1));
+        let p50 = sizes.percentile(50.0).unwrap();
+        assert!((470..=530).contains(&p50), "p50 is {}", p50);
+        let p99 = sizes.percentile(99.0).unwrap();
+        assert!((930..=1000).contains(&p99), "p99 is {}", p99);
+        assert_eq!(sizes.percentile(100.0), Some(9000));
+        assert_eq!(Histogram::inter_arrival_times().percentile(50.0), None);
+    }
+}
@@ -0,0 +1,220 @@
+//! The stop module contains the conditions that end a scanning without anyone typing `stop`: a number of packets,
+//! a duration or a volume of bytes, like `tcpdump -c`. When one of them is reached the final report is written
+//! and the reason is given to whoever waits for the scanning to complete.
+
+use std::fmt::{Display, Formatter};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// The limits after which the scanning is stopped. A zero disables the corresponding limit.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct StopConditions {
+    max_packets: u64,
+    max_seconds: u64,
+    max_bytes: u64,
+}
+
+impl StopConditions {
+    /// Creates the conditions that stop the scanning after `max_packets` packets, `max_seconds` seconds or
+    /// `max_bytes` bytes, whichever comes first. The time the scanning is paused doesn't count in the duration.
+    pub fn new(max_packets: u64, max_seconds: u64, max_bytes: u64) -> Self {
+        StopConditions { max_packets, max_seconds, max_bytes }
+    }
+
+    /// Returns true if at least a limit is set.
+    pub fn is_enabled(&self) -> bool {
+        self.max_packets > 0 || self.max_seconds > 0 || self.max_bytes > 0
+    }
+
+    pub fn get_max_packets(&self) -> u64 { return self.max_packets }
+    pub fn get_max_seconds(&self) -> u64 { return self.max_seconds }
+    pub fn get_max_bytes(&self) -> u64 { return self.max_bytes }
+}
+
+impl Display for StopConditions {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let mut limits = Vec::new();
+        if self.max_packets > 0 { limits.push(format!("{} packets", self.max_packets)) }
+        if self.max_seconds > 0 { limits.push(format!("{} sec", self.max_seconds)) }
+        if self.max_bytes > 0 { limits.push(format!("{} bytes", self.max_bytes)) }
+        match limits.is_empty() {
+            true => write!(f, "none"),
+            false => write!(f, "{}", limits.join(" or "))
+        }
+    }
+}
+
+/// Why a scanning has ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StopReason {
+    /// The given number of packets has been captured.
+    PacketCount(u64),
+    /// The scanning has lasted the given number of seconds.
+    Duration(u64),
+    /// The given number of bytes has been captured.
+    ByteVolume(u64),
+    /// The report has been saved by the user.
+    User,
+}
+
+impl Display for StopReason {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StopReason::PacketCount(packets) => write!(f, "{} packets have been captured", packets),
+            StopReason::Duration(seconds) => write!(f, "the scanning has lasted {} sec", seconds),
+            StopReason::ByteVolume(bytes) => write!(f, "{} bytes have been captured", bytes),
+            StopReason::User => write!(f, "the scanning has been stopped by the user"),
+        }
+    }
+}
+
+/// Counts what the capture threads have taken in a scanning, records the first stop condition reached and whether the
+/// final report is being written.
+#[derive(Debug)]
+pub(crate) struct StopMonitor {
+    conditions: StopConditions,
+    packets: AtomicU64,
+    bytes: AtomicU64,
+    /// The time spent running before the last pause, and since when the scanning is running.
+    running: Mutex<(Duration, Option<Instant>)>,
+    reason: Mutex<Option<StopReason>>,
+    failure: Mutex<Option<String>>,
+    stopping: AtomicBool,
+}
+
+impl StopMonitor {
+    /// Creates the monitor of a scanning started at the given time. All the times are given by the caller, so that the
+    /// duration can be checked without waiting for it.
+    pub(crate) fn new(conditions: StopConditions, now: Instant) -> Self {
+        StopMonitor { conditions, packets: AtomicU64::new(0), bytes: AtomicU64::new(0), running: Mutex::new((Duration::ZERO, Some(now))),
+            reason: Mutex::new(None), failure: Mutex::new(None), stopping: AtomicBool::new(false) }
+    }
+
+    /// Accounts a captured packet of `len` bytes. Returns false if a limit has already been reached, in which case
+    /// the packet is not part of the scanning: the packet that reaches a limit is the last one accepted.
+    pub(crate) fn admit(&self, len: u32) -> bool {
+        if self.get_reason().is_some() {
+            return false
+        }
+        let packets = self.packets.fetch_add(1, Ordering::Relaxed) + 1;
+        if self.conditions.max_packets > 0 && packets > self.conditions.max_packets {
+            return false
+        }
+        let bytes = self.bytes.fetch_add(len as u64, Ordering::Relaxed) + len as u64;
+        if self.conditions.max_packets > 0 && packets == self.conditions.max_packets {
+            self.reach(StopReason::PacketCount(packets));
+        } else if self.conditions.max_bytes > 0 && bytes >= self.conditions.max_bytes {
+            self.reach(StopReason::ByteVolume(bytes));
+        }
+        true
+    }
+
+    /// Stops counting the duration while the scanning is paused, from the given time.
+    pub(crate) fn pause(&self, now: Instant) {
+        let mut running = self.running.lock().unwrap();
+        if let Some(since) = running.1.take() {
+            running.0 += now.saturating_duration_since(since);
+        }
+    }
+
+    /// Counts the duration again when the scanning is resumed, from the given time.
+    pub(crate) fn resume(&self, now: Instant) {
+        self.running.lock().unwrap().1.get_or_insert(now);
+    }
+
+    /// Returns how long the scanning has been running at the given time, without the pauses.
+    pub(crate) fn elapsed(&self, now: Instant) -> Duration {
+        let running = self.running.lock().unwrap();
+        running.0 + running.1.map_or(Duration::ZERO, |since| now.saturating_duration_since(since))
+    }
+
+    /// Returns true if the duration has been reached at the given time, recording it as the reason if it is the first
+    /// limit reached.
+    pub(crate) fn check_duration(&self, now: Instant) -> bool {
+        let limit = Duration::from_secs(self.conditions.max_seconds);
+        if self.conditions.max_seconds > 0 && self.elapsed(now) >= limit {
+            self.reach(StopReason::Duration(self.conditions.max_seconds));
+        }
+        self.get_reason().is_some()
+    }
+
+    /// Records the reason of the stop, unless another one has been recorded before.
+    pub(crate) fn reach(&self, reason: StopReason) {
+        self.reason.lock().unwrap().get_or_insert(reason);
+    }
+
+    pub(crate) fn get_reason(&self) -> Option<StopReason> {
+        *self.reason.lock().unwrap()
+    }
+
+    /// Records an error that has spoiled the scanning, like a final report that couldn't be written or a capture that
+    /// has failed.
+    pub(crate) fn fail(&self, error: String) {
+        *self.failure.lock().unwrap() = Some(error);
+    }
+
+    pub(crate) fn get_failure(&self) -> Option<String> {
+        self.failure.lock().unwrap().clone()
+    }
+
+    /// Records that the threads of the scanning are being stopped, and the report written.
+    pub(crate) fn set_stopping(&self, stopping: bool) {
+        self.stopping.store(stopping, Ordering::SeqCst);
+    }
+
+    pub(crate) fn is_stopping(&self) -> bool {
+        self.stopping.load(Ordering::SeqCst)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+    use crate::stop::{StopConditions, StopMonitor, StopReason};
+
+    #[test]
+    fn test_packet_and_byte_limits() {
+        let monitor = StopMonitor::new(StopConditions::new(3, 0, 0), Instant::now());
+        assert!((0..3).all(|_| monitor.admit(100)));
+        assert_eq!(monitor.get_reason(), Some(StopReason::PacketCount(3)));
+        assert!(!monitor.admit(100));
+        assert!(monitor.check_duration(Instant::now()));
+
+        let monitor = StopMonitor::new(StopConditions::new(10, 0, 250), Instant::now());
+        assert!(monitor.admit(100) && monitor.admit(100));
+        assert_eq!(monitor.get_reason(), None);
+        // the packet that exceeds the volume is still counted, the next ones are not
+        assert!(monitor.admit(100));
+        assert!(!monitor.admit(100));
+        assert_eq!(monitor.get_reason(), Some(StopReason::ByteVolume(300)));
+        monitor.reach(StopReason::User);
+        assert_eq!(monitor.get_reason(), Some(StopReason::ByteVolume(300)));
+    }
+
+    #[test]
+    fn test_no_limits() {
+        let conditions = StopConditions::default();
+        assert!(!conditions.is_enabled());
+        assert_eq!(conditions.to_string(), "none");
+        assert_eq!(StopConditions::new(1000, 60, 0).to_string(), "1000 packets or 60 sec");
+        let start = Instant::now();
+        let monitor = StopMonitor::new(conditions, start);
+        assert!((0..1000).all(|_| monitor.admit(1500)));
+        assert!(!monitor.check_duration(start + Duration::from_secs(3600)));
+    }
+
+    #[test]
+    fn test_paused_time() {
+        let start = Instant::now();
+        let monitor = StopMonitor::new(StopConditions::new(0, 1, 0), start);
+        monitor.pause(start + Duration::from_millis(400));
+        // the scanning has been paused for most of the time
+        assert!(!monitor.check_duration(start + Duration::from_millis(1500)));
+        monitor.resume(start + Duration::from_millis(1500));
+        assert_eq!(monitor.elapsed(start + Duration::from_millis(2000)), Duration::from_millis(900));
+        assert!(!monitor.check_duration(start + Duration::from_millis(2000)));
+        assert!(monitor.check_duration(start + Duration::from_millis(2100)));
+        assert_eq!(monitor.get_reason(), Some(StopReason::Duration(1)));
+    }
+}
@@ -0,0 +1,261 @@
+//! The stream module contains the async API, enabled by the `tokio` feature, for the applications that run on a
+//! tokio runtime: a source of the captured packets and a stream of the events of the decoded traffic, that can be
+//! consumed with any combinator of the futures ecosystem.
+//!
+//! The reads of libpcap block until a packet arrives or the read timeout expires, so every interface is read by a
+//! task on the blocking threads of the runtime, while the packets are decoded and aggregated by the task that polls
+//! the stream, with the same parser and flow table used by `Sniffer::run`. Dropping the stream ends the capture.
+//! ```rust,no_run
+//! # async fn example() -> Result<(), packet_sniffer::sniffer::SnifferError> {
+//! use std::future::poll_fn;
+//! use std::pin::Pin;
+//! use futures_core::Stream;
+//! use packet_sniffer::events::{EventKind, SnifferEvent};
+//! use packet_sniffer::sniffer::Sniffer;
+//! let mut sniffer = Sniffer::new();
+//! sniffer.attach(Sniffer::list_devices()?[0].clone())?;
+//! let mut events = sniffer.event_stream(&[EventKind::Flow])?;
+//! while let Some(event) = poll_fn(|cx| Pin::new(&mut events).poll_next(cx)).await {
+//!     if let SnifferEvent::FlowStarted(key, _) = event {
+//!         println!("New flow with {}:{}", key.0, key.1);
+//!     }
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use std::collections::VecDeque;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use futures_core::Stream;
+use pcap::Device;
+use tokio::runtime::Handle;
+use tokio::sync::mpsc::{channel, Receiver};
+use crate::capture::CaptureConfig;
+use crate::events::{EventKind, SnifferEvent};
+use crate::flow::FlowTable;
+use crate::pipeline::{DecodeFailures, UnclassifiedTraffic};
+use crate::sniffer::{decode_info_from_packet, PacketExt, SnifferError};
+
+/// The default number of captured packets that can wait to be decoded.
+pub const DEFAULT_STREAM_CAPACITY: usize = 4096;
+
+/// An async source of the packets captured on one or more interfaces.
+/// When the stream isn't polled fast enough the reads stop, and the packets are dropped by the kernel.
+#[derive(Debug)]
+pub struct PacketSource {
+    receiver: Receiver<PacketExt>,
+    devices: Vec<Device>,
+    stop: Arc<AtomicBool>,
+    error: Arc<Mutex<Option<String>>>,
+}
+
+impl PacketSource {
+    /// Opens the capture on the devices with the given settings and capture filter, keeping at most `capacity`
+    /// packets waiting. It must be called inside a tokio runtime.
+    pub fn open(devices: Vec<Device>, config: &CaptureConfig, filter: Option<&str>, capacity: usize) -> Result<Self, SnifferError> {
+        let handle = match Handle::try_current() {
+            Ok(handle) => handle,
+            Err(_) => return Err(SnifferError::UserError("The stream must be opened inside a tokio runtime ...".to_string()))
+        };
+        if devices.is_empty() {
+            return Err(SnifferError::UserError("You have to specify a device ...".to_string()));
+        }
+        // the captures are opened here, so that a device or filter error is returned to the caller
+        let mut captures = Vec::new();
+        for device in &devices {
+            let mut cap = config.open(device.clone())?;
+            if let Some(filter) = filter {
+                if let Err(error) = cap.filter(filter, true) {
+                    return Err(SnifferError::UserError(format!("The capture filter '{}' can't be applied on {}: {} ...", filter, device.name, error)));
+                }
+            }
+            captures.push(cap);
+        }
+
+        let (sender, receiver) = channel(capacity.max(1));
+        let stop = Arc::new(AtomicBool::new(false));
+        let error = Arc::new(Mutex::new(None));
+        let precision = config.get_precision();
+        for (interface, mut cap) in captures.into_iter().enumerate() {
+            let name = devices[interface].name.clone();
+            let sender = sender.clone();
+            let stop = stop.clone();
+            let error = error.clone();
+            // the stop is noticed at the next packet or read timeout
+            handle.spawn_blocking(move || {
+                while !stop.load(Ordering::Relaxed) {
+                    match cap.next_packet() {
+                        Ok(packet) => {
+                            let packet = PacketExt::new(packet.data, packet.header.ts, packet.header.len, precision, interface);
+                            if sender.blocking_send(packet).is_err() {
+                                break;
+                            }
+                        },
+                        Err(pcap::Error::TimeoutExpired) => {},
+                        Err(e) => {
+                            *error.lock().unwrap() = Some(format!("The capture on {} has failed: {} ...", name, e));
+                            break;
+                        }
+                    }
+                }
+            });
+        }
+        Ok(PacketSource { receiver, devices, stop, error })
+    }
+
+    /// Returns the devices sniffed, in the order of the interface of the packets.
+    pub fn get_devices(&self) -> &[Device] {
+        &self.devices
+    }
+
+    /// Returns the error that has ended the capture on an interface, if any.
+    pub fn get_error(&self) -> Option<String> {
+        self.error.lock().unwrap().clone()
+    }
+}
+
+impl Stream for PacketSource {
+    type Item = PacketExt;
+
+    /// Returns the next packet, or None when the capture has ended on all the interfaces.
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.receiver.poll_recv(cx)
+    }
+}
+
+impl Drop for PacketSource {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+/// An async stream of the events of the traffic captured by a source: the decoded packets and the flows started and
+/// closed, according to the kinds requested. The flows are aggregated in a table that can be read between the events.
+#[derive(Debug)]
+pub struct EventStream {
+    source: PacketSource,
+    kinds: Vec<EventKind>,
+    flows: FlowTable,
+    failures: DecodeFailures,
+    pending: VecDeque<SnifferEvent>,
+}
+
+impl EventStream {
+    /// Creates the stream of the events of the given kinds, aggregating the packets in the given table, that sets the
+    /// timeouts and the limits of the flows.
+    pub fn new(source: PacketSource, kinds: &[EventKind], mut flows: FlowTable) -> Self {
+        flows.set_track_events(kinds.contains(&EventKind::Flow));
+        EventStream { source, kinds: kinds.to_vec(), flows, failures: DecodeFailures::new(), pending: VecDeque::new() }
+    }
+
+    /// Returns the flows collected so far.
+    pub fn get_flow_table(&self) -> &FlowTable {
+        &self.flows
+    }
+
+    /// Returns the packets that couldn't be decoded, grouped by layer and reason.
+    pub fn get_unclassified_traffic(&self) -> Vec<UnclassifiedTraffic> {
+        self.failures.snapshot()
+    }
+
+    pub fn get_source(&self) -> &PacketSource {
+        &self.source
+    }
+
+    /// Decodes a packet and queues the events it produces, the flows changed before the packet itself.
+    fn process(&mut self, packet: PacketExt) {
+        let frame_len = packet.get_len();
+        let device = self.source.devices[packet.get_interface()].clone();
+        let name = device.name.clone();
+        match decode_info_from_packet(device, packet).map(|info| info.with_interface(name)) {
+            Ok(info) => {
+                self.flows.update(&info);
+                self.pending.extend(self.flows.take_events().into_iter().map(SnifferEvent::from));
+                if self.kinds.contains(&EventKind::Packet) {
+                    self.pending.push_back(SnifferEvent::Packet(info));
+                }
+            },
+            Err(error) => self.failures.add(&error, frame_len, None)
+        }
+    }
+}
+
+impl Stream for EventStream {
+    type Item = SnifferEvent;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        loop {
+            if let Some(event) = self.pending.pop_front() {
+                return Poll::Ready(Some(event));
+            }
+            match Pin::new(&mut self.source).poll_next(cx) {
+                Poll::Ready(Some(packet)) => self.process(packet),
+                Poll::Ready(None) => return Poll::Ready(None),
+                Poll::Pending => return Poll::Pending
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::future::poll_fn;
+    use std::pin::Pin;
+    use std::sync::atomic::AtomicBool;
+    use std::sync::{Arc, Mutex};
+    use futures_core::Stream;
+    use pcap::Device;
+    use tokio::sync::mpsc::channel;
+    use crate::capture::{CaptureConfig, TimestampPrecision};
+    use crate::events::{EventKind, SnifferEvent};
+    use crate::flow::FlowTable;
+    use crate::sniffer::{PacketExt, SnifferError};
+    use crate::stream::{EventStream, PacketSource};
+
+    /// An Ethernet frame with a TCP segment from 10.0.0.2:443 to 10.0.0.1:50000 carrying 4 bytes.
+    fn frame() -> Vec<u8> {
+        let mut frame = vec![0u8; 12];
+        frame.extend([0x08, 0x00]);
+        frame.extend([0x45, 0x00, 0x00, 0x2c, 0x00, 0x00, 0x40, 0x00, 0x40, 0x06, 0x00, 0x00, 10, 0, 0, 2, 10, 0, 0, 1]);
+        frame.extend([0x01, 0xbb, 0xc3, 0x50, 0, 0, 0, 1, 0, 0, 0, 0, 0x50, 0x18, 0xff, 0xff, 0, 0, 0, 0]);
+        frame.extend(b"ping");
+        frame
+    }
+
+    #[test]
+    fn test_event_stream() {
+        let runtime = tokio::runtime::Builder::new_current_thread().build().unwrap();
+        runtime.block_on(async {
+            let (sender, receiver) = channel(16);
+            let source = PacketSource { receiver, devices: vec![Device::from("eth0")], stop: Arc::new(AtomicBool::new(false)),
+                error: Arc::new(Mutex::new(None)) };
+            let mut events = EventStream::new(source, &[EventKind::Packet, EventKind::Flow], FlowTable::new());
+            for i in 0..2 {
+                let ts = libc::timeval { tv_sec: 1_700_000_000, tv_usec: i * 1000 };
+                sender.send(PacketExt::new(&frame(), ts, 58, TimestampPrecision::Micro, 0)).await.unwrap();
+            }
+            sender.send(PacketExt::new(&[0u8; 10], libc::timeval { tv_sec: 1_700_000_000, tv_usec: 5000 }, 10, TimestampPrecision::Micro, 0)).await.unwrap();
+            drop(sender);
+
+            let mut received = Vec::new();
+            while let Some(event) = poll_fn(|cx| Pin::new(&mut events).poll_next(cx)).await {
+                received.push(event);
+            }
+            assert_eq!(received.len(), 3);
+            assert!(matches!(&received[0], SnifferEvent::FlowStarted(key, _) if key == &("10.0.0.2".to_string(), 443)));
+            assert!(matches!(&received[1], SnifferEvent::Packet(info) if info.get_interface() == Some("eth0".to_string())));
+            assert!(matches!(&received[2], SnifferEvent::Packet(_)));
+            assert_eq!(events.get_flow_table().get_total_packets(), 2);
+            assert_eq!(events.get_unclassified_traffic().len(), 1);
+        });
+    }
+
+    #[test]
+    fn test_outside_runtime() {
+        let error = PacketSource::open(vec![Device::from("eth0")], &CaptureConfig::default(), None, 16).unwrap_err();
+        assert_eq!(error, SnifferError::UserError("The stream must be opened inside a tokio runtime ...".to_string()));
+    }
+}
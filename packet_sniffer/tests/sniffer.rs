@@ -1,5 +1,7 @@
 use packet_sniffer::sniffer::{RunStatus, Sniffer, SnifferError};
 use packet_sniffer::sniffer::SnifferError::{UserError, UserWarning};
+use packet_sniffer::stop::StopConditions;
+use packet_sniffer::events::EventKind;
 
 #[test]
 fn init_status_is_stop() {
@@ -190,4 +192,86 @@ fn pause_while_already_paused() {
             panic!("Repeat the test");
         }
     }
+}
+
+#[test]
+fn set_top_n_zero_should_fail() {
+    let mut sniffer = Sniffer::new();
+    let res = sniffer.set_top_n(0);
+    assert!(res.is_err());
+    assert_eq!(res.unwrap_err(), SnifferError::UserError("The number of entries of the summary must be positive ...".to_string()));
+    assert_eq!(sniffer.get_top_n(), 10);
+}
+
+#[test]
+fn set_workers_zero_should_fail() {
+    let mut sniffer = Sniffer::new();
+    let res = sniffer.set_workers(0);
+    assert!(res.is_err());
+    assert_eq!(res.unwrap_err(), SnifferError::UserError("The number of decoders must be positive ...".to_string()));
+    assert!(sniffer.set_workers(3).is_ok());
+    assert_eq!(sniffer.get_workers(), 3);
+}
+
+#[test]
+fn set_invalid_filter_should_fail() {
+    let mut sniffer = Sniffer::new();
+    let res = sniffer.set_filter("not port");
+    assert!(res.is_err());
+    assert_eq!(sniffer.get_filter(), None);
+    assert!(sniffer.set_filter("not port 22").is_ok());
+    assert_eq!(sniffer.get_filter(), Some("not port 22".to_string()));
+    assert!(sniffer.set_filter("").is_ok());
+    assert_eq!(sniffer.get_filter(), None);
+}
+
+#[test]
+fn set_invalid_display_filter_should_fail() {
+    let mut sniffer = Sniffer::new();
+    let res = sniffer.set_display_filter("tcp and flow.bytes > lots");
+    assert!(res.is_err());
+    assert_eq!(res.unwrap_err(), SnifferError::UserError(
+        "Invalid display filter: 'lots' is not a number, as required by 'flow.bytes' (at column 22)\ntcp and flow.bytes > lots\n                     ^ ...".to_string()));
+    assert_eq!(sniffer.get_display_filter(), None);
+    assert!(sniffer.set_display_filter("tcp and flow.bytes > 1MB").is_ok());
+    assert_eq!(sniffer.get_display_filter(), Some("tcp and flow.bytes > 1MB".to_string()));
+    assert!(sniffer.live_view(None).is_ok());
+    assert!(sniffer.set_display_filter("").is_ok());
+    assert_eq!(sniffer.get_display_filter(), None);
+}
+
+#[test]
+fn attach_no_devices_should_fail() {
+    let mut sniffer = Sniffer::new();
+    let res = sniffer.attach_all(Vec::new());
+    assert!(res.is_err());
+    assert_eq!(res.unwrap_err(), SnifferError::UserError("You have to specify a device ...".to_string()));
+}
+
+#[test]
+fn wait_without_scanning_should_fail() {
+    let mut sniffer = Sniffer::new();
+    assert!(sniffer.set_stop_conditions(StopConditions::new(100, 0, 0)).is_ok());
+    assert_eq!(sniffer.get_stop_conditions().get_max_packets(), 100);
+    assert_eq!(sniffer.wait_for_completion().unwrap_err(), SnifferError::UserWarning("There is no scanning in execution ...".to_string()));
+}
+
+#[test]
+fn discard_unknown_session_should_fail() {
+    let mut sniffer = Sniffer::new();
+    assert!(sniffer.get_sessions().is_empty());
+    assert!(sniffer.get_session(1).is_none());
+    assert_eq!(sniffer.discard_session(1).unwrap_err(), SnifferError::UserWarning("There is no session #1 ...".to_string()));
+    assert_eq!(sniffer.discard_sessions(), 0);
+}
+
+#[test]
+fn subscribe_and_unsubscribe() {
+    let sniffer = Sniffer::new();
+    let subscription = sniffer.subscribe(&[EventKind::Packet, EventKind::Flow], 16);
+    let id = sniffer.subscribe_with(&[EventKind::Snapshot], |_| {});
+    assert!(subscription.try_recv().is_none());
+    assert!(sniffer.unsubscribe(id));
+    assert!(sniffer.unsubscribe(subscription.get_id()));
+    assert!(!sniffer.unsubscribe(id));
 }
\ No newline at end of file